@@ -29,11 +29,15 @@ pub struct Model {
     pub o_player: Uuid,
     pub x_start_chat: bool,
     pub o_start_chat: bool,
+    pub x_auto_plays: i16,
+    pub o_auto_plays: i16,
     pub board: String,
     pub winner: Option<Uuid>,
     pub reason: Option<String>,
     pub created_at: chrono::NaiveDateTime,
     pub ended_at: Option<chrono::NaiveDateTime>,
+    pub x_player_name: Option<String>,
+    pub o_player_name: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -14,6 +14,16 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+pub mod failed_signin;
 pub mod game;
+pub mod oidc_authorization;
+pub mod password_recovery_code;
+pub mod password_reset_token;
 pub mod prelude;
+pub mod privacy_setting;
+pub mod recovery_code;
+pub mod refresh_token;
+pub mod session;
 pub mod user;
+pub mod user_identity;
+pub mod username_history;
@@ -26,3 +26,53 @@ pub use super::user::{
 pub use super::game::{
     ActiveModel as GameActiveModel, Column as GameColumn, Entity as GameEntity, Model as GameModel,
 };
+
+pub use super::refresh_token::{
+    ActiveModel as RefreshTokenActiveModel, Column as RefreshTokenColumn,
+    Entity as RefreshTokenEntity, Model as RefreshTokenModel,
+};
+
+pub use super::session::{
+    ActiveModel as SessionActiveModel, Column as SessionColumn, Entity as SessionEntity,
+    Model as SessionModel,
+};
+
+pub use super::recovery_code::{
+    ActiveModel as RecoveryCodeActiveModel, Column as RecoveryCodeColumn,
+    Entity as RecoveryCodeEntity, Model as RecoveryCodeModel,
+};
+
+pub use super::failed_signin::{
+    ActiveModel as FailedSigninActiveModel, Column as FailedSigninColumn,
+    Entity as FailedSigninEntity, Model as FailedSigninModel,
+};
+
+pub use super::password_recovery_code::{
+    ActiveModel as PasswordRecoveryCodeActiveModel, Column as PasswordRecoveryCodeColumn,
+    Entity as PasswordRecoveryCodeEntity, Model as PasswordRecoveryCodeModel,
+};
+
+pub use super::password_reset_token::{
+    ActiveModel as PasswordResetTokenActiveModel, Column as PasswordResetTokenColumn,
+    Entity as PasswordResetTokenEntity, Model as PasswordResetTokenModel,
+};
+
+pub use super::user_identity::{
+    ActiveModel as UserIdentityActiveModel, Column as UserIdentityColumn,
+    Entity as UserIdentityEntity, Model as UserIdentityModel,
+};
+
+pub use super::oidc_authorization::{
+    ActiveModel as OidcAuthorizationActiveModel, Column as OidcAuthorizationColumn,
+    Entity as OidcAuthorizationEntity, Model as OidcAuthorizationModel,
+};
+
+pub use super::username_history::{
+    ActiveModel as UsernameHistoryActiveModel, Column as UsernameHistoryColumn,
+    Entity as UsernameHistoryEntity, Model as UsernameHistoryModel,
+};
+
+pub use super::privacy_setting::{
+    ActiveModel as PrivacySettingActiveModel, Column as PrivacySettingColumn,
+    Entity as PrivacySettingEntity, Model as PrivacySettingModel,
+};
@@ -31,6 +31,14 @@ pub struct Model {
     pub draw: i64,
     pub password_hash: String,
     pub created_at: chrono::NaiveDateTime,
+    pub token_version: i32,
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub deleted_at: Option<chrono::NaiveDateTime>,
+    pub purged_at: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -19,6 +19,27 @@ pub use sea_orm_migration::prelude::*;
 mod m20240108_114814_user_table;
 mod m20240119_135153_game;
 mod m20240201_110331_add_start_chat_columns_to_game_table;
+mod m20261018_141500_add_game_players_indexes;
+mod m20261018_153000_add_auto_plays_columns_to_game_table;
+mod m20261018_170000_refresh_token_table;
+mod m20261018_183000_add_token_version_column_to_user_table;
+mod m20261018_200000_session_table;
+mod m20261018_213000_add_totp_columns_to_user_table;
+mod m20261018_213500_recovery_code_table;
+mod m20261018_223000_failed_signin_table;
+mod m20261018_233000_add_recovery_email_column_to_user_table;
+mod m20261018_233500_password_recovery_code_table;
+mod m20261018_234000_password_reset_token_table;
+mod m20261018_235000_add_email_columns_to_user_table;
+mod m20261019_000000_user_identity_table;
+mod m20261019_000500_oidc_authorization_table;
+mod m20261019_003000_username_history_table;
+mod m20261019_010000_add_lower_username_index_to_user_table;
+mod m20261019_013000_add_deleted_at_column_to_user_table;
+mod m20261019_013500_add_players_names_columns_to_game_table;
+mod m20261019_014000_add_purged_at_column_to_user_table;
+mod m20261019_020000_privacy_setting_table;
+mod m20261019_030000_add_search_indexes_to_user_table;
 
 pub struct Migrator;
 
@@ -29,6 +50,27 @@ impl MigratorTrait for Migrator {
             Box::new(m20240108_114814_user_table::Migration),
             Box::new(m20240119_135153_game::Migration),
             Box::new(m20240201_110331_add_start_chat_columns_to_game_table::Migration),
+            Box::new(m20261018_141500_add_game_players_indexes::Migration),
+            Box::new(m20261018_153000_add_auto_plays_columns_to_game_table::Migration),
+            Box::new(m20261018_170000_refresh_token_table::Migration),
+            Box::new(m20261018_183000_add_token_version_column_to_user_table::Migration),
+            Box::new(m20261018_200000_session_table::Migration),
+            Box::new(m20261018_213000_add_totp_columns_to_user_table::Migration),
+            Box::new(m20261018_213500_recovery_code_table::Migration),
+            Box::new(m20261018_223000_failed_signin_table::Migration),
+            Box::new(m20261018_233000_add_recovery_email_column_to_user_table::Migration),
+            Box::new(m20261018_233500_password_recovery_code_table::Migration),
+            Box::new(m20261018_234000_password_reset_token_table::Migration),
+            Box::new(m20261018_235000_add_email_columns_to_user_table::Migration),
+            Box::new(m20261019_000000_user_identity_table::Migration),
+            Box::new(m20261019_000500_oidc_authorization_table::Migration),
+            Box::new(m20261019_003000_username_history_table::Migration),
+            Box::new(m20261019_010000_add_lower_username_index_to_user_table::Migration),
+            Box::new(m20261019_013000_add_deleted_at_column_to_user_table::Migration),
+            Box::new(m20261019_013500_add_players_names_columns_to_game_table::Migration),
+            Box::new(m20261019_014000_add_purged_at_column_to_user_table::Migration),
+            Box::new(m20261019_020000_privacy_setting_table::Migration),
+            Box::new(m20261019_030000_add_search_indexes_to_user_table::Migration),
         ]
     }
 }
@@ -77,7 +77,7 @@ impl MigrationTrait for Migration {
 }
 
 #[derive(DeriveIden)]
-enum User {
+pub enum User {
     Table,
     Id,
     Uuid,
@@ -90,4 +90,13 @@ enum User {
     Draw,
     PasswordHash,
     CreatedAt,
+    TokenVersion,
+    TotpSecret,
+    TotpEnabled,
+    TotpLastStep,
+    RecoveryEmail,
+    Email,
+    EmailVerified,
+    DeletedAt,
+    PurgedAt,
 }
@@ -69,9 +69,13 @@ pub enum Game {
     OPlayer,
     XStartChat,
     OStartChat,
+    XAutoPlays,
+    OAutoPlays,
     Board,
     Winner,
     Reason,
     CreatedAt,
     EndedAt,
+    XPlayerName,
+    OPlayerName,
 }
@@ -0,0 +1,58 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20240119_135153_game::Game;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Used by the games of two players, and the games of the X player.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-game-x_player-o_player")
+                    .table(Game::Table)
+                    .col(Game::XPlayer)
+                    .col(Game::OPlayer)
+                    .to_owned(),
+            )
+            .await?;
+        // Used by the games of the O player.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-game-o_player")
+                    .table(Game::Table)
+                    .col(Game::OPlayer)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx-game-o_player").to_owned())
+            .await?;
+        manager
+            .drop_index(Index::drop().name("idx-game-x_player-o_player").to_owned())
+            .await
+    }
+}
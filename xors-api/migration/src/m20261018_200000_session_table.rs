@@ -0,0 +1,87 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20240108_114814_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Session::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Session::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Session::Uuid).uuid().unique_key().not_null())
+                    .col(ColumnDef::new(Session::UserUuid).uuid().not_null())
+                    .col(ColumnDef::new(Session::UserAgent).string())
+                    .col(ColumnDef::new(Session::Ip).string())
+                    .col(ColumnDef::new(Session::CreatedAt).date_time().not_null())
+                    .col(ColumnDef::new(Session::LastRefreshAt).date_time())
+                    .col(ColumnDef::new(Session::RevokedAt).date_time())
+                    // The user's sessions are deleted with him.
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-session-user_uuid")
+                            .from(Session::Table, Session::UserUuid)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Used to list the user's sessions.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-session-user_uuid")
+                    .table(Session::Table)
+                    .col(Session::UserUuid)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Session::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Session {
+    Table,
+    Id,
+    Uuid,
+    UserUuid,
+    UserAgent,
+    Ip,
+    CreatedAt,
+    LastRefreshAt,
+    RevokedAt,
+}
@@ -0,0 +1,86 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FailedSignin::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(FailedSignin::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    // Not a foreign key, the attempts of the unknown usernames are recorded too.
+                    .col(ColumnDef::new(FailedSignin::Username).string().not_null())
+                    .col(ColumnDef::new(FailedSignin::UserAgent).string())
+                    .col(ColumnDef::new(FailedSignin::Ip).string())
+                    .col(
+                        ColumnDef::new(FailedSignin::AttemptedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Used to count the username's failed attempts.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-failed_signin-username")
+                    .table(FailedSignin::Table)
+                    .col(FailedSignin::Username)
+                    .to_owned(),
+            )
+            .await?;
+        // Used to forget the old failed attempts.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-failed_signin-attempted_at")
+                    .table(FailedSignin::Table)
+                    .col(FailedSignin::AttemptedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FailedSignin::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum FailedSignin {
+    Table,
+    Id,
+    Username,
+    UserAgent,
+    Ip,
+    AttemptedAt,
+}
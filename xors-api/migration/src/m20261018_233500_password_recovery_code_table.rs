@@ -0,0 +1,87 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use sea_orm_migration::prelude::*;
+
+use crate::m20240108_114814_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PasswordRecoveryCode::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PasswordRecoveryCode::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PasswordRecoveryCode::UserUuid)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasswordRecoveryCode::CodeHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PasswordRecoveryCode::UsedAt).date_time())
+                    // The user's recovery codes are deleted with him.
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-password_recovery_code-user_uuid")
+                            .from(PasswordRecoveryCode::Table, PasswordRecoveryCode::UserUuid)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Used to find the user's recovery codes.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-password_recovery_code-user_uuid")
+                    .table(PasswordRecoveryCode::Table)
+                    .col(PasswordRecoveryCode::UserUuid)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PasswordRecoveryCode::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PasswordRecoveryCode {
+    Table,
+    Id,
+    UserUuid,
+    CodeHash,
+    UsedAt,
+}
@@ -0,0 +1,110 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use sea_orm_migration::{prelude::*, sea_orm::Statement};
+
+use crate::m20240108_114814_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The recovery email becomes the user's email, it's verified before receiving the password reset tokens.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .rename_column(User::RecoveryEmail, User::Email)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(
+                        ColumnDef::new(User::EmailVerified)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // The recovery emails were set by their users with their passwords, so they stay usable to reset the passwords.
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE "user" SET "email_verified" = TRUE WHERE "email" IS NOT NULL"#,
+            )
+            .await?;
+
+        // The emails are unique regardless of their case. The duplicates can't be resolved automatically
+        // without taking the email from one of the users, so they have to be resolved manually first.
+        let duplicates = manager
+            .get_connection()
+            .query_one(Statement::from_string(
+                manager.get_database_backend(),
+                r#"SELECT COUNT(*) AS "count" FROM (SELECT LOWER("email") FROM "user" WHERE "email" IS NOT NULL GROUP BY LOWER("email") HAVING COUNT(*) > 1) AS "duplicates""#,
+            ))
+            .await?
+            .map(|row| row.try_get::<i64>("", "count"))
+            .transpose()?
+            .unwrap_or_default();
+        if duplicates != 0 {
+            return Err(DbErr::Migration(format!(
+                "Some recovery emails are used by more than one user regardless of their case ({duplicates} emails), \
+                 make them unique before running this migration"
+            )));
+        }
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"CREATE UNIQUE INDEX "idx-user-email" ON "user" (LOWER("email"))"#,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-user-email")
+                    .table(User::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::EmailVerified)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .rename_column(User::Email, User::RecoveryEmail)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,87 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use sea_orm_migration::prelude::*;
+
+use crate::m20240108_114814_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserIdentity::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserIdentity::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserIdentity::UserUuid).uuid().not_null())
+                    .col(ColumnDef::new(UserIdentity::Provider).string().not_null())
+                    // The `sub` claim of the provider's ID token, it's unique per provider.
+                    .col(ColumnDef::new(UserIdentity::Subject).string().not_null())
+                    .col(
+                        ColumnDef::new(UserIdentity::CreatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    // The user's identities are deleted with him.
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-user_identity-user_uuid")
+                            .from(UserIdentity::Table, UserIdentity::UserUuid)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // An identity can only be linked to one user.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-user_identity-provider-subject")
+                    .table(UserIdentity::Table)
+                    .col(UserIdentity::Provider)
+                    .col(UserIdentity::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserIdentity::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum UserIdentity {
+    Table,
+    Id,
+    UserUuid,
+    Provider,
+    Subject,
+    CreatedAt,
+}
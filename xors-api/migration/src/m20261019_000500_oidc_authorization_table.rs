@@ -0,0 +1,81 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OidcAuthorization::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OidcAuthorization::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    // The `state` parameter of the authorization request.
+                    .col(
+                        ColumnDef::new(OidcAuthorization::State)
+                            .string()
+                            .unique_key()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OidcAuthorization::Provider)
+                            .string()
+                            .not_null(),
+                    )
+                    // The PKCE code verifier, it never leaves the server until the code exchange.
+                    .col(
+                        ColumnDef::new(OidcAuthorization::CodeVerifier)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OidcAuthorization::Nonce).string().not_null())
+                    .col(
+                        ColumnDef::new(OidcAuthorization::ExpiresAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OidcAuthorization::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum OidcAuthorization {
+    Table,
+    Id,
+    State,
+    Provider,
+    CodeVerifier,
+    Nonce,
+    ExpiresAt,
+}
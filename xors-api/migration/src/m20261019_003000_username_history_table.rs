@@ -0,0 +1,104 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use sea_orm_migration::prelude::*;
+
+use crate::m20240108_114814_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UsernameHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UsernameHistory::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UsernameHistory::UserUuid).uuid().not_null())
+                    // The old username, before the change.
+                    .col(
+                        ColumnDef::new(UsernameHistory::Username)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UsernameHistory::ChangedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    // Only the user himself can take the old username before this date.
+                    .col(
+                        ColumnDef::new(UsernameHistory::ReservedUntil)
+                            .date_time()
+                            .not_null(),
+                    )
+                    // The user's history is deleted with him.
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-username_history-user_uuid")
+                            .from(UsernameHistory::Table, UsernameHistory::UserUuid)
+                            .to(User::Table, User::Uuid)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Used to find the last change of the user.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-username_history-user_uuid")
+                    .table(UsernameHistory::Table)
+                    .col(UsernameHistory::UserUuid)
+                    .to_owned(),
+            )
+            .await?;
+        // Used to check the reserved usernames.
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-username_history-username")
+                    .table(UsernameHistory::Table)
+                    .col(UsernameHistory::Username)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UsernameHistory::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum UsernameHistory {
+    Table,
+    Id,
+    UserUuid,
+    Username,
+    ChangedAt,
+    ReservedUntil,
+}
@@ -0,0 +1,54 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let conn = manager.get_connection();
+        // The trigram indexes are used by the fuzzy search, and the prefix index by the
+        // short queries, which the trigrams can't match.
+        conn.execute_unprepared("CREATE EXTENSION IF NOT EXISTS pg_trgm")
+            .await?;
+        conn.execute_unprepared(
+            r#"CREATE INDEX "idx-user-username-prefix" ON "user" (LOWER("username") text_pattern_ops)"#,
+        )
+        .await?;
+        conn.execute_unprepared(
+            r#"CREATE INDEX "idx-user-username-trgm" ON "user" USING GIN (LOWER("username") gin_trgm_ops)"#,
+        )
+        .await?;
+        conn.execute_unprepared(
+            r#"CREATE INDEX "idx-user-name-trgm" ON "user" USING GIN (LOWER("first_name" || ' ' || COALESCE("last_name", '')) gin_trgm_ops)"#,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"DROP INDEX "idx-user-username-prefix", "idx-user-username-trgm", "idx-user-name-trgm""#,
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -25,15 +25,29 @@ use uuid::Uuid;
 
 use crate::{
     db_utils,
-    errors::{ApiError, ApiResult},
-    schemas::{GameOverReason, XoServerEventData},
+    errors::{ApiError, ApiResult, GameParseError},
+    schemas::{Board, GameOverReason, NewSessionSchema, RoundsResult, XoServerEventData},
 };
 
 use super::{jwt::JwtClaims, xo::Player};
 
 #[ext(UserExt)]
 impl Depot {
+    /// Returns the authorized user, the deleted accounts are rejected until they are restored.
     pub(crate) async fn user(&self, conn: &DatabaseConnection) -> ApiResult<UserModel> {
+        let user = self.user_including_deleted(conn).await?;
+        if user.deleted_at.is_some() {
+            return Err(ApiError::AccountDeleted);
+        }
+        Ok(user)
+    }
+
+    /// Returns the authorized user even if his account is deleted.
+    /// Only for the routes that the deleted accounts can use, restoring, exporting and logging out.
+    pub(crate) async fn user_including_deleted(
+        &self,
+        conn: &DatabaseConnection,
+    ) -> ApiResult<UserModel> {
         // Note: The `Unauthorized` and `Forbidden` errors are handled by the `JwtAuth` middleware.
         let jwt = self
             .jwt_auth_data::<JwtClaims>()
@@ -44,11 +58,36 @@ impl Depot {
         } else if jwt.claims.is_expired() {
             Err(ApiError::ExpiredToken)
         } else {
-            UserEntity::find()
+            let user = UserEntity::find()
                 .filter(UserColumn::Uuid.eq(jwt.claims.uuid))
                 .one(conn)
                 .await?
-                .ok_or_else(|| ApiError::UserNotFound)
+                .ok_or_else(|| ApiError::UserNotFound)?;
+
+            // Tokens without session are issued before the sessions, so they can't be revoked.
+            let Some(session) = jwt.claims.session else {
+                return Err(ApiError::RevokedToken);
+            };
+            if jwt.claims.token_version != user.token_version
+                || !db_utils::is_active_session(conn, session).await?
+            {
+                Err(ApiError::RevokedToken)
+            } else {
+                Ok(user)
+            }
+        }
+    }
+
+    /// Returns the requester's uuid, in the routes that the authorization is optional in.
+    /// Will be `None` if the request has no valid user token.
+    pub(crate) async fn requester(&self, conn: &DatabaseConnection) -> ApiResult<Option<Uuid>> {
+        if self.jwt_auth_data::<JwtClaims>().is_none() {
+            return Ok(None);
+        }
+        match self.user(conn).await {
+            Ok(user) => Ok(Some(user.uuid)),
+            Err(ApiError::SeaOrm(err)) => Err(err.into()),
+            Err(_) => Ok(None),
         }
     }
 
@@ -60,6 +99,76 @@ impl Depot {
     }
 }
 
+#[ext(RequestExt)]
+impl Request {
+    /// Returns the new session's data of the request's client.
+    pub(crate) fn new_session(&self) -> NewSessionSchema {
+        NewSessionSchema {
+            user_agent: self
+                .headers()
+                .get(salvo::http::header::USER_AGENT)
+                .and_then(|user_agent| user_agent.to_str().ok())
+                .map(ToOwned::to_owned),
+            ip: self
+                .remote_addr()
+                .clone()
+                .into_std()
+                .map(|addr| addr.ip().to_string()),
+        }
+    }
+}
+
+#[ext(GameModelExt)]
+impl GameModel {
+    /// Returns the parsed board of the game, or [`ApiError::CorruptedGame`] if it's invalid.
+    pub(crate) fn parse_board(&self) -> ApiResult<Board> {
+        self.board
+            .parse()
+            .map_err(|source| ApiError::CorruptedGame {
+                uuid: self.uuid,
+                source,
+            })
+    }
+
+    /// Returns the parsed rounds result of the game, or [`ApiError::CorruptedGame`] if it's invalid.
+    pub(crate) fn parse_rounds_result(&self) -> ApiResult<RoundsResult> {
+        self.rounds_result
+            .parse()
+            .map_err(|source| ApiError::CorruptedGame {
+                uuid: self.uuid,
+                source,
+            })
+    }
+
+    /// Returns the parsed game over reason, or [`ApiError::CorruptedGame`] if it's invalid.
+    pub(crate) fn parse_reason(&self) -> ApiResult<Option<GameOverReason>> {
+        self.reason
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|source| ApiError::CorruptedGame {
+                uuid: self.uuid,
+                source,
+            })
+    }
+
+    /// Validates the stored game data. The rounds result, the game over reason and the board,
+    /// the board of an ended game must be cleared.
+    pub(crate) fn validate(&self) -> ApiResult<()> {
+        self.parse_rounds_result()?;
+        self.parse_reason()?;
+        if self.ended_at.is_none() {
+            self.parse_board()?;
+        } else if !self.board.is_empty() {
+            return Err(ApiError::CorruptedGame {
+                uuid: self.uuid,
+                source: GameParseError::EndedGameBoard(self.board.clone()),
+            });
+        }
+        Ok(())
+    }
+}
+
 #[ext(WriteGamesExt)]
 impl RwLock<super::xo::Games> {
     pub(crate) async fn add_game(&self, game_uuid: Uuid, player1: Player, player2: Player) {
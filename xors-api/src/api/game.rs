@@ -14,7 +14,13 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::{db_utils, errors::ApiResult, schemas::*};
+use crate::api::exts::*;
+use crate::{
+    db_utils,
+    errors::{ApiError, ApiResult},
+    schemas::*,
+    utils,
+};
 
 use futures_util::StreamExt;
 use salvo::oapi::extract::PathParam;
@@ -28,6 +34,7 @@ use std::sync::Arc;
 /// Get the game by uuid.
 ///
 /// **Note**: This will return the game only if it's ended.
+/// The games of the players that hide their game history are only returned to them, send their JWT in the `Authorization` header.
 #[endpoint(
     operation_id = "get_game_by_uuid",
     tags("Game"),
@@ -47,38 +54,69 @@ pub async fn get_game_by_uuid(
     uuid: PathParam<Uuid>,
 ) -> ApiResult<Json<GameSchema>> {
     let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap().as_ref();
+    let requester = depot.requester(conn).await?;
 
-    GameSchema::from_game(
-        conn,
-        db_utils::get_game::<true>(conn, &uuid.into_inner()).await?,
-    )
-    .await
-    .map(Json)
+    let game = db_utils::get_game::<true>(conn, &uuid.into_inner()).await?;
+    for player in [game.x_player, game.o_player] {
+        db_utils::check_game_history_visibility(conn, player, requester)
+            .await
+            .map_err(|err| match err {
+                ApiError::PrivateProfile => ApiError::GameNotFound,
+                err => err,
+            })?;
+    }
+    GameSchema::from_game(conn, game, requester).await.map(Json)
 }
 
-/// Get the lastest 10 games.
+/// Get the games history.
 ///
-/// This endpoint will return the lastest 10 games, sorted by the creation date (newest first)
+/// This endpoint will return a page of the ended games, sorted by the creation date (newest first).
+/// - Use the filters to get the games of a player, e.g. `/games?player=<uuid>&result=win`.
+/// - To get the next page, send the `next_cursor` of the current page as the `cursor`, with the same filters.
+/// - The games of the players that hide their game history are excluded, except for the requester's own games. Send the JWT in the `Authorization` header to see them.
 ///
 /// **Note**: If the O player or the X player is deleted, the game will return it as deleted user, which it's uuid is `00000000-0000-0000-0000-000000000000` and username is `Deleted`.
 #[endpoint(
-    operation_id = "get_lastest_games",
+    operation_id = "get_games",
     tags("Game"),
     responses(
-        (status_code = 200, description = "The lastest 10 games", content_type = "application/json", body = Vec<GameSchema>),
+        (status_code = 200, description = "The games page", content_type = "application/json", body = GamesPageSchema),
+        (status_code = 400, description = "Invalid games filter", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid cursor", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The player hides his game history", content_type = "application/json", body = MessageSchema),
         (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
         (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
     ),
 )]
-pub async fn get_lastest_games(depot: &mut Depot) -> ApiResult<Json<Vec<GameSchema>>> {
+pub async fn get_games(
+    depot: &mut Depot,
+    filter: GamesFilterSchema,
+) -> ApiResult<Json<GamesPageSchema>> {
     let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap().as_ref();
+    let requester = depot.requester(conn).await?;
+
+    utils::validate_games_filter(&filter)?;
+    if let Some(player) = filter.player {
+        db_utils::check_game_history_visibility(conn, player, requester).await?;
+    }
+    let limit = filter.limit.unwrap_or(10);
+    let cursor = filter.cursor.as_deref().map(str::parse).transpose()?;
+
+    let mut games = db_utils::get_games(conn, &filter, cursor, limit + 1, requester).await?;
+    let next_cursor = if games.len() as u64 > limit {
+        games.truncate(limit as usize);
+        games.last().map(|game| GamesCursor::from(game).to_string())
+    } else {
+        None
+    };
 
-    Ok(Json(
-        futures_util::stream::iter(db_utils::get_lastest_games(conn).await?)
-            .then(|game| GameSchema::from_game(conn, game))
+    Ok(Json(GamesPageSchema {
+        games: futures_util::stream::iter(games)
+            .then(|game| GameSchema::from_game(conn, game, requester))
             .collect::<Vec<_>>()
             .await
             .into_iter()
             .collect::<ApiResult<_>>()?,
-    ))
+        next_cursor,
+    }))
 }
@@ -17,22 +17,30 @@
 use std::{str::FromStr, sync::Arc};
 
 use crate::{
+    config::AuthConfig,
     db_utils,
     errors::{ApiError, ApiResult},
+    mailer::{Mail, Mailer},
+    password::PasswordHasher,
     schemas::*,
-    utils,
+    totp, utils,
 };
 
 use base64::Engine;
 use chrono::Duration;
-use salvo::{oapi::extract::JsonBody, prelude::*};
+use entity::prelude::*;
+use salvo::{
+    oapi::extract::{JsonBody, PathParam, QueryParam},
+    prelude::*,
+};
 use salvo_captcha::{
     CacacheStorage, CaptchaDepotExt, CaptchaDifficulty, CaptchaGenerator, CaptchaName,
 };
+use sea_orm::TryIntoModel;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::exts::UserExt;
+use super::exts::{RequestExt, UserExt};
 
 #[derive(Debug, Serialize, Deserialize, derive_new::new)]
 pub struct JwtClaims {
@@ -41,6 +49,38 @@ pub struct JwtClaims {
     /// The refresh token activate date.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub active_after: Option<i64>,
+    /// The refresh token's unique id. Used to rotate and revoke the refresh token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<Uuid>,
+    /// The session that the token belongs to, it's the refresh tokens family of the signin.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<Uuid>,
+    /// The user's token version when the token was issued. Logging out of all devices will increase it.
+    #[serde(default)]
+    pub token_version: i32,
+    /// The token's expiration date.
+    pub exp: i64,
+}
+
+/// The two-factor authentication challenge claims. It's a different claims than [`JwtClaims`],
+/// so the challenge token can't be used as a JWT token and vice versa.
+#[derive(Debug, Serialize, Deserialize, derive_new::new)]
+pub struct TotpChallengeClaims {
+    /// The uuid of the user that passed the password step.
+    pub sub: Uuid,
+    /// The user's token version when the challenge was issued.
+    pub token_version: i32,
+    /// The challenge's expiration date.
+    pub exp: i64,
+}
+
+/// The email verification claims. The token is sent by mail, and it's only valid for the email that it was issued for.
+#[derive(Debug, Serialize, Deserialize, derive_new::new)]
+pub struct EmailVerificationClaims {
+    /// The uuid of the user that owns the email.
+    pub sub: Uuid,
+    /// The email that the token was issued for.
+    pub email: String,
     /// The token's expiration date.
     pub exp: i64,
 }
@@ -57,6 +97,53 @@ impl JwtClaims {
     }
 }
 
+/// Sends the email verification link to the user's email.
+pub(crate) async fn send_email_verification(
+    mailer: &dyn Mailer,
+    auth_config: &AuthConfig,
+    user_uuid: Uuid,
+    first_name: &str,
+    username: &str,
+    email: String,
+) -> ApiResult<()> {
+    log::info!("Sending the email verification link to user: {username}");
+
+    let expired_at = chrono::Utc::now() + auth_config.email_verification_ttl;
+    let token = auth_config.encode_token(&EmailVerificationClaims::new(
+        user_uuid,
+        email.clone(),
+        expired_at.timestamp(),
+    ));
+    mailer
+        .send(Mail::new(
+            email,
+            "Verify your XORS email".to_owned(),
+            format!(
+                "Hi {},\n\nOpen the following link to verify the email of your XORS account `{}`, \
+                 it expires at {} UTC:\n\n{}/auth/verify_email?token={token}\n\n\
+                 If you didn't add this email, you can ignore this mail.",
+                first_name,
+                username,
+                expired_at.format("%Y-%m-%d %H:%M:%S"),
+                auth_config.public_url
+            ),
+        ))
+        .await
+}
+
+/// Returns the two-factor authentication challenge of the user, he passed the first step of the signin.
+fn totp_challenge(auth_config: &AuthConfig, user: &UserActiveModel) -> SigninResponseSchema {
+    let expired_at = chrono::Utc::now().naive_utc() + auth_config.totp_challenge_ttl;
+    SigninResponseSchema::TotpChallenge(TotpChallengeSchema {
+        challenge_token: auth_config.encode_token(&TotpChallengeClaims::new(
+            *user.uuid.as_ref(),
+            *user.token_version.as_ref(),
+            expired_at.timestamp(),
+        )),
+        expired_at,
+    })
+}
+
 /// Create a new captcha.
 ///
 /// This endpoint will create a new captcha and return the captcha token and the captcha image as base64.
@@ -91,11 +178,34 @@ pub async fn captcha(depot: &mut Depot) -> ApiResult<Json<CaptchaSchema>> {
     }))
 }
 
+/// Get the public keys.
+///
+/// This endpoint will return the active public keys that verify the XORS tokens, as a JSON Web Key Set.
+/// The token's `kid` header is the id of the key that signed it. Will be empty if the tokens are signed with a secret.
+#[endpoint(
+    operation_id = "jwks",
+    tags("Auth"),
+    responses(
+        (status_code = 200, description = "The public keys", content_type = "application/json", body = JwksSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    )
+)]
+pub async fn jwks(depot: &mut Depot) -> Json<JwksSchema> {
+    Json(JwksSchema {
+        keys: depot.obtain::<Arc<AuthConfig>>().unwrap().public_keys(),
+    })
+}
+
 /// Sign up a new user.
 ///
-/// This endpoint will create a new user and return a JWT token.
+/// This endpoint will create a new user and return a JWT token, with the password recovery codes.
+/// If an email is provided, a verification link will be sent to it. If sending it fails the user is still
+/// created, and the link can be sent again with the `/user/email/verify` endpoint.
 /// - `captcha_token`: The captcha token. Get it from the `/auth/captcha` endpoint.
 /// - `captcha_answer`: The captcha answer. The text that in the captcha image.
+/// - The password recovery codes can be exchanged with a password reset token in `/auth/recover_password`,
+///   each one only one time. They will not be shown again.
 #[endpoint(
     operation_id = "signup_user",
     tags("Auth"),
@@ -112,6 +222,8 @@ pub async fn captcha(depot: &mut Depot) -> ApiResult<Json<CaptchaSchema>> {
     responses(
         (status_code = 200, description = "User created", content_type = "application/json", body = UserSigninSchema),
         (status_code = 400, description = "Username already exists", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Email already exists", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid email", content_type = "application/json", body = MessageSchema),
         (status_code = 400, description = "Unprovided captcha token or answer", content_type = "application/json", body = MessageSchema),
         (status_code = 403, description = "Invalid captcha token", content_type = "application/json", body = MessageSchema),
         (status_code = 403, description = "Invalid captcha answer", content_type = "application/json", body = MessageSchema),
@@ -120,11 +232,13 @@ pub async fn captcha(depot: &mut Depot) -> ApiResult<Json<CaptchaSchema>> {
     )
 )]
 pub async fn signup(
+    req: &mut Request,
     depot: &mut Depot,
     new_user: JsonBody<NewUserSchema>,
 ) -> ApiResult<Json<UserSigninSchema>> {
     let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
-    let secret_key = depot.get::<Arc<String>>("secret_key").unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let mailer = depot.obtain::<Arc<dyn Mailer>>().unwrap();
     let user = new_user.into_inner();
 
     utils::handle_captcha_state(
@@ -134,17 +248,48 @@ pub async fn signup(
     )?;
     utils::validate_user_registration(&user)?;
 
-    db_utils::signin_user(
-        db_utils::create_user(conn.as_ref(), user).await?,
-        secret_key,
+    let user = db_utils::create_user(conn.as_ref(), user, &auth_config.password_hasher).await?;
+    let recovery_codes = db_utils::generate_password_recovery_codes(
+        conn.as_ref(),
+        user.uuid,
+        &auth_config.password_hasher,
     )
-    .await
-    .map(Json)
+    .await?;
+    if let Some(email) = user.email.clone() {
+        if let Err(err) = send_email_verification(
+            mailer.as_ref(),
+            auth_config,
+            user.uuid,
+            &user.first_name,
+            &user.username,
+            email,
+        )
+        .await
+        {
+            log::error!(
+                "Failed to send the email verification link to user {}: {err}",
+                user.username
+            );
+        }
+    }
+    db_utils::signin_user(conn.as_ref(), user, auth_config, req.new_session())
+        .await
+        .map(|user_signin| {
+            Json(UserSigninSchema {
+                recovery_codes: Some(recovery_codes),
+                ..user_signin
+            })
+        })
 }
 
 /// Signin a user.
 ///
 /// This endpoint will return a JWT token with a refresh token.
+/// If the user has enabled the two-factor authentication, it will return a challenge token instead,
+/// send it with the code to the `/auth/signin/totp` endpoint to complete the signin.
+/// - The failed signin attempts since the last signin are returned in `failed_signins`.
+/// - After 5 failed attempts by default, the account will be locked for 30 seconds, doubled with every failed attempt.
+///   The `Retry-After` header has the remaining seconds.
 #[endpoint(
     operation_id = "signin_user",
     tags("Auth"),
@@ -159,21 +304,23 @@ pub async fn signup(
         ("X-Captcha-Answer" = String, Header, description = "The captcha answer, the text that in the captcha image"),
     ),
     responses(
-        (status_code = 200, description = "User signed in", content_type = "application/json", body = UserSigninSchema),
+        (status_code = 200, description = "User signed in, or the two-factor authentication challenge", content_type = "application/json", body = SigninResponseSchema),
         (status_code = 400, description = "Invalid username or password", content_type = "application/json", body = MessageSchema),
         (status_code = 400, description = "Unprovided captcha token or answer", content_type = "application/json", body = MessageSchema),
         (status_code = 403, description = "Invalid captcha token", content_type = "application/json", body = MessageSchema),
         (status_code = 403, description = "Invalid captcha answer", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is locked, retry after the `Retry-After` header seconds", content_type = "application/json", body = MessageSchema),
         (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
         (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
     )
 )]
 pub async fn signin(
+    req: &mut Request,
     depot: &mut Depot,
     signin_schema: JsonBody<SigninSchema>,
-) -> ApiResult<Json<UserSigninSchema>> {
+) -> ApiResult<Json<SigninResponseSchema>> {
     let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
-    let secret_key = depot.get::<Arc<String>>("secret_key").unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
     let signin_schema = signin_schema.into_inner();
 
     utils::handle_captcha_state(
@@ -184,25 +331,875 @@ pub async fn signin(
 
     utils::validate_password(&signin_schema.password)?;
     utils::validate_user_signin(&signin_schema.username)?;
+    db_utils::check_account_lock(conn.as_ref(), auth_config, &signin_schema.username).await?;
 
-    if let Ok(user) = db_utils::get_user_by_username(conn.as_ref(), signin_schema.username).await {
-        if bcrypt::verify(&signin_schema.password, user.password_hash.as_ref()).unwrap_or_default()
-        {
+    if let Ok(user) =
+        db_utils::get_user_by_username(conn.as_ref(), signin_schema.username.clone()).await
+    {
+        let (is_correct_password, new_password_hash) = auth_config
+            .password_hasher
+            .verify_and_rehash_async(&signin_schema.password, user.password_hash.as_ref())
+            .await
+            .unwrap_or_default();
+        if is_correct_password {
+            if let Some(new_password_hash) = new_password_hash {
+                db_utils::rehash_password(
+                    conn.as_ref(),
+                    *user.uuid.as_ref(),
+                    user.password_hash.as_ref(),
+                    new_password_hash,
+                )
+                .await?;
+            }
+            if *user.totp_enabled.as_ref() {
+                return Ok(Json(totp_challenge(auth_config, &user)));
+            }
             return db_utils::signin_user(
-                UserSchema::from_active_model(conn, user).await?,
-                secret_key,
+                conn.as_ref(),
+                UserSchema::owner_from_active_model(conn, user).await?,
+                auth_config,
+                req.new_session(),
             )
             .await
-            .map(Json);
+            .map(|user_signin| Json(SigninResponseSchema::Signin(Box::new(user_signin))));
         }
     }
+    db_utils::record_failed_signin(conn.as_ref(), signin_schema.username, req.new_session())
+        .await?;
     Err(ApiError::InvalidSigninCredentials)
 }
 
+/// Complete the signin with the two-factor authentication.
+///
+/// This endpoint will return a JWT token with a refresh token, in exchange of the signin challenge token
+/// and the authenticator app code or a recovery code.
+/// - The challenge token is valid for 5 minutes by default.
+/// - The authenticator app code can only be used one time, and so the recovery code.
+/// - The incorrect codes are counted as failed signin attempts, and can lock the account.
+#[endpoint(
+    operation_id = "signin_totp",
+    tags("Auth"),
+    request_body(
+        content = TotpSigninSchema,
+        description = "The challenge token and the code",
+        example = json!(TotpSigninSchema::default()),
+        content_type = "application/json",
+    ),
+    responses(
+        (status_code = 200, description = "User signed in", content_type = "application/json", body = UserSigninSchema),
+        (status_code = 403, description = "The challenge token is invalid or expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The code is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is locked, retry after the `Retry-After` header seconds", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    )
+)]
+pub async fn signin_totp(
+    req: &mut Request,
+    depot: &mut Depot,
+    totp_signin: JsonBody<TotpSigninSchema>,
+) -> ApiResult<Json<UserSigninSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let totp_signin = totp_signin.into_inner();
+
+    let claims = auth_config
+        .decode_token::<TotpChallengeClaims>(&totp_signin.challenge_token)
+        .map_err(|_| ApiError::InvalidTotpChallenge)?
+        .claims;
+    if claims.exp <= chrono::Utc::now().timestamp() {
+        return Err(ApiError::InvalidTotpChallenge);
+    }
+
+    let user = db_utils::get_user(conn.as_ref(), claims.sub)
+        .await?
+        .try_into_model()?;
+    // Logging out of all devices or resetting the password revokes the challenges
+    if user.token_version != claims.token_version {
+        return Err(ApiError::InvalidTotpChallenge);
+    }
+    db_utils::check_account_lock(conn.as_ref(), auth_config, &user.username).await?;
+    if let Err(err) = db_utils::verify_second_factor(conn.as_ref(), &user, &totp_signin.code).await
+    {
+        if matches!(err, ApiError::InvalidTotpCode) {
+            db_utils::record_failed_signin(conn.as_ref(), user.username, req.new_session()).await?;
+        }
+        return Err(err);
+    }
+
+    db_utils::signin_user(
+        conn.as_ref(),
+        UserSchema::owner_from_active_model(conn, user.into_active_model()).await?,
+        auth_config,
+        req.new_session(),
+    )
+    .await
+    .map(Json)
+}
+
+/// Start the signin with an OpenID Connect provider.
+///
+/// This endpoint will return the provider authorization URL, redirect the user to it to signin there.
+/// The provider redirects the user back to the `/auth/oidc/{provider}/callback` endpoint with the authorization code.
+/// - The authorization is valid for 10 minutes, and can only be used one time.
+/// - The code exchange is protected with PKCE, the code verifier never leaves the server.
+#[endpoint(
+    operation_id = "oidc_authorize",
+    tags("Auth"),
+    parameters(
+        ("provider" = String, Path, description = "The OpenID Connect provider name"),
+    ),
+    responses(
+        (status_code = 200, description = "The provider authorization URL", content_type = "application/json", body = OidcAuthorizationSchema),
+        (status_code = 404, description = "OIDC provider not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Failed to reach the OIDC provider", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    )
+)]
+pub async fn oidc_authorize(
+    depot: &mut Depot,
+    provider: PathParam<String>,
+) -> ApiResult<Json<OidcAuthorizationSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let provider = auth_config.oidc_provider(&provider.into_inner())?;
+
+    let metadata = provider.metadata().await?;
+    let authorization = db_utils::create_oidc_authorization(conn.as_ref(), &provider.name).await?;
+
+    Ok(Json(OidcAuthorizationSchema {
+        authorization_url: provider.authorization_url(
+            &metadata,
+            &authorization.state,
+            &authorization.nonce,
+            &authorization.code_verifier,
+        )?,
+        expired_at: authorization.expires_at,
+    }))
+}
+
+/// Complete the signin with an OpenID Connect provider.
+///
+/// This endpoint is the callback that the provider redirects the user to, it will exchange the authorization code
+/// with the provider ID token, and return a JWT token with a refresh token like the `/auth/signin` endpoint.
+/// - The identity is linked to the user with the same verified email, if the provider verified it too.
+/// - Otherwise a new user is created, and the password recovery codes are returned. They can set a password.
+/// - If the user has enabled the two-factor authentication, the challenge token is returned instead.
+#[endpoint(
+    operation_id = "oidc_callback",
+    tags("Auth"),
+    parameters(
+        ("provider" = String, Path, description = "The OpenID Connect provider name"),
+        ("state" = String, Query, description = "The authorization state, returned by the provider"),
+        ("code" = Option<String>, Query, description = "The authorization code, returned by the provider"),
+        ("error" = Option<String>, Query, description = "The authorization error, returned by the provider"),
+    ),
+    responses(
+        (status_code = 200, description = "User signed in, or the two-factor authentication challenge", content_type = "application/json", body = SigninResponseSchema),
+        (status_code = 403, description = "Invalid OIDC state", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Invalid OIDC login", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "OIDC provider not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Failed to reach the OIDC provider", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    )
+)]
+pub async fn oidc_callback(
+    req: &mut Request,
+    depot: &mut Depot,
+    provider: PathParam<String>,
+    state: QueryParam<String, true>,
+    code: QueryParam<String, false>,
+    error: QueryParam<String, false>,
+) -> ApiResult<Json<SigninResponseSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let provider = auth_config.oidc_provider(&provider.into_inner())?;
+
+    let authorization =
+        db_utils::take_oidc_authorization(conn.as_ref(), &provider.name, &state.into_inner())
+            .await?;
+    if let Some(error) = error.into_inner() {
+        return Err(ApiError::InvalidOidcLogin(format!(
+            "The provider returned `{error}`"
+        )));
+    }
+    let code = code.into_inner().ok_or_else(|| {
+        ApiError::InvalidOidcLogin("The authorization code is missing".to_owned())
+    })?;
+
+    let metadata = provider.metadata().await?;
+    let claims = provider
+        .exchange_code(
+            &metadata,
+            &code,
+            &authorization.code_verifier,
+            &authorization.nonce,
+        )
+        .await?;
+    let (user, created) = db_utils::get_or_create_oidc_user(
+        conn.as_ref(),
+        &provider.name,
+        claims,
+        &auth_config.password_hasher,
+    )
+    .await?;
+
+    if *user.totp_enabled.as_ref() {
+        return Ok(Json(totp_challenge(auth_config, &user)));
+    }
+    let recovery_codes = if created {
+        Some(
+            db_utils::generate_password_recovery_codes(
+                conn.as_ref(),
+                *user.uuid.as_ref(),
+                &auth_config.password_hasher,
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
+    db_utils::signin_user(
+        conn.as_ref(),
+        UserSchema::owner_from_active_model(conn, user).await?,
+        auth_config,
+        req.new_session(),
+    )
+    .await
+    .map(|user_signin| {
+        Json(SigninResponseSchema::Signin(Box::new(UserSigninSchema {
+            recovery_codes,
+            ..user_signin
+        })))
+    })
+}
+
+/// Enroll the two-factor authentication.
+///
+/// This endpoint will return a new TOTP secret with its provisioning URI and QR code, to add it to an authenticator app.
+/// The two-factor authentication will not be enabled until a code is verified with the `/auth/totp/verify` endpoint.
+/// Enrolling again will replace the unverified secret.
+#[endpoint(
+    operation_id = "enroll_totp",
+    tags("Auth"),
+    responses(
+        (status_code = 200, description = "The TOTP secret", content_type = "application/json", body = TotpEnrollmentSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The two-factor authentication is already enabled", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn enroll_totp(depot: &mut Depot) -> ApiResult<Json<TotpEnrollmentSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+    let username = user.username.clone();
+
+    let secret = db_utils::enroll_totp(conn.as_ref(), user).await?;
+    let provisioning_uri = totp::provisioning_uri(&username, &secret);
+
+    Ok(Json(TotpEnrollmentSchema {
+        secret: totp::encode_secret(&secret),
+        qr_code: totp::qr_code_data_url(&provisioning_uri),
+        provisioning_uri,
+    }))
+}
+
+/// Verify the two-factor authentication.
+///
+/// This endpoint will enable the two-factor authentication if the authenticator app code is correct,
+/// and return the recovery codes.
+/// - The recovery codes can be used instead of the authenticator app code, each one only one time.
+/// - The recovery codes will not be shown again.
+#[endpoint(
+    operation_id = "verify_totp",
+    tags("Auth"),
+    request_body(
+        content = TotpCodeSchema,
+        description = "The authenticator app code",
+        example = json!(TotpCodeSchema::default()),
+        content_type = "application/json",
+    ),
+    responses(
+        (status_code = 200, description = "The two-factor authentication has been enabled", content_type = "application/json", body = RecoveryCodesSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The two-factor authentication is already enabled", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The two-factor authentication is not enrolled", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The code is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn verify_totp(
+    depot: &mut Depot,
+    totp_code: JsonBody<TotpCodeSchema>,
+) -> ApiResult<Json<RecoveryCodesSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+
+    Ok(Json(RecoveryCodesSchema {
+        recovery_codes: db_utils::enable_totp(
+            conn.as_ref(),
+            user,
+            &totp_code.into_inner().code,
+            &auth_config.password_hasher,
+        )
+        .await?,
+    }))
+}
+
+/// Disable the two-factor authentication.
+///
+/// This endpoint will disable the two-factor authentication and delete the recovery codes.
+/// It requires the user's password and the authenticator app code or a recovery code.
+#[endpoint(
+    operation_id = "disable_totp",
+    tags("Auth"),
+    request_body(
+        content = DisableTotpSchema,
+        description = "The password and the code",
+        example = json!(DisableTotpSchema::default()),
+        content_type = "application/json",
+    ),
+    responses(
+        (status_code = 200, description = "The two-factor authentication has been disabled", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid password: The password is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The two-factor authentication is not enabled", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The code is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn disable_totp(
+    depot: &mut Depot,
+    disable_totp_schema: JsonBody<DisableTotpSchema>,
+) -> ApiResult<Json<MessageSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+    let disable_totp_schema = disable_totp_schema.into_inner();
+
+    if !PasswordHasher::verify_async(&disable_totp_schema.password, &user.password_hash).await? {
+        return Err(ApiError::InvalidPassword(
+            "The password is incorrect".to_owned(),
+        ));
+    }
+    db_utils::verify_second_factor(conn.as_ref(), &user, &disable_totp_schema.code).await?;
+    db_utils::disable_totp(conn.as_ref(), user).await?;
+
+    Ok(Json(MessageSchema::new(
+        "The two-factor authentication has been disabled".to_owned(),
+    )))
+}
+
+/// Verify the user's email.
+///
+/// This endpoint is the link that sent to the user's email, it will mark the email as verified.
+/// - The token is valid for 24 hours by default.
+/// - Changing the email invalidates the tokens of the old email.
+#[endpoint(
+    operation_id = "verify_email",
+    tags("Auth"),
+    parameters(
+        ("token" = String, Query, description = "The email verification token, it's sent by mail"),
+    ),
+    responses(
+        (status_code = 200, description = "The email has been verified", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The email is already verified", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Invalid email verification token", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    )
+)]
+pub async fn verify_email(
+    depot: &mut Depot,
+    token: QueryParam<String, true>,
+) -> ApiResult<Json<MessageSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+
+    let claims = auth_config
+        .decode_token::<EmailVerificationClaims>(&token.into_inner())
+        .map_err(|_| ApiError::InvalidEmailVerificationToken)?
+        .claims;
+    if claims.exp <= chrono::Utc::now().timestamp() {
+        return Err(ApiError::InvalidEmailVerificationToken);
+    }
+    db_utils::verify_email(conn.as_ref(), claims.sub, &claims.email).await?;
+
+    Ok(Json(MessageSchema::new(
+        "The email has been verified".to_owned(),
+    )))
+}
+
+/// Set the recovery email.
+///
+/// This endpoint will set the email address that the password reset tokens are sent to, by the `/auth/forgot_password` endpoint.
+/// The recovery email is the user's email, so it's the same as the `/user/email` endpoint.
+/// - A verification link will be sent to the new email, only the verified email receives the password reset tokens.
+/// - A null email removes the recovery email.
+#[endpoint(
+    operation_id = "set_recovery_email",
+    tags("Auth"),
+    request_body(
+        content = RecoveryEmailSchema,
+        description = "The recovery email and the password",
+        example = json!(RecoveryEmailSchema::default()),
+        content_type = "application/json",
+    ),
+    responses(
+        (status_code = 200, description = "The recovery email has been set", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid password: The password is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid email", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Email already exists", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn set_recovery_email(
+    depot: &mut Depot,
+    recovery_email: JsonBody<RecoveryEmailSchema>,
+) -> ApiResult<Json<MessageSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let mailer = depot.obtain::<Arc<dyn Mailer>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+    let recovery_email = recovery_email.into_inner();
+
+    super::user::change_email(
+        conn.as_ref(),
+        auth_config,
+        mailer.as_ref(),
+        user,
+        recovery_email.email,
+        &recovery_email.password,
+    )
+    .await?;
+
+    Ok(Json(MessageSchema::new(
+        "The recovery email has been set".to_owned(),
+    )))
+}
+
+/// Regenerate the password recovery codes.
+///
+/// This endpoint will replace the password recovery codes with new ones, the old codes can't be used anymore.
+/// - The recovery codes can be exchanged with a password reset token in `/auth/recover_password`, each one only one time.
+/// - The recovery codes will not be shown again.
+#[endpoint(
+    operation_id = "regenerate_recovery_codes",
+    tags("Auth"),
+    request_body(
+        content = RegenerateRecoveryCodesSchema,
+        description = "The password",
+        example = json!(RegenerateRecoveryCodesSchema::default()),
+        content_type = "application/json",
+    ),
+    responses(
+        (status_code = 200, description = "The new password recovery codes", content_type = "application/json", body = RecoveryCodesSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid password: The password is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn regenerate_recovery_codes(
+    depot: &mut Depot,
+    regenerate_schema: JsonBody<RegenerateRecoveryCodesSchema>,
+) -> ApiResult<Json<RecoveryCodesSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+
+    if !PasswordHasher::verify_async(
+        &regenerate_schema.into_inner().password,
+        &user.password_hash,
+    )
+    .await?
+    {
+        return Err(ApiError::InvalidPassword(
+            "The password is incorrect".to_owned(),
+        ));
+    }
+
+    Ok(Json(RecoveryCodesSchema {
+        recovery_codes: db_utils::generate_password_recovery_codes(
+            conn.as_ref(),
+            user.uuid,
+            &auth_config.password_hasher,
+        )
+        .await?,
+    }))
+}
+
+/// Request a password reset.
+///
+/// This endpoint will send a password reset token to the user's verified email, send it with the new password
+/// to the `/auth/reset_password` endpoint. The response is the same whether the user has a verified email or not.
+/// - The reset token is valid for 30 minutes by default, and can only be used one time.
+/// - Requesting a new token will invalidate the old one.
+#[endpoint(
+    operation_id = "forgot_password",
+    tags("Auth"),
+    request_body(
+        content = ForgotPasswordSchema,
+        description = "The username",
+        example = json!(ForgotPasswordSchema::default()),
+        content_type = "application/json",
+    ),
+    parameters(
+        ("X-Captcha-Token" = String, Header, description = "The captcha token, get it from the `/auth/captcha` endpoint"),
+        ("X-Captcha-Answer" = String, Header, description = "The captcha answer, the text that in the captcha image"),
+    ),
+    responses(
+        (status_code = 200, description = "The reset token has been sent if the user has a verified email", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid username", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Unprovided captcha token or answer", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Invalid captcha token", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Invalid captcha answer", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    )
+)]
+pub async fn forgot_password(
+    depot: &mut Depot,
+    forgot_password_schema: JsonBody<ForgotPasswordSchema>,
+) -> ApiResult<Json<MessageSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let mailer = depot.obtain::<Arc<dyn Mailer>>().unwrap();
+    let username = forgot_password_schema.into_inner().username;
+
+    utils::handle_captcha_state(
+        depot
+            .get_captcha_state()
+            .expect("This route is protected by the `salvo_captcha` middleware"),
+    )?;
+    utils::validate_user_signin(&username)?;
+
+    if let Ok(user) = db_utils::get_user_by_username(conn.as_ref(), username).await {
+        let user = user.try_into_model()?;
+        if let Some(email) = user.email.filter(|_| user.email_verified) {
+            let (reset_token, expired_at) =
+                db_utils::create_password_reset_token(conn.as_ref(), user.uuid, auth_config)
+                    .await?;
+            mailer
+                .send(Mail::new(
+                    email,
+                    "Reset your XORS password".to_owned(),
+                    format!(
+                        "Hi {},\n\nA password reset has been requested for your XORS account `{}`. \
+                         Use the following token to reset your password, it expires at {expired_at} UTC:\n\n\
+                         {reset_token}\n\nIf you didn't request it, you can ignore this mail.",
+                        user.first_name, user.username
+                    ),
+                ))
+                .await?;
+        }
+    }
+
+    Ok(Json(MessageSchema::new(
+        "If the user has a verified email, a password reset token has been sent to it".to_owned(),
+    )))
+}
+
+/// Recover the password with a recovery code.
+///
+/// This endpoint will return a password reset token in exchange of a password recovery code, send it with
+/// the new password to the `/auth/reset_password` endpoint.
+/// - The recovery code can only be used one time.
+/// - The reset token is valid for 30 minutes by default, and can only be used one time.
+/// - The incorrect codes are counted as failed signin attempts, and can lock the account.
+#[endpoint(
+    operation_id = "recover_password",
+    tags("Auth"),
+    request_body(
+        content = RecoverPasswordSchema,
+        description = "The username and the recovery code",
+        example = json!(RecoverPasswordSchema::default()),
+        content_type = "application/json",
+    ),
+    parameters(
+        ("X-Captcha-Token" = String, Header, description = "The captcha token, get it from the `/auth/captcha` endpoint"),
+        ("X-Captcha-Answer" = String, Header, description = "The captcha answer, the text that in the captcha image"),
+    ),
+    responses(
+        (status_code = 200, description = "The password reset token", content_type = "application/json", body = PasswordResetTokenSchema),
+        (status_code = 400, description = "Invalid username", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Unprovided captcha token or answer", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Invalid captcha token", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Invalid captcha answer", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The username or recovery code is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is locked, retry after the `Retry-After` header seconds", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    )
+)]
+pub async fn recover_password(
+    req: &mut Request,
+    depot: &mut Depot,
+    recover_password_schema: JsonBody<RecoverPasswordSchema>,
+) -> ApiResult<Json<PasswordResetTokenSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let recover_password_schema = recover_password_schema.into_inner();
+
+    utils::handle_captcha_state(
+        depot
+            .get_captcha_state()
+            .expect("This route is protected by the `salvo_captcha` middleware"),
+    )?;
+    utils::validate_user_signin(&recover_password_schema.username)?;
+    db_utils::check_account_lock(
+        conn.as_ref(),
+        auth_config,
+        &recover_password_schema.username,
+    )
+    .await?;
+
+    match db_utils::recover_password(
+        conn.as_ref(),
+        recover_password_schema.username.clone(),
+        &recover_password_schema.recovery_code,
+        auth_config,
+    )
+    .await
+    {
+        Ok((reset_token, expired_at)) => Ok(Json(PasswordResetTokenSchema {
+            reset_token,
+            expired_at,
+        })),
+        Err(ApiError::InvalidRecoveryCredentials) => {
+            db_utils::record_failed_signin(
+                conn.as_ref(),
+                recover_password_schema.username,
+                req.new_session(),
+            )
+            .await?;
+            Err(ApiError::InvalidRecoveryCredentials)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Reset the password with a password reset token.
+///
+/// This endpoint will reset the user's password, the reset token is sent to the recovery email by `/auth/forgot_password`
+/// or returned by `/auth/recover_password`. The user will be logged out from all the devices.
+#[endpoint(
+    operation_id = "complete_password_reset",
+    tags("Auth"),
+    request_body(
+        content = CompletePasswordResetSchema,
+        description = "The reset token and the new password",
+        example = json!(CompletePasswordResetSchema::default()),
+        content_type = "application/json",
+    ),
+    responses(
+        (status_code = 200, description = "The password has been reset", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid password", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The new password is same as the old password", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The reset token is invalid, expired or already used", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    )
+)]
+pub async fn complete_password_reset(
+    depot: &mut Depot,
+    password_reset: JsonBody<CompletePasswordResetSchema>,
+) -> ApiResult<Json<MessageSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let password_reset = password_reset.into_inner();
+
+    utils::validate_password(&password_reset.new_password)?;
+    db_utils::reset_password_with_token(
+        conn.as_ref(),
+        &password_reset.reset_token,
+        &password_reset.new_password,
+        &auth_config.password_hasher,
+    )
+    .await?;
+
+    Ok(Json(MessageSchema::new(
+        "The user's password has been reset".to_owned(),
+    )))
+}
+
+/// Logout from the current session.
+///
+/// This endpoint will revoke the JWT token and the refresh tokens of the current session.
+#[endpoint(
+    operation_id = "logout",
+    tags("Auth"),
+    responses(
+        (status_code = 200, description = "Logged out", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn logout(depot: &mut Depot) -> ApiResult<Json<MessageSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let user = depot.user_including_deleted(conn.as_ref()).await?;
+
+    let session = depot
+        .jwt_claims()
+        .session
+        .expect("The user's token has a session, it's checked by `UserExt::user`");
+    db_utils::revoke_session(conn.as_ref(), user.uuid, session).await?;
+
+    Ok(Json(MessageSchema::new("Logged out".to_owned())))
+}
+
+/// Logout from all the devices.
+///
+/// This endpoint will revoke all the user's JWT tokens and refresh tokens, including the current one.
+#[endpoint(
+    operation_id = "logout_all",
+    tags("Auth"),
+    responses(
+        (status_code = 200, description = "Logged out from all the devices", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn logout_all(depot: &mut Depot) -> ApiResult<Json<MessageSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let user = depot.user_including_deleted(conn.as_ref()).await?;
+
+    db_utils::logout_all(conn.as_ref(), user).await?;
+
+    Ok(Json(MessageSchema::new(
+        "Logged out from all the devices".to_owned(),
+    )))
+}
+
+/// Get the user's sessions.
+///
+/// This endpoint will return the active sessions of the user, newest first. Every signin creates a new session.
+#[endpoint(
+    operation_id = "get_sessions",
+    tags("Auth"),
+    responses(
+        (status_code = 200, description = "The user's sessions", content_type = "application/json", body = Vec<SessionSchema>),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn get_sessions(depot: &mut Depot) -> ApiResult<Json<Vec<SessionSchema>>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+    let current_session = depot.jwt_claims().session;
+
+    Ok(Json(
+        db_utils::get_user_sessions(conn.as_ref(), user.uuid)
+            .await?
+            .into_iter()
+            .map(|(session, expires_at)| {
+                SessionSchema::from_session(session, expires_at, current_session)
+            })
+            .collect(),
+    ))
+}
+
+/// Revoke a session.
+///
+/// This endpoint will revoke the session's JWT tokens and refresh tokens. Revoking the current session is like logging out.
+#[endpoint(
+    operation_id = "revoke_session",
+    tags("Auth"),
+    parameters(
+        ("id" = Uuid, Path, description = "The session's id"),
+    ),
+    responses(
+        (status_code = 200, description = "The session has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The id is invalid", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "Session not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn revoke_session(
+    depot: &mut Depot,
+    id: PathParam<Uuid>,
+) -> ApiResult<Json<MessageSchema>> {
+    let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+
+    db_utils::revoke_session(conn.as_ref(), user.uuid, id.into_inner()).await?;
+
+    Ok(Json(MessageSchema::new(
+        "The session has been revoked".to_owned(),
+    )))
+}
+
 /// Refresh a JWT token.
 ///
-/// This endpoint will return a new JWT token with the refresh token.
+/// This endpoint will return a new JWT token with a new refresh token.
 /// Note: You need to authorize with the refresh token to get a new JWT token.
+/// - The refresh token can only be used one time, the new refresh token must be used next time.
+/// - Using an already used refresh token will revoke all the refresh tokens that came from the same signin.
 #[endpoint(
     operation_id = "refresh_token",
     tags("Auth"),
@@ -211,6 +1208,8 @@ pub async fn signin(
         (status_code = 400, description = "The token is not a refresh token", content_type = "application/json", body = MessageSchema),
         (status_code = 403, description = "The refresh token is not active yet", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The refresh token has been revoked", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The refresh token has already been used", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
         (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
         (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
@@ -220,23 +1219,25 @@ pub async fn signin(
 )]
 pub async fn refresh(depot: &mut Depot) -> ApiResult<Json<UserSigninSchema>> {
     let conn = depot.obtain::<Arc<sea_orm::DatabaseConnection>>().unwrap();
-    let secret_key = depot.get::<Arc<String>>("secret_key").unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
 
     // Note: The `Unauthorized` and `Forbidden` errors are handled by the `JwtAuth` middleware.
     let refresh_token = depot.jwt_claims();
     if let Some(active_after) = refresh_token.active_after {
         if !refresh_token.is_expired() {
             if active_after <= chrono::Utc::now().timestamp() {
-                db_utils::signin_user(
-                    UserSchema::from_active_model(
-                        conn,
-                        db_utils::get_user(conn.as_ref(), refresh_token.uuid).await?,
-                    )
-                    .await?,
-                    secret_key,
+                // Refresh tokens without `jti` are issued before the rotation, so they can't be trusted.
+                let jti = refresh_token.jti.ok_or(ApiError::RevokedRefreshToken)?;
+                let user = UserSchema::owner_from_active_model(
+                    conn,
+                    db_utils::get_user(conn.as_ref(), refresh_token.uuid).await?,
                 )
-                .await
-                .map(Json)
+                .await?;
+                let family = db_utils::rotate_refresh_token(conn.as_ref(), user.uuid, jti).await?;
+
+                db_utils::issue_tokens(conn.as_ref(), user, auth_config, family)
+                    .await
+                    .map(Json)
             } else {
                 Err(ApiError::UnActiveRefreshToken)
             }
@@ -21,14 +21,14 @@ use std::time::Duration;
 
 use salvo::http::ResBody;
 use salvo::hyper::header::HeaderName;
-use salvo::jwt_auth::{ConstDecoder, HeaderFinder};
+use salvo::jwt_auth::HeaderFinder;
 use salvo::oapi::security::{Http, HttpAuthScheme};
 use salvo::oapi::{Info, License, SecurityScheme};
 use salvo::rate_limiter::*;
 use salvo::{catcher::Catcher, http::HeaderValue, hyper::header, logging::Logger, prelude::*};
 use salvo_captcha::*;
 
-use crate::schemas::MessageSchema;
+use crate::{config::AuthConfig, mailer::Mailer, schemas::MessageSchema};
 
 pub mod exts;
 pub mod game;
@@ -72,7 +72,7 @@ async fn handle_server_errors(res: &mut Response, ctrl: &mut FlowCtrl) {
                         .map_or_else(|| "".to_owned(), ToString::to_string)
                         .trim_end_matches('.')
                         .split(':')
-                        .last()
+                        .next_back()
                         .unwrap_or_default()
                         .trim()
                 )),
@@ -101,14 +101,20 @@ pub fn service(
     conn: sea_orm::DatabaseConnection,
     max_online_games: usize,
     move_period: i64,
-    secret_key: String,
+    auth_config: AuthConfig,
+    mailer: Arc<dyn Mailer>,
 ) -> (Service, OpenApi) {
-    let auth_handler: JwtAuth<jwt::JwtClaims, _> =
-        JwtAuth::new(ConstDecoder::from_secret(secret_key.as_bytes()))
-            .finders(vec![Box::new(
-                HeaderFinder::new().header_names(vec![header::AUTHORIZATION]),
-            )])
-            .force_passed(false);
+    let auth_handler: JwtAuth<jwt::JwtClaims, _> = JwtAuth::new(auth_config.clone())
+        .finders(vec![Box::new(
+            HeaderFinder::new().header_names(vec![header::AUTHORIZATION]),
+        )])
+        .force_passed(false);
+    // The public routes show more to the requester if he is authorized
+    let optional_auth_handler: JwtAuth<jwt::JwtClaims, _> = JwtAuth::new(auth_config.clone())
+        .finders(vec![Box::new(
+            HeaderFinder::new().header_names(vec![header::AUTHORIZATION]),
+        )])
+        .force_passed(true);
     let captcha_middleware = Captcha::<CacacheStorage, CaptchaHeaderFinder<String, String>>::new(
         CacacheStorage::new("chapcha_cache"),
         CaptchaHeaderFinder::new()
@@ -133,6 +139,14 @@ pub fn service(
         CelledQuota::per_minute(30, 1),
     )
     .add_headers(true);
+    // The users search is heavier than the other public routes
+    let search_limiter = RateLimiter::new(
+        SlidingGuard::new(),
+        MokaStore::<String, SlidingGuard>::new(),
+        RemoteIpIssuer,
+        CelledQuota::per_minute(15, 1),
+    )
+    .add_headers(true);
     let auth_limiter = RateLimiter::new(
         FixedGuard::new(),
         MokaStore::new(),
@@ -146,7 +160,8 @@ pub fn service(
         .hoop(
             affix::inject(Arc::new(conn))
                 .inject(captcha_storage.clone())
-                .insert("secret_key", Arc::new(secret_key))
+                .inject(Arc::new(auth_config))
+                .inject(mailer)
                 .insert("max_online_games", Arc::new(max_online_games))
                 .insert("move_period", Arc::new(move_period)),
         )
@@ -154,20 +169,55 @@ pub fn service(
         .push(
             Router::new()
                 .hoop(unauth_limiter)
+                .hoop(optional_auth_handler)
                 .hoop(add_server_headers)
                 .push(
                     Router::with_path("auth")
                         .push(
                             Router::with_hoop(captcha_middleware)
                                 .push(Router::with_path("signup").post(jwt::signup))
-                                .push(Router::with_path("signin").post(jwt::signin)),
+                                .push(Router::with_path("signin").post(jwt::signin))
+                                .push(
+                                    Router::with_path("forgot_password").post(jwt::forgot_password),
+                                )
+                                .push(
+                                    Router::with_path("recover_password")
+                                        .post(jwt::recover_password),
+                                ),
+                        )
+                        .push(
+                            Router::with_path("reset_password").post(jwt::complete_password_reset),
                         )
+                        .push(Router::with_path("verify_email").get(jwt::verify_email))
+                        .push(
+                            Router::with_path("oidc/<provider>")
+                                .get(jwt::oidc_authorize)
+                                .push(Router::with_path("callback").get(jwt::oidc_callback)),
+                        )
+                        .push(Router::with_path("signin/totp").post(jwt::signin_totp))
                         .push(Router::with_path("captcha").get(jwt::captcha)),
                 )
-                .push(Router::with_path("user").get(user::get_user_info))
+                .push(
+                    Router::with_path("user")
+                        .get(user::get_user_info)
+                        .push(Router::with_path("<uuid>/stats").get(user::get_user_stats))
+                        .push(
+                            Router::with_path("<uuid>/versus/<other_uuid>")
+                                .get(user::get_user_versus),
+                        ),
+                )
+                .push(
+                    Router::with_path("users/search")
+                        .hoop(search_limiter)
+                        .get(user::search_users),
+                )
+                .push(
+                    Router::with_path("profiles/<name>.png").get(user::get_user_profile_image_png),
+                )
                 .push(Router::with_path("profiles/<uuid>").get(user::get_user_profile_image))
                 .push(Router::with_path("game/<uuid>").get(game::get_game_by_uuid))
-                .push(Router::with_path("games").get(game::get_lastest_games)),
+                .push(Router::with_path("games").get(game::get_games))
+                .push(Router::with_path(".well-known/jwks.json").get(jwt::jwks)),
         )
         // Authorized routes
         .push(
@@ -176,13 +226,50 @@ pub fn service(
                 .hoop(auth_handler)
                 .hoop(add_server_headers)
                 .push(
-                    Router::with_path("auth").push(Router::with_path("refresh").get(jwt::refresh)),
+                    Router::with_path("auth")
+                        .push(Router::with_path("refresh").get(jwt::refresh))
+                        .push(Router::with_path("logout").post(jwt::logout))
+                        .push(Router::with_path("logout_all").post(jwt::logout_all))
+                        .push(
+                            Router::with_path("totp")
+                                .push(Router::with_path("enroll").post(jwt::enroll_totp))
+                                .push(Router::with_path("verify").post(jwt::verify_totp))
+                                .push(Router::with_path("disable").post(jwt::disable_totp)),
+                        )
+                        .push(
+                            Router::with_path("recovery")
+                                .push(Router::with_path("email").put(jwt::set_recovery_email))
+                                .push(
+                                    Router::with_path("codes").post(jwt::regenerate_recovery_codes),
+                                ),
+                        )
+                        .push(
+                            Router::with_path("sessions")
+                                .get(jwt::get_sessions)
+                                .push(Router::with_path("<id>").delete(jwt::revoke_session)),
+                        ),
                 )
                 .push(
                     Router::with_path("user")
                         .put(user::update_user)
                         .delete(user::delete_user)
                         .push(Router::with_path("reset_password").post(user::reset_user_password))
+                        .push(Router::with_path("email").put(user::update_email).push(
+                            Router::with_path("verify").post(user::resend_email_verification),
+                        ))
+                        .push(Router::with_path("username").put(user::update_username))
+                        .push(
+                            Router::with_path("profile_image")
+                                .put(user::update_profile_image)
+                                .delete(user::delete_profile_image),
+                        )
+                        .push(Router::with_path("restore").post(user::restore_user))
+                        .push(Router::with_path("export").get(user::export_user_data))
+                        .push(
+                            Router::with_path("privacy")
+                                .get(user::get_privacy_settings)
+                                .put(user::update_privacy_settings),
+                        )
                         .push(Router::with_path("me").get(user::get_me)),
                 )
                 .push(Router::with_path("xo").goal(xo::user_connected)),
@@ -15,14 +15,20 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::api::exts::*;
-use crate::{db_utils, utils};
 use crate::{
+    config::AuthConfig,
     errors::{ApiError, ApiResult},
+    mailer::Mailer,
+    password::PasswordHasher,
     schemas::*,
 };
+use crate::{db_utils, utils};
 
 use base64::Engine;
 use entity::prelude::*;
+use futures_util::StreamExt;
+use salvo::fs::NamedFile;
+use salvo::http::{body::BodySender, header, mime, HeaderValue};
 use salvo::oapi::extract::{JsonBody, PathParam, QueryParam};
 use salvo::prelude::*;
 use salvo::{oapi::endpoint, writing::Json};
@@ -32,6 +38,9 @@ use uuid::Uuid;
 use std::path::Path;
 use std::sync::Arc;
 
+/// The number of the games that written to the data export at once.
+const EXPORT_GAMES_PAGE_SIZE: u64 = 100;
+
 /// Get me, the user that make the request.
 ///
 /// This endpoint will return the user that make the request.
@@ -43,6 +52,7 @@ use std::sync::Arc;
         (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
         (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
         (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
         (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
@@ -53,12 +63,15 @@ pub async fn get_me(depot: &mut Depot) -> ApiResult<Json<UserSchema>> {
     let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
     let user = depot.user(conn.as_ref()).await?;
 
-    UserSchema::from_active_model(conn, user.into_active_model())
+    UserSchema::owner_from_active_model(conn, user.into_active_model())
         .await
         .map(Json)
 }
 
 /// Get the user's info.
+///
+/// The user's privacy settings are applied, except if the requester is the user himself.
+/// Send the JWT in the `Authorization` header to be identified as the requester.
 #[endpoint(
     operation_id = "get_user_info",
     tags("User"),
@@ -67,6 +80,7 @@ pub async fn get_me(depot: &mut Depot) -> ApiResult<Json<UserSchema>> {
     ),
     responses(
         (status_code = 200, description = "The user's info", content_type = "application/json", body = UserSchema),
+        (status_code = 403, description = "The user's profile is private", content_type = "application/json", body = MessageSchema),
         (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
         (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
         (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
@@ -77,16 +91,191 @@ pub async fn get_user_info(
     uuid: QueryParam<Uuid, true>,
 ) -> ApiResult<Json<UserSchema>> {
     let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let requester = depot.requester(conn).await?;
     let requested_user_uuid = uuid.into_inner();
 
-    UserSchema::from_active_model(conn, db_utils::get_user(conn, requested_user_uuid).await?)
-        .await
-        .map(Json)
+    UserSchema::visible_from_active_model(
+        conn,
+        db_utils::get_active_user(conn, requested_user_uuid).await?,
+        requester,
+    )
+    .await
+    .map(Json)
+}
+
+/// Search the users.
+///
+/// This endpoint will return a page of the users that match the query, by their username or first and last name.
+/// - The username prefix matches come first, then the name prefix matches, then the fuzzy matches.
+/// - To get the next page, send the `next_cursor` of the current page as the `cursor`, with the same query.
+/// - The deleted and private users are excluded, and the users that hide their real name are matched by their username only.
+///   Send the JWT in the `Authorization` header to be identified as the requester.
+/// - There is no users blocking yet, so the blocked users are not excluded.
+#[endpoint(
+    operation_id = "search_users",
+    tags("User"),
+    responses(
+        (status_code = 200, description = "The users page", content_type = "application/json", body = UsersPageSchema),
+        (status_code = 400, description = "Invalid users search", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid cursor", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+)]
+pub async fn search_users(
+    depot: &mut Depot,
+    search: UsersSearchSchema,
+) -> ApiResult<Json<UsersPageSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap().as_ref();
+    let requester = depot.requester(conn).await?;
+
+    utils::validate_users_search(&search)?;
+    let limit = search.limit.unwrap_or(10);
+    let cursor = search.cursor.as_deref().map(str::parse).transpose()?;
+
+    let mut users = db_utils::search_users(conn, &search.q, cursor, limit + 1, requester).await?;
+    let next_cursor = if users.len() as u64 > limit {
+        users.truncate(limit as usize);
+        users
+            .last()
+            .map(|(rank, user, _)| UsersCursor::new(*rank, &user.username).to_string())
+    } else {
+        None
+    };
+
+    Ok(Json(UsersPageSchema {
+        users: futures_util::stream::iter(users)
+            .then(|(_, user, settings)| {
+                UserSchema::with_privacy_settings(conn, user.into_active_model(), settings)
+            })
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<ApiResult<_>>()?,
+        next_cursor,
+    }))
+}
+
+/// Get the head-to-head statistics between two users.
+///
+/// This endpoint will return the games and rounds results of the user against the other user, from the user side.
+/// Also the last shared games between them, newest first.
+#[endpoint(
+    operation_id = "get_user_versus",
+    tags("User"),
+    parameters(
+        ("uuid" = Uuid, Path, description = "The user's uuid"),
+        ("other_uuid" = Uuid, Path, description = "The other user's uuid"),
+        ("last" = Option<u64>, Query, description = "The number of the last shared games, between 1 and 50. Default is 10"),
+    ),
+    responses(
+        (status_code = 200, description = "The head-to-head statistics", content_type = "application/json", body = VersusSchema),
+        (status_code = 400, description = "The two users are the same", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid games count", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "One of the users hides his game history", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+)]
+pub async fn get_user_versus(
+    depot: &mut Depot,
+    uuid: PathParam<Uuid>,
+    other_uuid: PathParam<Uuid>,
+    last: QueryParam<u64, false>,
+) -> ApiResult<Json<VersusSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap().as_ref();
+    let (user_uuid, other_uuid) = (uuid.into_inner(), other_uuid.into_inner());
+    let last = last.into_inner().unwrap_or(10);
+
+    if user_uuid == other_uuid {
+        return Err(ApiError::SameVersusUsers);
+    }
+    if !(1..=50).contains(&last) {
+        return Err(ApiError::InvalidGamesFilter(
+            "The `last` must be between 1 and 50".to_owned(),
+        ));
+    }
+    let requester = depot.requester(conn).await?;
+    for uuid in [user_uuid, other_uuid] {
+        db_utils::get_active_user(conn, uuid).await?;
+        db_utils::check_game_history_visibility(conn, uuid, requester).await?;
+    }
+
+    let shared_games = db_utils::get_shared_games(conn, user_uuid, other_uuid).await?;
+    let mut versus = VersusSchema {
+        user: user_uuid,
+        other_user: other_uuid,
+        games: ResultsSchema::default(),
+        rounds: ResultsSchema::default(),
+        latest_games: shared_games
+            .iter()
+            .take(last as usize)
+            .map(|game| game.uuid)
+            .collect(),
+    };
+
+    for game in &shared_games {
+        match game.winner {
+            Some(winner) if winner == user_uuid => versus.games.wins += 1,
+            Some(_) => versus.games.losses += 1,
+            None => versus.games.draws += 1,
+        }
+
+        let (symbol, other_symbol) = if game.x_player == user_uuid {
+            (XoSymbol::X, XoSymbol::O)
+        } else {
+            (XoSymbol::O, XoSymbol::X)
+        };
+        let rounds_result = game.parse_rounds_result()?;
+        versus.rounds.wins += rounds_result.wins(&symbol) as u64;
+        versus.rounds.losses += rounds_result.wins(&other_symbol) as u64;
+        versus.rounds.draws += rounds_result.draws as u64;
+    }
+
+    Ok(Json(versus))
+}
+
+/// Get the user's statistics.
+///
+/// This endpoint will return the user's detailed statistics, computed from his ended games.
+#[endpoint(
+    operation_id = "get_user_stats",
+    tags("User"),
+    parameters(
+        ("uuid" = Uuid, Path, description = "The requested user's uuid"),
+    ),
+    responses(
+        (status_code = 200, description = "The user's statistics", content_type = "application/json", body = UserStatsSchema),
+        (status_code = 400, description = "The uuid is invalid", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The user hides his game history", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+)]
+pub async fn get_user_stats(
+    depot: &mut Depot,
+    uuid: PathParam<Uuid>,
+) -> ApiResult<Json<UserStatsSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap().as_ref();
+    let user_uuid = uuid.into_inner();
+
+    db_utils::get_active_user(conn, user_uuid).await?;
+    db_utils::check_game_history_visibility(conn, user_uuid, depot.requester(conn).await?).await?;
+    UserStatsSchema::from_games(
+        user_uuid,
+        &db_utils::get_player_games(conn, user_uuid).await?,
+    )
+    .map(Json)
 }
 
 /// Delete the user's account.
 ///
-/// This endpoint will delete the user's account and all the user's data. Forever.
+/// This endpoint will delete the user's account and logout him from all the devices.
+/// - The account is hidden from the other users, and can be restored by signing in and using `/user/restore`.
+/// - After the grace period, all the user's data are purged forever, the account is anonymized and its username is released.
+/// - The purged user's games show a placeholder name like `deleted-<uuid-prefix>`, as a deleted player.
 #[endpoint(
     operation_id = "delete_user_info",
     tags("User"),
@@ -94,8 +283,10 @@ pub async fn get_user_info(
         (status_code = 200, description = "The user's account has been deleted", content_type = "application/json", body = MessageSchema),
         (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
         (status_code = 400, description = "Invalid password: The password is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The account is already deleted", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
         (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
         (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
         (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
@@ -107,19 +298,21 @@ pub async fn delete_user(
     delete_user_schema: JsonBody<DeleteUserSchema>,
 ) -> ApiResult<Json<MessageSchema>> {
     let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
     let user = depot.user(conn.as_ref()).await?;
 
-    if bcrypt::verify(
-        delete_user_schema.into_inner().password,
-        user.password_hash.as_ref(),
-    )? {
-        UserEntity::delete(user.into_active_model())
-            .exec(conn.as_ref())
-            .await?;
+    if PasswordHasher::verify_async(
+        &delete_user_schema.into_inner().password,
+        &user.password_hash,
+    )
+    .await?
+    {
+        db_utils::delete_user(conn.as_ref(), user).await?;
 
-        Ok(Json(MessageSchema::new(
-            "The user's account has been deleted".to_owned(),
-        )))
+        Ok(Json(MessageSchema::new(format!(
+            "The user's account has been deleted, it can be restored within {} days",
+            auth_config.account_deletion_grace_period.num_days()
+        ))))
     } else {
         Err(ApiError::InvalidPassword(
             "The password is incorrect".to_owned(),
@@ -127,29 +320,69 @@ pub async fn delete_user(
     }
 }
 
+/// Restore the user's deleted account.
+///
+/// This endpoint will cancel the deletion of the user's account, before the grace period ends.
+#[endpoint(
+    operation_id = "restore_user",
+    tags("User"),
+    responses(
+        (status_code = 200, description = "The user's account has been restored", content_type = "application/json", body = UserSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The account is not deleted", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn restore_user(depot: &mut Depot) -> ApiResult<Json<UserSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let user = depot.user_including_deleted(conn.as_ref()).await?;
+
+    UserSchema::owner_from_active_model(
+        conn,
+        db_utils::restore_user(conn.as_ref(), user.uuid).await?,
+    )
+    .await
+    .map(Json)
+}
+
 /// Update the user's info.
+///
+/// The request body can be up to 6.7MB, so the base64 encoded profile image fits in it.
 #[endpoint(
     operation_id = "update_user_info",
     tags("User"),
+    request_body(
+        content = UpdateUserSchema,
+        description = "The user's new info",
+        example = json!(UpdateUserSchema::default()),
+        content_type = "application/json",
+    ),
     responses(
         (status_code = 200, description = "The user's info has been updated", content_type = "application/json", body = UserSchema),
         (status_code = 400, description = "There is no changes", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid request body", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid profile image", content_type = "application/json", body = MessageSchema),
         (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
         (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 413, description = "The request body is too large", content_type = "application/json", body = MessageSchema),
         (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
         (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
     ),
     security(("bearerAuth" = [])),
 )]
-pub async fn update_user(
-    depot: &mut Depot,
-    updated_user: JsonBody<UpdateUserSchema>,
-) -> ApiResult<Json<UserSchema>> {
+pub async fn update_user(req: &mut Request, depot: &mut Depot) -> ApiResult<Json<UserSchema>> {
     let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
     let user = depot.user(conn.as_ref()).await?;
-    let updated_user = updated_user.into_inner();
+    let updated_user: UpdateUserSchema =
+        utils::parse_json_with_max_size(req, utils::MAX_UPDATE_USER_BODY_BYTES).await?;
 
     let mut user = user.into_active_model();
 
@@ -171,14 +404,106 @@ pub async fn update_user(
         user.last_name = Set(updated_user.last_name);
     }
 
-    let profile_image_path =
-        db_utils::update_profile_image_path(*user.uuid.as_ref(), updated_user.profile_image)?;
-    user.profile_image_path = Set(profile_image_path);
+    if let Some(profile_image) = updated_user.profile_image {
+        let user_uuid = *user.uuid.as_ref();
+        // Decoding the image is CPU heavy, so it's done in a blocking thread
+        user.profile_image_path = Set(tokio::task::spawn_blocking(move || {
+            let image_bytes = crate::BASE_64_ENGINE
+                .decode(profile_image)
+                .map_err(|_| ApiError::InvalidProfileImage("Invalid base64 string".to_owned()))?;
+            db_utils::save_profile_image(user_uuid, &image_bytes)
+        })
+        .await
+        .map_err(|_| ApiError::InternalServer)??);
+    }
     let user = user.save(conn.as_ref()).await?;
-    UserSchema::from_active_model(conn, user).await.map(Json)
+    UserSchema::owner_from_active_model(conn, user)
+        .await
+        .map(Json)
+}
+
+/// Update the user's profile image.
+///
+/// The image is sent as `multipart/form-data` in the `image` field, it can be png, jpeg or webp,
+/// less than 5MB in size and at most 4096x4096 pixels. It will be center-cropped and resized to 128x128 pixels.
+#[endpoint(
+    operation_id = "update_profile_image",
+    tags("User"),
+    request_body(
+        content = ProfileImageUploadSchema,
+        description = "The new profile image",
+        content_type = "multipart/form-data",
+    ),
+    responses(
+        (status_code = 200, description = "The user's profile image has been updated", content_type = "application/json", body = UserSchema),
+        (status_code = 400, description = "Invalid request body", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid profile image", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 413, description = "The request body is too large", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn update_profile_image(
+    req: &mut Request,
+    depot: &mut Depot,
+) -> ApiResult<Json<UserSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+
+    let image_bytes =
+        utils::read_multipart_file(req, "image", utils::MAX_PROFILE_IMAGE_BYTES).await?;
+
+    let mut user = user.into_active_model();
+    let user_uuid = *user.uuid.as_ref();
+    // Normalizing the image is CPU heavy, so it's done in a blocking thread
+    user.profile_image_path = Set(tokio::task::spawn_blocking(move || {
+        db_utils::save_profile_image(user_uuid, &image_bytes)
+    })
+    .await
+    .map_err(|_| ApiError::InternalServer)??);
+    let user = user.save(conn.as_ref()).await?;
+    UserSchema::owner_from_active_model(conn, user)
+        .await
+        .map(Json)
+}
+
+/// Delete the user's profile image, the default profile image will be used instead.
+#[endpoint(
+    operation_id = "delete_profile_image",
+    tags("User"),
+    responses(
+        (status_code = 200, description = "The user's profile image has been deleted", content_type = "application/json", body = UserSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn delete_profile_image(depot: &mut Depot) -> ApiResult<Json<UserSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+
+    let mut user = user.into_active_model();
+    user.profile_image_path = Set(db_utils::delete_profile_image(*user.uuid.as_ref())?);
+    let user = user.save(conn.as_ref()).await?;
+    UserSchema::owner_from_active_model(conn, user)
+        .await
+        .map(Json)
 }
 
 /// Reset the user's password.
+///
+/// This endpoint will logout the user from all the devices, including the current one.
 #[endpoint(
     operation_id = "reset_user_password",
     tags("User"),
@@ -190,6 +515,7 @@ pub async fn update_user(
         (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
         (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
         (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
         (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
@@ -201,16 +527,23 @@ pub async fn reset_user_password(
     reset_password: JsonBody<ResetPasswordSchema>,
 ) -> ApiResult<Json<MessageSchema>> {
     let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
     let user = depot.user(conn.as_ref()).await?;
     let reset_password = reset_password.into_inner();
 
     utils::validate_password(&reset_password.new_password)?;
-    if bcrypt::verify(&reset_password.new_password, user.password_hash.as_ref())? {
+    if PasswordHasher::verify_async(&reset_password.new_password, &user.password_hash).await? {
         return Err(ApiError::PasswordNotChanged);
     }
 
-    if bcrypt::verify(&reset_password.old_password, user.password_hash.as_ref())? {
-        db_utils::reset_password(conn.as_ref(), user, &reset_password.new_password).await?;
+    if PasswordHasher::verify_async(&reset_password.old_password, &user.password_hash).await? {
+        db_utils::reset_password(
+            conn.as_ref(),
+            user,
+            &reset_password.new_password,
+            &auth_config.password_hasher,
+        )
+        .await?;
         Ok(Json(MessageSchema::new(
             "The user's password has been reset".to_owned(),
         )))
@@ -221,7 +554,427 @@ pub async fn reset_user_password(
     }
 }
 
+/// Changes the user's email after checking his password, and sends the verification link to the new email.
+/// Used by the `/user/email` and the `/auth/recovery/email` endpoints. Returns the updated user.
+pub(crate) async fn change_email(
+    conn: &DatabaseConnection,
+    auth_config: &AuthConfig,
+    mailer: &dyn Mailer,
+    user: UserModel,
+    email: Option<String>,
+    password: &str,
+) -> ApiResult<UserModel> {
+    if !PasswordHasher::verify_async(password, &user.password_hash).await? {
+        return Err(ApiError::InvalidPassword(
+            "The password is incorrect".to_owned(),
+        ));
+    }
+    email.as_deref().map(utils::validate_email).transpose()?;
+
+    let user = db_utils::update_email(conn, user, email).await?;
+    if let Some(email) = user.email.clone().filter(|_| !user.email_verified) {
+        super::jwt::send_email_verification(
+            mailer,
+            auth_config,
+            user.uuid,
+            &user.first_name,
+            &user.username,
+            email,
+        )
+        .await?;
+    }
+    Ok(user)
+}
+
+/// Update the user's email.
+///
+/// This endpoint will set the user's email, a verification link will be sent to the new email.
+/// - The email must be unique, regardless of its case.
+/// - A changed email is unverified until the link is opened, only the verified email receives the password reset tokens.
+/// - A null email removes the email.
+#[endpoint(
+    operation_id = "update_user_email",
+    tags("User"),
+    request_body(
+        content = UpdateEmailSchema,
+        description = "The new email and the password",
+        example = json!(UpdateEmailSchema::default()),
+        content_type = "application/json",
+    ),
+    responses(
+        (status_code = 200, description = "The user's email has been updated", content_type = "application/json", body = UserSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid password: The password is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid email", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Email already exists", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn update_email(
+    depot: &mut Depot,
+    update_email_schema: JsonBody<UpdateEmailSchema>,
+) -> ApiResult<Json<UserSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let mailer = depot.obtain::<Arc<dyn Mailer>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+    let update_email_schema = update_email_schema.into_inner();
+
+    let user = change_email(
+        conn.as_ref(),
+        auth_config,
+        mailer.as_ref(),
+        user,
+        update_email_schema.email,
+        &update_email_schema.password,
+    )
+    .await?;
+
+    UserSchema::owner_from_active_model(conn, user.into_active_model())
+        .await
+        .map(Json)
+}
+
+/// Update the user's username.
+///
+/// This endpoint will change the user's username, the old username stays reserved for him for a period.
+/// - The username can be changed once per cooldown period.
+/// - The recently used usernames of the other users can't be taken until their reservation ends.
+#[endpoint(
+    operation_id = "update_user_username",
+    tags("User"),
+    request_body(
+        content = UpdateUsernameSchema,
+        description = "The new username and the password",
+        example = json!(UpdateUsernameSchema::default()),
+        content_type = "application/json",
+    ),
+    responses(
+        (status_code = 200, description = "The user's username has been updated", content_type = "application/json", body = UserSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid password: The password is incorrect", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Invalid username", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The new username is the same as the current one", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Username already exists", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "Username is reserved", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The username was changed recently, the `Retry-After` header has the remaining seconds", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn update_username(
+    depot: &mut Depot,
+    update_username_schema: JsonBody<UpdateUsernameSchema>,
+) -> ApiResult<Json<UserSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+    let update_username_schema = update_username_schema.into_inner();
+
+    if !PasswordHasher::verify_async(&update_username_schema.password, &user.password_hash).await? {
+        return Err(ApiError::InvalidPassword(
+            "The password is incorrect".to_owned(),
+        ));
+    }
+    utils::validate_user_signin(&update_username_schema.username)?;
+
+    let user = db_utils::change_username(
+        conn.as_ref(),
+        user,
+        update_username_schema.username,
+        auth_config,
+    )
+    .await?;
+    UserSchema::owner_from_active_model(conn, user)
+        .await
+        .map(Json)
+}
+
+/// Resend the email verification link.
+///
+/// This endpoint will send a new verification link to the user's unverified email.
+#[endpoint(
+    operation_id = "resend_email_verification",
+    tags("User"),
+    responses(
+        (status_code = 200, description = "The verification link has been sent", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The user does not have an email", content_type = "application/json", body = MessageSchema),
+        (status_code = 400, description = "The email is already verified", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn resend_email_verification(depot: &mut Depot) -> ApiResult<Json<MessageSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let auth_config = depot.obtain::<Arc<AuthConfig>>().unwrap();
+    let mailer = depot.obtain::<Arc<dyn Mailer>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+
+    let Some(email) = user.email.clone() else {
+        return Err(ApiError::EmailNotSet);
+    };
+    if user.email_verified {
+        return Err(ApiError::EmailAlreadyVerified);
+    }
+    super::jwt::send_email_verification(
+        mailer.as_ref(),
+        auth_config,
+        user.uuid,
+        &user.first_name,
+        &user.username,
+        email,
+    )
+    .await?;
+
+    Ok(Json(MessageSchema::new(
+        "The verification link has been sent".to_owned(),
+    )))
+}
+
+/// Get the user's privacy settings.
+#[endpoint(
+    operation_id = "get_privacy_settings",
+    tags("User"),
+    responses(
+        (status_code = 200, description = "The user's privacy settings", content_type = "application/json", body = PrivacySettingsSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn get_privacy_settings(depot: &mut Depot) -> ApiResult<Json<PrivacySettingsSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+
+    db_utils::get_privacy_settings(conn.as_ref(), user.uuid)
+        .await
+        .map(Json)
+}
+
+/// Update the user's privacy settings.
+///
+/// This endpoint will update the settings that applied to the user's profile, profile image and games.
+/// - `hide_real_name`: The username is shown instead of the first and last name.
+/// - `hide_game_history`: The user's latest games, statistics, and games are hidden.
+/// - `friends_only`: The user's profile is shown to his friends only. There are no friends yet, so it currently means
+///   private: his profile is private, his profile image is the default image and his games are hidden from everyone.
+#[endpoint(
+    operation_id = "update_privacy_settings",
+    tags("User"),
+    request_body(
+        content = PrivacySettingsSchema,
+        description = "The new privacy settings",
+        example = json!(PrivacySettingsSchema::default()),
+        content_type = "application/json",
+    ),
+    responses(
+        (status_code = 200, description = "The user's privacy settings have been updated", content_type = "application/json", body = PrivacySettingsSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The account is deleted, restore it first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn update_privacy_settings(
+    depot: &mut Depot,
+    settings: JsonBody<PrivacySettingsSchema>,
+) -> ApiResult<Json<PrivacySettingsSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let user = depot.user(conn.as_ref()).await?;
+
+    db_utils::update_privacy_settings(conn.as_ref(), user.uuid, settings.into_inner())
+        .await
+        .map(Json)
+}
+
+/// Export the user's data.
+///
+/// This endpoint will return all the user's data as a single JSON document attachment.
+/// - The document contains the user's info, profile image, active sessions, old usernames, linked accounts, and all his games with their rounds boards.
+/// - The games are streamed page by page, so the response has no `Content-Length` header.
+#[endpoint(
+    operation_id = "export_user_data",
+    tags("User"),
+    responses(
+        (status_code = 200, description = "The user's data", content_type = "application/json", body = UserExportSchema),
+        (status_code = 400, description = "The token is not a user token", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "The token is expired", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Unauthorized, missing JWT", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+    security(("bearerAuth" = [])),
+)]
+pub async fn export_user_data(depot: &mut Depot, res: &mut Response) -> ApiResult<()> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap();
+    let user = depot.user_including_deleted(conn.as_ref()).await?;
+    let current_session = depot.jwt_claims().session;
+    let user_uuid = user.uuid;
+
+    let image_path = utils::get_image_disk_path(&user_uuid.to_string());
+    let profile_image = if Path::new(&image_path).exists() {
+        Some(
+            crate::BASE_64_ENGINE
+                .encode(std::fs::read(image_path).map_err(|_| ApiError::InternalServer)?),
+        )
+    } else {
+        None
+    };
+    let sessions = db_utils::get_user_sessions(conn.as_ref(), user_uuid)
+        .await?
+        .into_iter()
+        .map(|(session, expires_at)| {
+            SessionSchema::from_session(session, expires_at, current_session)
+        })
+        .collect();
+    let username_history = db_utils::get_username_history(conn.as_ref(), user_uuid)
+        .await?
+        .into_iter()
+        .map(|history| UsernameHistorySchema {
+            username: history.username,
+            changed_at: history.changed_at,
+        })
+        .collect();
+    let identities = db_utils::get_user_identities(conn.as_ref(), user_uuid)
+        .await?
+        .into_iter()
+        .map(|identity| IdentitySchema {
+            provider: identity.provider,
+            subject: identity.subject,
+            created_at: identity.created_at,
+        })
+        .collect();
+    let content_disposition = format!("attachment; filename=\"xors-{}.json\"", user.username);
+
+    // The document is serialized without the games, then the games are streamed into its array
+    let document = serde_json::to_string(&UserExportSchema {
+        user: UserSchema::owner_from_active_model(conn, user.into_active_model()).await?,
+        profile_image,
+        sessions,
+        username_history,
+        identities,
+        games: Vec::new(),
+    })?;
+    let head = document
+        .strip_suffix("]}")
+        .expect("The games are the last field of the document")
+        .to_owned();
+
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    res.headers_mut().insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&content_disposition).map_err(|_| ApiError::InternalServer)?,
+    );
+    let mut sender = res.channel();
+    let conn = conn.as_ref().clone();
+    tokio::spawn(async move {
+        if let Err(err) = write_export_games(&conn, &mut sender, head, user_uuid).await {
+            log::error!("Failed to export the data of user {user_uuid}: {err}");
+            sender.send_error(std::io::Error::other(err.to_string()));
+        }
+    });
+    Ok(())
+}
+
+/// Writes the data export document, the player's games are written page by page.
+async fn write_export_games(
+    conn: &DatabaseConnection,
+    sender: &mut BodySender,
+    head: String,
+    user_uuid: Uuid,
+) -> ApiResult<()> {
+    sender
+        .send_data(head)
+        .await
+        .map_err(|_| ApiError::InternalServer)?;
+
+    let mut after_id = None;
+    loop {
+        let games =
+            db_utils::get_player_games_after(conn, user_uuid, after_id, EXPORT_GAMES_PAGE_SIZE)
+                .await?;
+        let Some(last_game) = games.last() else {
+            break;
+        };
+        let mut chunk = String::new();
+        if after_id.is_some() {
+            chunk.push(',');
+        }
+        after_id = Some(last_game.id);
+
+        let games = games
+            .into_iter()
+            .map(|game| {
+                Ok(serde_json::to_string(&ExportedGameSchema::from_game(
+                    game,
+                )?)?)
+            })
+            .collect::<ApiResult<Vec<_>>>()?;
+        chunk.push_str(&games.join(","));
+        sender
+            .send_data(chunk)
+            .await
+            .map_err(|_| ApiError::InternalServer)?;
+    }
+
+    sender
+        .send_data("]}")
+        .await
+        .map_err(|_| ApiError::InternalServer)
+}
+
+/// Returns the profile image file name that the requester can see of the user.
+///
+/// The users without profile image, and the private profiles, are shown with the default image.
+async fn visible_profile_image(
+    conn: &DatabaseConnection,
+    requester: Option<Uuid>,
+    user_uuid: Uuid,
+) -> ApiResult<String> {
+    let is_private = requester != Some(user_uuid)
+        && db_utils::get_privacy_settings(conn, user_uuid)
+            .await?
+            .friends_only;
+    if !is_private && Path::new(&utils::get_image_disk_path(&user_uuid.to_string())).exists() {
+        Ok(user_uuid.to_string())
+    } else {
+        Ok("default".to_owned())
+    }
+}
+
 /// Returns the user's profile image.
+///
+/// **Deprecated**: Use `/profiles/<uuid>.png` instead, it returns the image as binary and supports caching.
 #[endpoint(
     operation_id = "get_user_profile_image",
     tags("User"),
@@ -235,20 +988,94 @@ pub async fn reset_user_password(
         (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
     ),
 )]
-pub async fn get_user_profile_image(uuid: PathParam<Uuid>) -> ApiResult<Json<ImageSchema>> {
-    let requested_user_uuid = uuid.into_inner();
-
-    let image_path = if Path::new(&utils::get_image_disk_path(
-        &requested_user_uuid.to_string(),
-    ))
-    .exists()
-    {
-        utils::get_image_disk_path(&requested_user_uuid.to_string())
-    } else {
-        utils::get_image_disk_path("default")
-    };
+pub async fn get_user_profile_image(
+    depot: &mut Depot,
+    uuid: PathParam<Uuid>,
+) -> ApiResult<Json<ImageSchema>> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap().as_ref();
+    let requester = depot.requester(conn).await?;
+    let image_path = utils::get_image_disk_path(
+        &visible_profile_image(conn, requester, uuid.into_inner()).await?,
+    );
 
     let image_base64 = crate::BASE_64_ENGINE
         .encode(std::fs::read(image_path).map_err(|_| ApiError::InternalServer)?);
     Ok(Json(ImageSchema::new(image_base64)))
 }
+
+/// Returns the user's profile image as PNG.
+///
+/// The `name` is the user's uuid or `default`, so the user's `profile_image_path` with `.png` extension is its path.
+/// - The image is 128x128 pixels, the `size` parameter returns a 32x32 or 64x64 pixels thumbnail instead.
+/// - The response has the `ETag` and `Last-Modified` headers, send them back in the `If-None-Match` and
+///   `If-Modified-Since` headers to get a `304 Not Modified` response if the image didn't change.
+/// - The private profiles are shown with the default image, except to the user himself.
+///   Send the JWT in the `Authorization` header to be identified as the requester.
+#[endpoint(
+    operation_id = "get_user_profile_image_png",
+    tags("User"),
+    parameters(
+        ("name" = String, Path, description = "The requested user's uuid, or `default` for the default image"),
+        ("size" = Option<u32>, Query, description = "The image size in pixels, one of 32, 64 or 128. Default is 128"),
+    ),
+    responses(
+        (status_code = 200, description = "The user's profile image", content_type = "image/png"),
+        (status_code = 304, description = "The image didn't change"),
+        (status_code = 400, description = "Invalid image size", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "User not found", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+)]
+pub async fn get_user_profile_image_png(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    name: PathParam<String>,
+    size: QueryParam<u32, false>,
+) -> ApiResult<()> {
+    let conn = depot.obtain::<Arc<DatabaseConnection>>().unwrap().as_ref();
+    let name = name.into_inner();
+
+    let size = size.into_inner().unwrap_or(utils::PROFILE_IMAGE_SIZES[2]);
+
+    let file_name = if name == "default" {
+        name
+    } else {
+        let user_uuid = name.parse().map_err(|_| ApiError::UserNotFound)?;
+        db_utils::get_active_user(conn, user_uuid).await?;
+        visible_profile_image(conn, depot.requester(conn).await?, user_uuid).await?
+    };
+    // The thumbnail may be generated, so it's done in a blocking thread
+    let image_path =
+        tokio::task::spawn_blocking(move || utils::get_profile_image_disk_path(&file_name, size))
+            .await
+            .map_err(|_| ApiError::InternalServer)??;
+
+    // The image can change anytime and depends on the requester, so it's always revalidated
+    res.headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    res.headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("Authorization"));
+    NamedFile::builder(image_path)
+        .content_type(mime::IMAGE_PNG)
+        .send(req.headers(), res)
+        .await;
+    Ok(())
+}
+
+/// Deleted accounts purge handler.
+/// ### Note
+/// This function will purge the accounts that their grace period is ended every hour.
+pub async fn purge_handler(conn: sea_orm::DatabaseConnection, grace_period: chrono::Duration) {
+    log::info!("Starting the deleted accounts purge handler");
+
+    loop {
+        match db_utils::purge_deleted_users(&conn, grace_period).await {
+            Ok(0) => {}
+            Ok(purged) => log::info!("Purged {purged} deleted accounts"),
+            Err(err) => log::error!("Deleted accounts purge handler error: {err}"),
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(60 * 60)).await;
+    }
+}
@@ -16,7 +16,6 @@
 
 use std::{
     collections::{HashMap, VecDeque},
-    str::FromStr,
     sync::Arc,
 };
 
@@ -268,7 +267,7 @@ async fn play(
         log::info!("Player {} is playing in place {}", player.0, place);
 
         let mut game = db_utils::get_game::<false>(conn, &game_uuid).await?;
-        let mut board = Board::from_str(&game.board).expect("The board is valid");
+        let mut board = game.parse_board()?;
         let (player_symbol, versus_symbol) = if game.x_player == *player.0 {
             (XoSymbol::X, XoSymbol::O)
         } else {
@@ -285,8 +284,7 @@ async fn play(
             game.auto_play_after =
                 Some((chrono::Utc::now() + Duration::seconds(move_period)).naive_utc());
             board.set_cell(place, player.symbol);
-            let mut rounds_result =
-                RoundsResult::from_str(&game.rounds_result).expect("The rounds result is valid");
+            let mut rounds_result = game.parse_rounds_result()?;
 
             if board.is_win(&player.symbol) {
                 log::info!("Player {} won the round {}", player.uuid, game.round);
@@ -545,13 +543,21 @@ pub async fn auto_play_handler(conn: sea_orm::DatabaseConnection, move_period: i
             if !games.is_empty() {
                 for game in games {
                     if let Some(auto_play_after) = game.auto_play_after {
-                        let board = Board::from_str(&game.board).expect("The board is valid");
+                        let board = match game.parse_board() {
+                            Ok(board) => board,
+                            Err(err) => {
+                                // Skip the corrupted game, so it doesn't block the other games.
+                                log::error!("Can't auto play: {err}");
+                                continue;
+                            }
+                        };
                         if chrono::Utc::now().naive_utc() >= auto_play_after {
                             let players = ONLINE_GAMES
                                 .get_game_players(&game.uuid)
                                 .await
                                 .expect("The game should be in the online games");
-                            let player = if board.turn() == XoSymbol::X {
+                            let turn = board.turn();
+                            let player = if turn == XoSymbol::X {
                                 players.0.clone()
                             } else {
                                 players.1.clone()
@@ -572,6 +578,7 @@ pub async fn auto_play_handler(conn: sea_orm::DatabaseConnection, move_period: i
                                 player.0,
                                 game.uuid
                             );
+                            db_utils::increase_auto_plays(conn, game, &turn).await?;
                             play(conn, player.clone(), place, move_period).await?;
                         }
                     }
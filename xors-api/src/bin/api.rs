@@ -31,8 +31,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .expect("`XORS_API_DATABASE_URL` environment variable must be set"),
     )
     .await?;
-    let secret_key = env::var("XORS_API_SECRET_KEY")
-        .expect("`XORS_API_SECRET_KEY` environment variable must be set");
+    let auth_config = config::AuthConfig::from_env();
+    let mailer = mailer::from_env();
     let max_online_games = env::var("XORS_API_MAX_ONLINE_GAMES")
         .expect("`XORS_API_MAX_ONLINE_GAMES` environment variable must be set")
         .parse::<usize>()
@@ -57,20 +57,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Press Ctrl+C to stop the API");
 
     let server_connection = connection.clone();
+    let purge_connection = connection.clone();
+    let grace_period = auth_config.account_deletion_grace_period;
     let acceptor = salvo::conn::TcpListener::new(format!("{host}:{port}"))
         .bind()
         .await;
     let server_handler = tokio::spawn(async move {
         Server::new(acceptor)
-            .serve(api::service(server_connection, max_online_games, move_period, secret_key).0)
+            .serve(
+                api::service(
+                    server_connection,
+                    max_online_games,
+                    move_period,
+                    auth_config,
+                    mailer,
+                )
+                .0,
+            )
             .await
     });
     let auto_play_handler = tokio::spawn(async move {
         api::xo::auto_play_handler(connection, move_period).await;
     });
 
+    let purge_handler = tokio::spawn(async move {
+        api::user::purge_handler(purge_connection, grace_period).await;
+    });
+
     server_handler.await?;
     auto_play_handler.await?;
+    purge_handler.await?;
     log::info!("API is shutting down");
 
     Ok(())
@@ -0,0 +1,46 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Admin command to scan the `game` table for inconsistent games.
+// Will exit with code 1 if there is a corrupted game.
+
+use std::env;
+
+use xors_api::*;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    pretty_env_logger::init();
+
+    let connection = sea_orm::Database::connect(
+        env::var("XORS_API_DATABASE_URL")
+            .expect("`XORS_API_DATABASE_URL` environment variable must be set"),
+    )
+    .await?;
+
+    let corrupted_games = db_utils::scan_games(&connection).await?;
+    for err in &corrupted_games {
+        println!("{err}");
+    }
+
+    if corrupted_games.is_empty() {
+        println!("There is no corrupted games");
+    } else {
+        println!("Found {} corrupted games", corrupted_games.len());
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,473 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{env, fs};
+
+use argon2::Params;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Duration;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use rsa::{
+    pkcs1::DecodeRsaPublicKey,
+    pkcs8::{DecodePublicKey, Document, ObjectIdentifier, SubjectPublicKeyInfoRef},
+    traits::PublicKeyParts,
+    RsaPublicKey,
+};
+use salvo::{jwt_auth::JwtAuthDecoder, Depot};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    errors::{ApiError, ApiResult, JwtKeyError},
+    oidc::{self, OidcProvider},
+    password::PasswordHasher,
+    schemas::JwkSchema,
+};
+
+/// The default bcrypt cost.
+const DEFAULT_BCRYPT_COST: u32 = 12;
+
+/// The Ed25519 object identifier.
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+/// The key that signs the new tokens.
+#[derive(Clone)]
+pub struct JwtSigningKey {
+    /// The key id, it's the `kid` header of the signed tokens.
+    pub kid: Option<String>,
+    /// The signing algorithm.
+    pub algorithm: Algorithm,
+    /// The signing key.
+    pub encoding_key: EncodingKey,
+}
+
+/// A key that verifies the tokens.
+#[derive(Clone)]
+pub struct JwtVerificationKey {
+    /// The key id, the tokens with the same `kid` header will be verified with this key.
+    pub kid: Option<String>,
+    /// The verification algorithm.
+    pub algorithm: Algorithm,
+    /// The verification key.
+    pub decoding_key: DecodingKey,
+    /// The public key, it's published in the JWKS endpoint. Will be `None` if it's a secret.
+    pub jwk: Option<JwkSchema>,
+}
+
+/// The authentication configuration. It's injected into the depot by [`crate::api::service`].
+#[derive(Clone)]
+pub struct AuthConfig {
+    /// The key that signs the new tokens.
+    pub signing_key: JwtSigningKey,
+    /// The keys that verify the tokens. Multiple keys can be active at once, to rotate the signing key.
+    pub verification_keys: Vec<JwtVerificationKey>,
+    /// The JWT token lifetime.
+    pub jwt_ttl: Duration,
+    /// The refresh token lifetime.
+    pub refresh_token_ttl: Duration,
+    /// The delay before the refresh token can be used.
+    pub refresh_token_active_after: Duration,
+    /// The two-factor authentication challenge token lifetime.
+    pub totp_challenge_ttl: Duration,
+    /// The failed signin attempts before locking the account.
+    pub lockout_threshold: u32,
+    /// The first lockout duration, it's doubled with every failed attempt after the threshold.
+    pub lockout_duration: Duration,
+    /// The maximum lockout duration.
+    pub lockout_max_duration: Duration,
+    /// The password hashing algorithm. The old hashes are rehashed with it after a successful signin.
+    pub password_hasher: PasswordHasher,
+    /// The password reset token lifetime.
+    pub password_reset_ttl: Duration,
+    /// The email verification token lifetime.
+    pub email_verification_ttl: Duration,
+    /// The public URL of the API, used to build the links that sent by mail.
+    pub public_url: String,
+    /// The OpenID Connect identity providers that the users can signin with.
+    pub oidc_providers: Vec<OidcProvider>,
+    /// The minimum duration between two username changes.
+    pub username_change_cooldown: Duration,
+    /// The duration that the old username is reserved for its user after changing it.
+    pub username_reservation_ttl: Duration,
+    /// The duration that a deleted account can be restored in, it's purged after it.
+    pub account_deletion_grace_period: Duration,
+}
+
+impl JwtSigningKey {
+    /// Create a new HMAC signing key from the secret.
+    pub fn from_secret(algorithm: Algorithm, secret: &str) -> Result<Self, JwtKeyError> {
+        if !matches!(
+            algorithm,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(JwtKeyError::UnsupportedAlgorithm(algorithm));
+        }
+        Ok(Self {
+            kid: None,
+            algorithm,
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+        })
+    }
+
+    /// Create a new signing key from a PEM private key. `RS256` for RSA keys and `EdDSA` for Ed25519 keys.
+    pub fn from_pem(kid: String, algorithm: Algorithm, pem: &[u8]) -> Result<Self, JwtKeyError> {
+        let encoding_key = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(pem)?,
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(pem)?,
+            _ => return Err(JwtKeyError::UnsupportedAlgorithm(algorithm)),
+        };
+        Ok(Self {
+            kid: Some(kid),
+            algorithm,
+            encoding_key,
+        })
+    }
+}
+
+impl JwtVerificationKey {
+    /// Create a new HMAC verification key from the secret.
+    pub fn from_secret(algorithm: Algorithm, secret: &str) -> Result<Self, JwtKeyError> {
+        JwtSigningKey::from_secret(algorithm, secret)?;
+        Ok(Self {
+            kid: None,
+            algorithm,
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            jwk: None,
+        })
+    }
+
+    /// Create a new verification key from a PEM public key. The algorithm is detected from the key,
+    /// `RS256` for RSA keys and `EdDSA` for Ed25519 keys.
+    pub fn from_pem(kid: String, pem: &str) -> Result<Self, JwtKeyError> {
+        let rsa_key = RsaPublicKey::from_public_key_pem(pem)
+            .ok()
+            .or_else(|| RsaPublicKey::from_pkcs1_pem(pem).ok());
+
+        let jwk = if let Some(rsa_key) = rsa_key {
+            JwkSchema::rsa(
+                kid,
+                URL_SAFE_NO_PAD.encode(rsa_key.n().to_bytes_be()),
+                URL_SAFE_NO_PAD.encode(rsa_key.e().to_bytes_be()),
+            )
+        } else {
+            let (_, document) =
+                Document::from_pem(pem).map_err(|err| JwtKeyError::InvalidPem(err.to_string()))?;
+            let public_key = SubjectPublicKeyInfoRef::try_from(document.as_bytes())
+                .map_err(|err| JwtKeyError::InvalidPem(err.to_string()))?;
+            if public_key.algorithm.oid != ED25519_OID {
+                return Err(JwtKeyError::UnsupportedKey);
+            }
+            JwkSchema::ed25519(
+                kid,
+                URL_SAFE_NO_PAD.encode(public_key.subject_public_key.raw_bytes()),
+            )
+        };
+
+        let (algorithm, decoding_key) = if let Some(n) = &jwk.n {
+            (
+                Algorithm::RS256,
+                DecodingKey::from_rsa_components(n, jwk.e.as_deref().unwrap_or_default())?,
+            )
+        } else {
+            (
+                Algorithm::EdDSA,
+                DecodingKey::from_ed_components(jwk.x.as_deref().unwrap_or_default())?,
+            )
+        };
+        Ok(Self {
+            kid: Some(jwk.kid.clone()),
+            algorithm,
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+}
+
+impl AuthConfig {
+    /// Create a new [`AuthConfig`] that signs the tokens with the secret using `HS256`,
+    /// with the default lifetimes.
+    pub fn new(secret_key: String) -> Self {
+        Self::with_keys(
+            JwtSigningKey::from_secret(Algorithm::HS256, &secret_key)
+                .expect("HS256 is a supported algorithm"),
+            vec![
+                JwtVerificationKey::from_secret(Algorithm::HS256, &secret_key)
+                    .expect("HS256 is a supported algorithm"),
+            ],
+        )
+    }
+
+    /// Create a new [`AuthConfig`] with the given keys and the default lifetimes.
+    pub fn with_keys(
+        signing_key: JwtSigningKey,
+        verification_keys: Vec<JwtVerificationKey>,
+    ) -> Self {
+        Self {
+            signing_key,
+            verification_keys,
+            jwt_ttl: Duration::hours(1),
+            refresh_token_ttl: Duration::hours(3),
+            refresh_token_active_after: Duration::minutes(58),
+            totp_challenge_ttl: Duration::minutes(5),
+            lockout_threshold: 5,
+            lockout_duration: Duration::seconds(30),
+            lockout_max_duration: Duration::hours(1),
+            password_hasher: PasswordHasher::default(),
+            password_reset_ttl: Duration::minutes(30),
+            email_verification_ttl: Duration::hours(24),
+            public_url: "http://localhost:8000".to_owned(),
+            oidc_providers: Vec::new(),
+            username_change_cooldown: Duration::days(30),
+            username_reservation_ttl: Duration::days(90),
+            account_deletion_grace_period: Duration::days(30),
+        }
+    }
+
+    /// Returns the OpenID Connect provider with the name.
+    pub fn oidc_provider(&self, name: &str) -> ApiResult<&OidcProvider> {
+        self.oidc_providers
+            .iter()
+            .find(|provider| provider.name == name)
+            .ok_or_else(|| ApiError::OidcProviderNotFound(name.to_owned()))
+    }
+
+    /// Returns the lockout duration after the failed signin attempts, `None` if the account will not be locked.
+    pub fn lockout_duration_after(&self, failed_attempts: u64) -> Option<Duration> {
+        let exponent = failed_attempts.checked_sub(self.lockout_threshold as u64)?;
+        let seconds = 2i64
+            .checked_pow(exponent.try_into().unwrap_or(u32::MAX))
+            .and_then(|factor| self.lockout_duration.num_seconds().checked_mul(factor))
+            .unwrap_or(i64::MAX);
+        Some(Duration::seconds(
+            seconds.min(self.lockout_max_duration.num_seconds()),
+        ))
+    }
+
+    /// Returns the claims signed with the signing key.
+    pub fn encode_token<C: Serialize>(&self, claims: &C) -> String {
+        let header = Header {
+            kid: self.signing_key.kid.clone(),
+            ..Header::new(self.signing_key.algorithm)
+        };
+        jsonwebtoken::encode(&header, claims, &self.signing_key.encoding_key)
+            .expect("JWT encode failed")
+    }
+
+    /// Verifies the token with the key of its `kid` and algorithm, and returns its claims.
+    pub fn decode_token<C: DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Result<TokenData<C>, jsonwebtoken::errors::Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let key = self
+            .verification_keys
+            .iter()
+            .find(|key| key.kid == header.kid && key.algorithm == header.alg)
+            .ok_or(jsonwebtoken::errors::ErrorKind::InvalidSignature)?;
+
+        jsonwebtoken::decode::<C>(token, &key.decoding_key, &Validation::new(key.algorithm))
+    }
+
+    /// Returns the public keys, to be published in the JWKS endpoint.
+    pub fn public_keys(&self) -> Vec<JwkSchema> {
+        self.verification_keys
+            .iter()
+            .filter_map(|key| key.jwk.clone())
+            .collect()
+    }
+
+    /// Create a new [`AuthConfig`] from the environment variables.
+    ///
+    /// - `XORS_API_JWT_ALGORITHM`: The JWT algorithm, `HS256`, `HS384`, `HS512`, `RS256` or `EdDSA`. Default `HS256`.
+    /// - `XORS_API_SECRET_KEY`: The secret key, required with the HMAC algorithms.
+    /// - `XORS_API_JWT_PRIVATE_KEY`: The PEM private key path, required with `RS256` and `EdDSA`.
+    /// - `XORS_API_JWT_KEY_ID`: The private key id, required with `RS256` and `EdDSA`.
+    /// - `XORS_API_JWT_PUBLIC_KEYS`: The verification keys, comma separated `<kid>:<path>` of PEM public keys.
+    ///   Required with `RS256` and `EdDSA`, and must contain the private key id.
+    /// - `XORS_API_JWT_TTL`: The JWT token lifetime in seconds. Default 1 hour.
+    /// - `XORS_API_REFRESH_TOKEN_TTL`: The refresh token lifetime in seconds. Default 3 hours.
+    /// - `XORS_API_REFRESH_TOKEN_ACTIVE_AFTER`: The refresh token activation delay in seconds. Default 58 minutes.
+    /// - `XORS_API_TOTP_CHALLENGE_TTL`: The two-factor authentication challenge token lifetime in seconds. Default 5 minutes.
+    /// - `XORS_API_LOCKOUT_THRESHOLD`: The failed signin attempts before locking the account. Default 5.
+    /// - `XORS_API_LOCKOUT_DURATION`: The first lockout duration in seconds, it's doubled with every failed attempt. Default 30 seconds.
+    /// - `XORS_API_LOCKOUT_MAX_DURATION`: The maximum lockout duration in seconds. Default 1 hour.
+    /// - `XORS_API_PASSWORD_HASHER`: The password hashing algorithm, `argon2id` or `bcrypt`. Default `argon2id`.
+    /// - `XORS_API_BCRYPT_COST`: The bcrypt cost. Default 12.
+    /// - `XORS_API_ARGON2_MEMORY_COST`: The Argon2id memory size in KiB. Default 19456.
+    /// - `XORS_API_ARGON2_TIME_COST`: The Argon2id iterations. Default 2.
+    /// - `XORS_API_ARGON2_PARALLELISM`: The Argon2id degree of parallelism. Default 1.
+    /// - `XORS_API_PASSWORD_RESET_TTL`: The password reset token lifetime in seconds. Default 30 minutes.
+    /// - `XORS_API_EMAIL_VERIFICATION_TTL`: The email verification token lifetime in seconds. Default 24 hours.
+    /// - `XORS_API_PUBLIC_URL`: The public URL of the API, used in the mailed links. Default `http://localhost:8000`.
+    /// - `XORS_API_OIDC_PROVIDERS`: The OpenID Connect providers, see [`oidc::providers_from_env`]. Default none.
+    /// - `XORS_API_USERNAME_CHANGE_COOLDOWN`: The minimum duration between two username changes in seconds. Default 30 days.
+    /// - `XORS_API_USERNAME_RESERVATION_TTL`: The duration that the old username is reserved in seconds. Default 90 days.
+    /// - `XORS_API_ACCOUNT_DELETION_GRACE_PERIOD`: The duration that a deleted account can be restored in seconds. Default 30 days.
+    ///
+    /// ### Panics
+    /// If a variable is invalid, or the refresh token will be activated after it expires.
+    pub fn from_env() -> Self {
+        let algorithm = match env::var("XORS_API_JWT_ALGORITHM").as_deref() {
+            Err(_) | Ok("HS256") => Algorithm::HS256,
+            Ok("HS384") => Algorithm::HS384,
+            Ok("HS512") => Algorithm::HS512,
+            Ok("RS256") => Algorithm::RS256,
+            Ok("EdDSA") => Algorithm::EdDSA,
+            _ => panic!(
+                "`XORS_API_JWT_ALGORITHM` environment variable must be `HS256`, `HS384`, `HS512`, `RS256` or `EdDSA`"
+            ),
+        };
+
+        let mut config = if matches!(algorithm, Algorithm::RS256 | Algorithm::EdDSA) {
+            let kid = env::var("XORS_API_JWT_KEY_ID")
+                .expect("`XORS_API_JWT_KEY_ID` environment variable must be set");
+            let private_key_path = env::var("XORS_API_JWT_PRIVATE_KEY")
+                .expect("`XORS_API_JWT_PRIVATE_KEY` environment variable must be set");
+            let signing_key = JwtSigningKey::from_pem(
+                kid.clone(),
+                algorithm,
+                &fs::read(&private_key_path)
+                    .unwrap_or_else(|err| panic!("Failed to read `{private_key_path}`: {err}")),
+            )
+            .unwrap_or_else(|err| panic!("Invalid private key `{private_key_path}`: {err}"));
+
+            let verification_keys: Vec<_> = env::var("XORS_API_JWT_PUBLIC_KEYS")
+                .expect("`XORS_API_JWT_PUBLIC_KEYS` environment variable must be set")
+                .split(',')
+                .map(|key| {
+                    let (kid, path) = key.trim().split_once(':').expect(
+                        "`XORS_API_JWT_PUBLIC_KEYS` environment variable must be comma separated `<kid>:<path>`",
+                    );
+                    JwtVerificationKey::from_pem(
+                        kid.to_owned(),
+                        &fs::read_to_string(path)
+                            .unwrap_or_else(|err| panic!("Failed to read `{path}`: {err}")),
+                    )
+                    .unwrap_or_else(|err| panic!("Invalid public key `{path}`: {err}"))
+                })
+                .collect();
+            if !verification_keys
+                .iter()
+                .any(|key| key.kid.as_deref() == Some(&kid) && key.algorithm == algorithm)
+            {
+                panic!("`XORS_API_JWT_PUBLIC_KEYS` must contain the `{kid}` public key");
+            }
+            Self::with_keys(signing_key, verification_keys)
+        } else {
+            let secret_key = env::var("XORS_API_SECRET_KEY")
+                .expect("`XORS_API_SECRET_KEY` environment variable must be set");
+            Self::with_keys(
+                JwtSigningKey::from_secret(algorithm, &secret_key).expect("It's an HMAC algorithm"),
+                vec![JwtVerificationKey::from_secret(algorithm, &secret_key)
+                    .expect("It's an HMAC algorithm")],
+            )
+        };
+
+        if let Some(jwt_ttl) = seconds_var("XORS_API_JWT_TTL") {
+            config.jwt_ttl = jwt_ttl;
+        }
+        if let Some(refresh_token_ttl) = seconds_var("XORS_API_REFRESH_TOKEN_TTL") {
+            config.refresh_token_ttl = refresh_token_ttl;
+        }
+        if let Some(active_after) = seconds_var("XORS_API_REFRESH_TOKEN_ACTIVE_AFTER") {
+            config.refresh_token_active_after = active_after;
+        }
+        if let Some(totp_challenge_ttl) = seconds_var("XORS_API_TOTP_CHALLENGE_TTL") {
+            config.totp_challenge_ttl = totp_challenge_ttl;
+        }
+        if let Some(lockout_threshold) = number_var("XORS_API_LOCKOUT_THRESHOLD") {
+            config.lockout_threshold = lockout_threshold;
+        }
+        if let Some(lockout_duration) = seconds_var("XORS_API_LOCKOUT_DURATION") {
+            config.lockout_duration = lockout_duration;
+        }
+        if let Some(lockout_max_duration) = seconds_var("XORS_API_LOCKOUT_MAX_DURATION") {
+            config.lockout_max_duration = lockout_max_duration;
+        }
+        config.password_hasher = match env::var("XORS_API_PASSWORD_HASHER").as_deref() {
+            Err(_) | Ok("argon2id") => PasswordHasher::Argon2id {
+                memory_cost: number_var("XORS_API_ARGON2_MEMORY_COST")
+                    .unwrap_or(Params::DEFAULT_M_COST),
+                time_cost: number_var("XORS_API_ARGON2_TIME_COST")
+                    .unwrap_or(Params::DEFAULT_T_COST),
+                parallelism: number_var("XORS_API_ARGON2_PARALLELISM")
+                    .unwrap_or(Params::DEFAULT_P_COST),
+            },
+            Ok("bcrypt") => PasswordHasher::Bcrypt {
+                cost: number_var("XORS_API_BCRYPT_COST").unwrap_or(DEFAULT_BCRYPT_COST),
+            },
+            _ => panic!(
+                "`XORS_API_PASSWORD_HASHER` environment variable must be `argon2id` or `bcrypt`"
+            ),
+        };
+        if let Some(password_reset_ttl) = seconds_var("XORS_API_PASSWORD_RESET_TTL") {
+            config.password_reset_ttl = password_reset_ttl;
+        }
+        if let Some(email_verification_ttl) = seconds_var("XORS_API_EMAIL_VERIFICATION_TTL") {
+            config.email_verification_ttl = email_verification_ttl;
+        }
+        if let Ok(public_url) = env::var("XORS_API_PUBLIC_URL") {
+            config.public_url = public_url.trim_end_matches('/').to_owned();
+        }
+        config.oidc_providers = oidc::providers_from_env(&config.public_url);
+        if let Some(cooldown) = seconds_var("XORS_API_USERNAME_CHANGE_COOLDOWN") {
+            config.username_change_cooldown = cooldown;
+        }
+        if let Some(reservation_ttl) = seconds_var("XORS_API_USERNAME_RESERVATION_TTL") {
+            config.username_reservation_ttl = reservation_ttl;
+        }
+        if let Some(grace_period) = seconds_var("XORS_API_ACCOUNT_DELETION_GRACE_PERIOD") {
+            config.account_deletion_grace_period = grace_period;
+        }
+        if config.refresh_token_active_after >= config.refresh_token_ttl {
+            panic!("The refresh token must be activated before it expires");
+        }
+
+        config
+    }
+}
+
+impl JwtAuthDecoder for AuthConfig {
+    type Error = jsonwebtoken::errors::Error;
+
+    async fn decode<C>(&self, token: &str, _depot: &mut Depot) -> Result<TokenData<C>, Self::Error>
+    where
+        C: for<'de> Deserialize<'de>,
+    {
+        self.decode_token(token)
+    }
+}
+
+/// Returns the duration of the environment variable, it's in seconds.
+fn seconds_var(name: &str) -> Option<Duration> {
+    env::var(name).ok().map(|seconds| {
+        let seconds = seconds
+            .parse::<i64>()
+            .unwrap_or_else(|_| panic!("`{name}` environment variable must be a number"));
+        if seconds <= 0 {
+            panic!("`{name}` environment variable must be a positive number");
+        }
+        Duration::seconds(seconds)
+    })
+}
+
+/// Returns the number of the environment variable.
+fn number_var(name: &str) -> Option<u32> {
+    env::var(name).ok().map(|number| {
+        number
+            .parse::<u32>()
+            .unwrap_or_else(|_| panic!("`{name}` environment variable must be a positive number"))
+    })
+}
@@ -16,111 +16,614 @@
 
 use std::fs;
 
-use crate::api::jwt::JwtClaims;
+use crate::api::{exts::GameModelExt, jwt::JwtClaims};
+use crate::config::AuthConfig;
 use crate::errors::{ApiError, ApiResult};
-use crate::{schemas::*, utils};
-use base64::Engine;
+use crate::oidc::{self, IdTokenClaims};
+use crate::password::PasswordHasher;
+use crate::{schemas::*, totp, utils};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::Duration;
 use entity::prelude::*;
-use jsonwebtoken::Header;
+use rand::Rng;
+use sea_orm::{Condition, TransactionTrait, TryIntoModel};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
+/// The number of the recovery codes of the two-factor authentication.
+const RECOVERY_CODES_COUNT: usize = 10;
+/// The number of the password recovery codes.
+const PASSWORD_RECOVERY_CODES_COUNT: usize = 10;
+/// The length of the password reset token in bytes.
+const PASSWORD_RESET_TOKEN_LEN: usize = 32;
+/// The failed signin attempts older than this, in hours, are forgotten.
+const FAILED_SIGNINS_TTL_HOURS: i64 = 24;
+/// The OpenID Connect authorization lifetime in minutes, the user must finish the provider login before it.
+const OIDC_AUTHORIZATION_TTL_MINUTES: i64 = 10;
+
 /// Creates a new user in the database.
 pub async fn create_user(
     conn: &sea_orm::DatabaseConnection,
     new_user: NewUserSchema,
+    password_hasher: &PasswordHasher,
 ) -> ApiResult<UserSchema> {
     log::info!("Creating user: {}", new_user.username);
 
-    if UserEntity::find()
-        .filter(UserColumn::Username.eq(new_user.username.clone()))
-        .count(conn)
-        .await?
-        != 0
-    {
-        log::error!("Username already exists: {}", new_user.username);
-        Err(ApiError::UsernameAlreadyExists(new_user.username))
-    } else {
-        log::info!("Hashing password for user: {}", new_user.username);
-        let password_hash = bcrypt::hash(&new_user.password, 4)?;
-
-        log::info!("Getting a new uuid for user: {}", new_user.username);
-        let uuid = loop {
-            let uuid = Uuid::new_v4();
-            if UserEntity::find()
-                .filter(UserColumn::Uuid.eq(uuid))
-                .count(conn)
-                .await?
-                == 0
-            {
-                break uuid;
-            }
-        };
-        log::info!("New uuid for user: {}", new_user.username);
-
-        UserSchema::from_active_model(
-            conn,
-            UserActiveModel {
-                uuid: Set(uuid),
-                first_name: Set(new_user.first_name),
-                last_name: Set(new_user.last_name),
-                profile_image_path: Set("/profiles/default".to_owned()),
-                username: Set(new_user.username),
-                password_hash: Set(password_hash),
-                created_at: Set(chrono::Utc::now().naive_utc()),
-                ..Default::default()
-            }
-            .save(conn)
-            .await?,
-        )
-        .await
+    check_username_availability(conn, &new_user.username, None).await?;
+    if let Some(email) = &new_user.email {
+        check_email_availability(conn, email).await?;
     }
+
+    log::info!("Hashing password for user: {}", new_user.username);
+    let password_hash = password_hasher.hash_async(&new_user.password).await?;
+
+    log::info!("Getting a new uuid for user: {}", new_user.username);
+    let uuid = loop {
+        let uuid = Uuid::new_v4();
+        if UserEntity::find()
+            .filter(UserColumn::Uuid.eq(uuid))
+            .count(conn)
+            .await?
+            == 0
+        {
+            break uuid;
+        }
+    };
+    log::info!("New uuid for user: {}", new_user.username);
+
+    UserSchema::owner_from_active_model(
+        conn,
+        UserActiveModel {
+            uuid: Set(uuid),
+            first_name: Set(new_user.first_name),
+            last_name: Set(new_user.last_name),
+            profile_image_path: Set("/profiles/default".to_owned()),
+            username: Set(new_user.username.clone()),
+            email: Set(new_user.email.clone()),
+            password_hash: Set(password_hash),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .save(conn)
+        .await
+        .map_err(|err| user_unique_violation(err, &new_user.username, new_user.email.as_deref()))?,
+    )
+    .await
 }
 
-/// Signin a user and returns a JWT token with a refresh token.
-pub async fn signin_user(user: UserSchema, secret_key: &str) -> ApiResult<UserSigninSchema> {
+/// Signin a user and returns a JWT token with a refresh token. The signin creates a new session.
+pub async fn signin_user(
+    conn: &sea_orm::DatabaseConnection,
+    user: UserSchema,
+    auth_config: &AuthConfig,
+    new_session: NewSessionSchema,
+) -> ApiResult<UserSigninSchema> {
     log::info!("Logging in user: {}", user.username);
+
+    log::info!("Deleting the expired refresh tokens and sessions of the user");
+    RefreshTokenEntity::delete_many()
+        .filter(RefreshTokenColumn::UserUuid.eq(user.uuid))
+        .filter(RefreshTokenColumn::ExpiresAt.lte(chrono::Utc::now().naive_utc()))
+        .exec(conn)
+        .await?;
+    // The session without refresh tokens is expired
+    SessionEntity::delete_many()
+        .filter(SessionColumn::UserUuid.eq(user.uuid))
+        .filter(
+            SessionColumn::Uuid.not_in_subquery(
+                sea_orm::sea_query::Query::select()
+                    .column(RefreshTokenColumn::Family)
+                    .from(RefreshTokenEntity)
+                    .to_owned(),
+            ),
+        )
+        .exec(conn)
+        .await?;
+
+    let session = SessionActiveModel {
+        uuid: Set(Uuid::new_v4()),
+        user_uuid: Set(user.uuid),
+        user_agent: Set(new_session.user_agent),
+        ip: Set(new_session.ip),
+        created_at: Set(chrono::Utc::now().naive_utc()),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?;
+
+    let failed_signins = take_failed_signins(conn, &user.username).await?;
+    Ok(UserSigninSchema {
+        failed_signins,
+        ..issue_tokens(conn, user, auth_config, session.uuid).await?
+    })
+}
+
+/// Returns an error if the username is locked because of the failed signin attempts.
+pub(crate) async fn check_account_lock(
+    conn: &sea_orm::DatabaseConnection,
+    auth_config: &AuthConfig,
+    username: &str,
+) -> ApiResult<()> {
     let now = chrono::Utc::now().naive_utc();
-    let jwt_exp = if matches!(std::env::var("XORS_API_TEST"), Ok(status) if status == "true") {
-        (now + chrono::Duration::seconds(2)).timestamp()
-    } else {
-        (now + chrono::Duration::hours(1)).timestamp()
-    };
+    let failed_signins = FailedSigninEntity::find()
+        .filter(FailedSigninColumn::Username.eq(username))
+        .filter(FailedSigninColumn::AttemptedAt.gt(now - Duration::hours(FAILED_SIGNINS_TTL_HOURS)))
+        .order_by_desc(FailedSigninColumn::AttemptedAt);
 
-    let refresh_exp = if matches!(std::env::var("XORS_API_TEST"), Ok(status) if status == "true") {
-        (now + chrono::Duration::seconds(5)).timestamp()
-    } else {
-        (now + chrono::Duration::hours(3)).timestamp()
+    let Some(lockout_duration) =
+        auth_config.lockout_duration_after(failed_signins.clone().count(conn).await?)
+    else {
+        return Ok(());
     };
+    let last_attempted_at = failed_signins
+        .one(conn)
+        .await?
+        .expect("The username has failed attempts")
+        .attempted_at;
 
-    let refresh_active_after = if matches!(std::env::var("XORS_API_TEST"), Ok(status) if status == "true")
-    {
-        Some((now + chrono::Duration::seconds(3)).timestamp())
+    let retry_after = (last_attempted_at + lockout_duration - now).num_seconds();
+    if retry_after > 0 {
+        log::warn!("The account `{username}` is locked for {retry_after} seconds");
+        Err(ApiError::AccountLocked { retry_after })
     } else {
-        Some((now + chrono::Duration::minutes(58)).timestamp())
-    };
+        Ok(())
+    }
+}
 
-    let jwt = jsonwebtoken::encode(
-        &Header::default(),
-        &JwtClaims::new(user.uuid, None, jwt_exp),
-        &jsonwebtoken::EncodingKey::from_secret(secret_key.as_bytes()),
-    )
-    .expect("JWT encode failed");
+/// Records a failed signin attempt of the username. The username may not exist, so
+/// the lockout can't be used to find the existing usernames.
+pub(crate) async fn record_failed_signin(
+    conn: &sea_orm::DatabaseConnection,
+    username: String,
+    new_session: NewSessionSchema,
+) -> ApiResult<()> {
+    log::info!("Recording a failed signin attempt of: {username}");
+    let now = chrono::Utc::now().naive_utc();
 
-    let refresh_token = jsonwebtoken::encode(
-        &Header::default(),
-        &JwtClaims::new(user.uuid, refresh_active_after, refresh_exp),
-        &jsonwebtoken::EncodingKey::from_secret(secret_key.as_bytes()),
-    )
-    .expect("JWT encode failed");
+    FailedSigninEntity::delete_many()
+        .filter(
+            FailedSigninColumn::AttemptedAt.lte(now - Duration::hours(FAILED_SIGNINS_TTL_HOURS)),
+        )
+        .exec(conn)
+        .await?;
+    FailedSigninActiveModel {
+        username: Set(username),
+        user_agent: Set(new_session.user_agent),
+        ip: Set(new_session.ip),
+        attempted_at: Set(now),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?;
+    Ok(())
+}
+
+/// Deletes the failed signin attempts of the username, and returns them to warn the user.
+async fn take_failed_signins(
+    conn: &sea_orm::DatabaseConnection,
+    username: &str,
+) -> ApiResult<Option<FailedSigninsSchema>> {
+    let failed_signins = FailedSigninEntity::find()
+        .filter(FailedSigninColumn::Username.eq(username))
+        .filter(
+            FailedSigninColumn::AttemptedAt
+                .gt(chrono::Utc::now().naive_utc() - Duration::hours(FAILED_SIGNINS_TTL_HOURS)),
+        )
+        .order_by_desc(FailedSigninColumn::AttemptedAt)
+        .all(conn)
+        .await?;
+    FailedSigninEntity::delete_many()
+        .filter(FailedSigninColumn::Username.eq(username))
+        .exec(conn)
+        .await?;
+
+    Ok(failed_signins
+        .first()
+        .map(|last_failed_signin| FailedSigninsSchema {
+            count: failed_signins.len() as u64,
+            last_attempted_at: last_failed_signin.attempted_at,
+            last_ip: last_failed_signin.ip.clone(),
+            last_user_agent: last_failed_signin.user_agent.clone(),
+        }))
+}
+
+/// Returns a JWT token with a refresh token of the given session.
+pub(crate) async fn issue_tokens(
+    conn: &sea_orm::DatabaseConnection,
+    user: UserSchema,
+    auth_config: &AuthConfig,
+    session: Uuid,
+) -> ApiResult<UserSigninSchema> {
+    let now = chrono::Utc::now().naive_utc();
+    let jwt_exp = (now + auth_config.jwt_ttl).timestamp();
+    let refresh_exp = (now + auth_config.refresh_token_ttl).timestamp();
+    let refresh_active_after = Some((now + auth_config.refresh_token_active_after).timestamp());
+
+    let token_version = get_user(conn, user.uuid).await?.token_version.unwrap();
+    let jti = Uuid::new_v4();
+    RefreshTokenActiveModel {
+        jti: Set(jti),
+        family: Set(session),
+        user_uuid: Set(user.uuid),
+        created_at: Set(now),
+        expires_at: Set(now + auth_config.refresh_token_ttl),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?;
+
+    let jwt = auth_config.encode_token(&JwtClaims::new(
+        user.uuid,
+        None,
+        None,
+        Some(session),
+        token_version,
+        jwt_exp,
+    ));
+    let refresh_token = auth_config.encode_token(&JwtClaims::new(
+        user.uuid,
+        refresh_active_after,
+        Some(jti),
+        Some(session),
+        token_version,
+        refresh_exp,
+    ));
 
     Ok(UserSigninSchema {
         user,
         jwt,
         refresh_token,
+        failed_signins: None,
+        recovery_codes: None,
     })
 }
 
+/// Marks the refresh token as used and returns its session, so a new refresh token can be issued in it.
+///
+/// If the refresh token has already been used, the whole session will be revoked, because
+/// this means that the refresh token has been stolen.
+pub(crate) async fn rotate_refresh_token(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    jti: Uuid,
+) -> ApiResult<Uuid> {
+    log::info!("Rotating refresh token");
+
+    let refresh_token = RefreshTokenEntity::find()
+        .filter(RefreshTokenColumn::Jti.eq(jti))
+        .filter(RefreshTokenColumn::UserUuid.eq(user_uuid))
+        .one(conn)
+        .await?
+        .ok_or(ApiError::RevokedRefreshToken)?;
+
+    if refresh_token.revoked_at.is_some() {
+        return Err(ApiError::RevokedRefreshToken);
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    // Only one request can use the refresh token, even if two requests come at the same time.
+    let used = RefreshTokenEntity::update_many()
+        .col_expr(RefreshTokenColumn::UsedAt, now.into())
+        .filter(RefreshTokenColumn::Jti.eq(jti))
+        .filter(RefreshTokenColumn::UsedAt.is_null())
+        .filter(RefreshTokenColumn::RevokedAt.is_null())
+        .exec(conn)
+        .await?
+        .rows_affected
+        == 1;
+
+    if used {
+        SessionEntity::update_many()
+            .col_expr(SessionColumn::LastRefreshAt, now.into())
+            .filter(SessionColumn::Uuid.eq(refresh_token.family))
+            .exec(conn)
+            .await?;
+        Ok(refresh_token.family)
+    } else {
+        log::warn!(
+            "Refresh token reuse detected, revoking the session of user: {}",
+            user_uuid
+        );
+        revoke_sessions(conn, SessionColumn::Uuid.eq(refresh_token.family)).await?;
+        Err(ApiError::ReusedRefreshToken)
+    }
+}
+
+/// Returns whether the session is active or not.
+pub(crate) async fn is_active_session(
+    conn: &sea_orm::DatabaseConnection,
+    session: Uuid,
+) -> ApiResult<bool> {
+    Ok(SessionEntity::find()
+        .filter(SessionColumn::Uuid.eq(session))
+        .filter(SessionColumn::RevokedAt.is_null())
+        .count(conn)
+        .await?
+        != 0)
+}
+
+/// Returns the user's active sessions, newest first. With the expiration date of each session.
+pub(crate) async fn get_user_sessions(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+) -> ApiResult<Vec<(SessionModel, chrono::NaiveDateTime)>> {
+    log::info!("Getting the sessions of user: {}", user_uuid);
+
+    let refresh_tokens = RefreshTokenEntity::find()
+        .filter(RefreshTokenColumn::UserUuid.eq(user_uuid))
+        .filter(RefreshTokenColumn::RevokedAt.is_null())
+        .filter(RefreshTokenColumn::ExpiresAt.gt(chrono::Utc::now().naive_utc()))
+        .all(conn)
+        .await?;
+
+    Ok(SessionEntity::find()
+        .filter(SessionColumn::UserUuid.eq(user_uuid))
+        .filter(SessionColumn::RevokedAt.is_null())
+        .order_by(SessionColumn::CreatedAt, Order::Desc)
+        .all(conn)
+        .await?
+        .into_iter()
+        .filter_map(|session| {
+            // The session expires with its last refresh token
+            refresh_tokens
+                .iter()
+                .filter(|token| token.family == session.uuid)
+                .map(|token| token.expires_at)
+                .max()
+                .map(|expires_at| (session, expires_at))
+        })
+        .collect())
+}
+
+/// Revoke the user's session, the session's tokens will be rejected.
+pub(crate) async fn revoke_session(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    session: Uuid,
+) -> ApiResult<()> {
+    log::info!("Revoking session: {}", session);
+
+    if revoke_sessions(
+        conn,
+        Condition::all()
+            .add(SessionColumn::Uuid.eq(session))
+            .add(SessionColumn::UserUuid.eq(user_uuid)),
+    )
+    .await?
+        == 0
+    {
+        Err(ApiError::SessionNotFound)
+    } else {
+        Ok(())
+    }
+}
+
+/// Logout the user from all the devices. All the issued tokens will be rejected.
+pub(crate) async fn logout_all(
+    conn: &sea_orm::DatabaseConnection,
+    user: UserModel,
+) -> ApiResult<()> {
+    log::info!("Logging out user from all the devices: {}", user.username);
+
+    let user_uuid = user.uuid;
+    let token_version = user.token_version + 1;
+    let mut user = user.into_active_model();
+    user.token_version = Set(token_version);
+    user.update(conn).await?;
+
+    revoke_sessions(conn, SessionColumn::UserUuid.eq(user_uuid)).await?;
+    Ok(())
+}
+
+/// Starts the user's two-factor authentication enrollment. Returns the new TOTP secret.
+///
+/// The two-factor authentication will not be enabled until a code of the secret is verified.
+pub(crate) async fn enroll_totp(
+    conn: &sea_orm::DatabaseConnection,
+    user: UserModel,
+) -> ApiResult<Vec<u8>> {
+    log::info!(
+        "Enrolling two-factor authentication for user: {}",
+        user.username
+    );
+
+    if user.totp_enabled {
+        return Err(ApiError::TotpAlreadyEnabled);
+    }
+
+    let secret = totp::generate_secret();
+    let mut user = user.into_active_model();
+    user.totp_secret = Set(Some(crate::BASE_64_ENGINE.encode(&secret)));
+    user.totp_last_step = Set(None);
+    user.update(conn).await?;
+
+    Ok(secret)
+}
+
+/// Enables the user's two-factor authentication if the code is valid. Returns the new recovery codes.
+pub(crate) async fn enable_totp(
+    conn: &sea_orm::DatabaseConnection,
+    user: UserModel,
+    code: &str,
+    password_hasher: &PasswordHasher,
+) -> ApiResult<Vec<String>> {
+    log::info!(
+        "Enabling two-factor authentication for user: {}",
+        user.username
+    );
+
+    if user.totp_enabled {
+        return Err(ApiError::TotpAlreadyEnabled);
+    }
+    if !verify_totp_code(conn, &user, code).await? {
+        return Err(ApiError::InvalidTotpCode);
+    }
+
+    let user_uuid = user.uuid;
+    let mut user = user.into_active_model();
+    user.totp_enabled = Set(true);
+    user.update(conn).await?;
+
+    RecoveryCodeEntity::delete_many()
+        .filter(RecoveryCodeColumn::UserUuid.eq(user_uuid))
+        .exec(conn)
+        .await?;
+    let recovery_codes = totp::generate_recovery_codes(RECOVERY_CODES_COUNT);
+    let recovery_codes_models = password_hasher
+        .hash_codes_async(&recovery_codes)
+        .await?
+        .into_iter()
+        .map(|code_hash| RecoveryCodeActiveModel {
+            user_uuid: Set(user_uuid),
+            code_hash: Set(code_hash),
+            ..Default::default()
+        });
+    RecoveryCodeEntity::insert_many(recovery_codes_models)
+        .exec(conn)
+        .await?;
+
+    Ok(recovery_codes)
+}
+
+/// Disables the user's two-factor authentication, and deletes its secret and recovery codes.
+pub(crate) async fn disable_totp(
+    conn: &sea_orm::DatabaseConnection,
+    user: UserModel,
+) -> ApiResult<()> {
+    log::info!(
+        "Disabling two-factor authentication for user: {}",
+        user.username
+    );
+
+    if !user.totp_enabled {
+        return Err(ApiError::TotpNotEnabled);
+    }
+
+    let user_uuid = user.uuid;
+    let mut user = user.into_active_model();
+    user.totp_enabled = Set(false);
+    user.totp_secret = Set(None);
+    user.totp_last_step = Set(None);
+    user.update(conn).await?;
+
+    RecoveryCodeEntity::delete_many()
+        .filter(RecoveryCodeColumn::UserUuid.eq(user_uuid))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+/// Verifies the user's second factor, the authenticator app code or an unused recovery code.
+pub(crate) async fn verify_second_factor(
+    conn: &sea_orm::DatabaseConnection,
+    user: &UserModel,
+    code: &str,
+) -> ApiResult<()> {
+    if !user.totp_enabled {
+        return Err(ApiError::TotpNotEnabled);
+    }
+    if verify_totp_code(conn, user, code).await? || use_recovery_code(conn, user, code).await? {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidTotpCode)
+    }
+}
+
+/// Verifies the authenticator app code. A valid code can't be used again.
+async fn verify_totp_code(
+    conn: &sea_orm::DatabaseConnection,
+    user: &UserModel,
+    code: &str,
+) -> ApiResult<bool> {
+    let secret = crate::BASE_64_ENGINE
+        .decode(user.totp_secret.as_ref().ok_or(ApiError::TotpNotEnrolled)?)
+        .map_err(|_| ApiError::InternalServer)?;
+
+    let Some(step) = totp::verify(&secret, code.trim(), user.totp_last_step) else {
+        return Ok(false);
+    };
+    // The condition prevents using the same code in concurrent requests
+    Ok(UserEntity::update_many()
+        .col_expr(UserColumn::TotpLastStep, step.into())
+        .filter(UserColumn::Uuid.eq(user.uuid))
+        .filter(
+            Condition::any()
+                .add(UserColumn::TotpLastStep.is_null())
+                .add(UserColumn::TotpLastStep.lt(step)),
+        )
+        .exec(conn)
+        .await?
+        .rows_affected
+        != 0)
+}
+
+/// Marks the recovery code as used if it's one of the user's unused recovery codes.
+async fn use_recovery_code(
+    conn: &sea_orm::DatabaseConnection,
+    user: &UserModel,
+    code: &str,
+) -> ApiResult<bool> {
+    let code = code.trim().to_lowercase();
+    let recovery_codes = RecoveryCodeEntity::find()
+        .filter(RecoveryCodeColumn::UserUuid.eq(user.uuid))
+        .filter(RecoveryCodeColumn::UsedAt.is_null())
+        .all(conn)
+        .await?;
+
+    let code_hashes = recovery_codes
+        .iter()
+        .map(|recovery_code| recovery_code.code_hash.clone())
+        .collect();
+    let Some(index) = PasswordHasher::find_code_hash_async(&code, code_hashes).await? else {
+        return Ok(false);
+    };
+    Ok(RecoveryCodeEntity::update_many()
+        .col_expr(
+            RecoveryCodeColumn::UsedAt,
+            chrono::Utc::now().naive_utc().into(),
+        )
+        .filter(RecoveryCodeColumn::Id.eq(recovery_codes[index].id))
+        .filter(RecoveryCodeColumn::UsedAt.is_null())
+        .exec(conn)
+        .await?
+        .rows_affected
+        != 0)
+}
+
+/// Revoke the active sessions that match the condition, with their refresh tokens.
+/// Returns the number of the revoked sessions.
+async fn revoke_sessions(
+    conn: &sea_orm::DatabaseConnection,
+    condition: impl sea_orm::sea_query::IntoCondition,
+) -> ApiResult<u64> {
+    let now = chrono::Utc::now().naive_utc();
+    let sessions: Vec<Uuid> = SessionEntity::find()
+        .filter(condition)
+        .filter(SessionColumn::RevokedAt.is_null())
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|session| session.uuid)
+        .collect();
+
+    if sessions.is_empty() {
+        return Ok(0);
+    }
+
+    SessionEntity::update_many()
+        .col_expr(SessionColumn::RevokedAt, now.into())
+        .filter(SessionColumn::Uuid.is_in(sessions.clone()))
+        .exec(conn)
+        .await?;
+    RefreshTokenEntity::update_many()
+        .col_expr(RefreshTokenColumn::RevokedAt, now.into())
+        .filter(RefreshTokenColumn::Family.is_in(sessions.clone()))
+        .filter(RefreshTokenColumn::RevokedAt.is_null())
+        .exec(conn)
+        .await?;
+    Ok(sessions.len() as u64)
+}
+
 /// Get a user from the database by uuid.
 pub async fn get_user(
     conn: &sea_orm::DatabaseConnection,
@@ -151,6 +654,397 @@ pub async fn get_user_by_username(
         .ok_or(ApiError::UserNotFound)
 }
 
+/// Get a user that isn't deleted. The deleted users are hidden from the other users until they restore their accounts.
+pub async fn get_active_user(
+    conn: &sea_orm::DatabaseConnection,
+    uuid: Uuid,
+) -> ApiResult<UserActiveModel> {
+    let user = get_user(conn, uuid).await?;
+    if user.deleted_at.as_ref().is_some() {
+        return Err(ApiError::UserNotFound);
+    }
+    Ok(user)
+}
+
+/// Deletes the user's account, it can be restored until the grace period ends. The user is logged out from all the devices.
+pub(crate) async fn delete_user(
+    conn: &sea_orm::DatabaseConnection,
+    user: UserModel,
+) -> ApiResult<()> {
+    log::info!("Deleting user: {}", user.username);
+
+    // The conditional update prevents concurrent deletions from restarting the grace period
+    let result = UserEntity::update_many()
+        .col_expr(
+            UserColumn::DeletedAt,
+            sea_orm::sea_query::Expr::value(chrono::Utc::now().naive_utc()),
+        )
+        .filter(UserColumn::Uuid.eq(user.uuid))
+        .filter(UserColumn::DeletedAt.is_null())
+        .exec(conn)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(ApiError::AccountAlreadyDeleted);
+    }
+    logout_all(conn, user).await
+}
+
+/// Restores the user's deleted account.
+pub(crate) async fn restore_user(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+) -> ApiResult<UserActiveModel> {
+    log::info!("Restoring user: {user_uuid}");
+
+    let result = UserEntity::update_many()
+        .col_expr(
+            UserColumn::DeletedAt,
+            sea_orm::sea_query::Expr::value(Option::<chrono::NaiveDateTime>::None),
+        )
+        .filter(UserColumn::Uuid.eq(user_uuid))
+        .filter(UserColumn::DeletedAt.is_not_null())
+        .filter(UserColumn::PurgedAt.is_null())
+        .exec(conn)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(ApiError::AccountNotDeleted);
+    }
+    get_user(conn, user_uuid).await
+}
+
+/// Purges the accounts that deleted before the grace period. Returns the number of the purged accounts.
+///
+/// The purged account is anonymized instead of removed, its username and first name are replaced with a
+/// placeholder name like `deleted-<uuid-prefix>`, that is frozen in the player's games too.
+pub async fn purge_deleted_users(
+    conn: &sea_orm::DatabaseConnection,
+    grace_period: Duration,
+) -> ApiResult<u64> {
+    use sea_orm::sea_query::Expr;
+
+    let deleted_before = chrono::Utc::now().naive_utc() - grace_period;
+    let users = UserEntity::find()
+        .filter(UserColumn::DeletedAt.lte(deleted_before))
+        .filter(UserColumn::PurgedAt.is_null())
+        .all(conn)
+        .await?;
+
+    let mut purged = 0;
+    for user in users {
+        log::info!("Purging user: {}", user.username);
+        // The placeholder can't be taken by a user, the usernames can't contain `-`
+        let placeholder_name = format!("deleted-{}", &user.uuid.simple().to_string()[..16]);
+
+        let txn = conn.begin().await?;
+        // The conditional update prevents purging the account if it has been restored meanwhile
+        let result = UserEntity::update_many()
+            .col_expr(UserColumn::Username, Expr::value(placeholder_name.clone()))
+            .col_expr(UserColumn::FirstName, Expr::value(placeholder_name.clone()))
+            .col_expr(UserColumn::LastName, Expr::value(Option::<String>::None))
+            .col_expr(UserColumn::Email, Expr::value(Option::<String>::None))
+            .col_expr(UserColumn::EmailVerified, Expr::value(false))
+            // An empty hash never matches a password
+            .col_expr(UserColumn::PasswordHash, Expr::value(String::new()))
+            .col_expr(
+                UserColumn::ProfileImagePath,
+                Expr::value("/profiles/default"),
+            )
+            .col_expr(UserColumn::TotpSecret, Expr::value(Option::<String>::None))
+            .col_expr(UserColumn::TotpEnabled, Expr::value(false))
+            .col_expr(UserColumn::TotpLastStep, Expr::value(Option::<i64>::None))
+            .col_expr(
+                UserColumn::TokenVersion,
+                Expr::col(UserColumn::TokenVersion).add(1),
+            )
+            .col_expr(
+                UserColumn::PurgedAt,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(UserColumn::Uuid.eq(user.uuid))
+            .filter(UserColumn::DeletedAt.lte(deleted_before))
+            .filter(UserColumn::PurgedAt.is_null())
+            .exec(&txn)
+            .await?;
+        if result.rows_affected == 0 {
+            txn.rollback().await?;
+            continue;
+        }
+        for (player_column, name_column) in [
+            (GameColumn::XPlayer, GameColumn::XPlayerName),
+            (GameColumn::OPlayer, GameColumn::OPlayerName),
+        ] {
+            GameEntity::update_many()
+                .col_expr(name_column, Expr::value(placeholder_name.clone()))
+                .filter(player_column.eq(user.uuid))
+                .exec(&txn)
+                .await?;
+        }
+        FailedSigninEntity::delete_many()
+            .filter(FailedSigninColumn::Username.eq(&user.username))
+            .exec(&txn)
+            .await?;
+        // The old usernames are kept, they are released when their reservations end
+        RefreshTokenEntity::delete_many()
+            .filter(RefreshTokenColumn::UserUuid.eq(user.uuid))
+            .exec(&txn)
+            .await?;
+        SessionEntity::delete_many()
+            .filter(SessionColumn::UserUuid.eq(user.uuid))
+            .exec(&txn)
+            .await?;
+        RecoveryCodeEntity::delete_many()
+            .filter(RecoveryCodeColumn::UserUuid.eq(user.uuid))
+            .exec(&txn)
+            .await?;
+        PasswordRecoveryCodeEntity::delete_many()
+            .filter(PasswordRecoveryCodeColumn::UserUuid.eq(user.uuid))
+            .exec(&txn)
+            .await?;
+        PasswordResetTokenEntity::delete_many()
+            .filter(PasswordResetTokenColumn::UserUuid.eq(user.uuid))
+            .exec(&txn)
+            .await?;
+        UserIdentityEntity::delete_many()
+            .filter(UserIdentityColumn::UserUuid.eq(user.uuid))
+            .exec(&txn)
+            .await?;
+        PrivacySettingEntity::delete_many()
+            .filter(PrivacySettingColumn::UserUuid.eq(user.uuid))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+
+        if let Err(err) = utils::remove_profile_image(&user.uuid.to_string()) {
+            log::error!("Failed to remove the profile image of {}: {err}", user.uuid);
+        }
+        purged += 1;
+    }
+    Ok(purged)
+}
+
+/// Get the user's privacy settings. The user without settings has the default settings.
+pub async fn get_privacy_settings(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+) -> ApiResult<PrivacySettingsSchema> {
+    Ok(PrivacySettingEntity::find()
+        .filter(PrivacySettingColumn::UserUuid.eq(user_uuid))
+        .one(conn)
+        .await?
+        .map(PrivacySettingsSchema::from)
+        .unwrap_or_default())
+}
+
+/// Updates the user's privacy settings. Returns the new settings.
+pub(crate) async fn update_privacy_settings(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    settings: PrivacySettingsSchema,
+) -> ApiResult<PrivacySettingsSchema> {
+    log::info!("Updating the privacy settings of user: {user_uuid}");
+
+    PrivacySettingEntity::insert(PrivacySettingActiveModel {
+        user_uuid: Set(user_uuid),
+        hide_real_name: Set(settings.hide_real_name),
+        hide_game_history: Set(settings.hide_game_history),
+        friends_only: Set(settings.friends_only),
+        ..Default::default()
+    })
+    .on_conflict(
+        sea_orm::sea_query::OnConflict::column(PrivacySettingColumn::UserUuid)
+            .update_columns([
+                PrivacySettingColumn::HideRealName,
+                PrivacySettingColumn::HideGameHistory,
+                PrivacySettingColumn::FriendsOnly,
+            ])
+            .to_owned(),
+    )
+    .exec(conn)
+    .await?;
+    Ok(settings)
+}
+
+/// Returns an error if the user's games are hidden from the requester. The user can always see his games.
+pub async fn check_game_history_visibility(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    requester: Option<Uuid>,
+) -> ApiResult<()> {
+    if requester == Some(user_uuid) {
+        return Ok(());
+    }
+    let settings = get_privacy_settings(conn, user_uuid).await?;
+    if settings.hide_game_history || settings.friends_only {
+        return Err(ApiError::PrivateProfile);
+    }
+    Ok(())
+}
+
+/// A searched user with its match rank and privacy settings.
+struct SearchedUser {
+    rank: i32,
+    user: UserModel,
+    settings: PrivacySettingsSchema,
+}
+
+impl sea_orm::FromQueryResult for SearchedUser {
+    fn from_query_result(res: &sea_orm::QueryResult, pre: &str) -> Result<Self, sea_orm::DbErr> {
+        Ok(Self {
+            rank: res.try_get(pre, "search_rank")?,
+            user: UserModel::from_query_result(res, pre)?,
+            settings: PrivacySettingsSchema {
+                hide_real_name: res.try_get(pre, "search_hide_real_name")?,
+                hide_game_history: res.try_get(pre, "search_hide_game_history")?,
+                friends_only: res.try_get(pre, "search_friends_only")?,
+            },
+        })
+    }
+}
+
+/// Search the active users by their username and first and last name, returns the users with their match rank
+/// and the privacy settings that applied to the requester.
+///
+/// The username prefix matches are ranked `0`, the name prefix matches `1` and the fuzzy matches `2`,
+/// the users are sorted by the rank then the username. The users that hide their real name are matched
+/// by their username only, and the private users are excluded, except for the requester.
+///
+/// There is no users blocking yet, so the blocked users are not excluded.
+pub async fn search_users(
+    conn: &sea_orm::DatabaseConnection,
+    query: &str,
+    cursor: Option<UsersCursor>,
+    limit: u64,
+    requester: Option<Uuid>,
+) -> ApiResult<Vec<(i32, UserModel, PrivacySettingsSchema)>> {
+    use sea_orm::sea_query::{Expr, Func, JoinType};
+
+    log::info!("Searching users");
+
+    let query = query.trim().to_lowercase();
+    let escaped_query = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    // Same expressions as the search indexes, so they are used
+    let username = || Expr::expr(Func::lower(Expr::col((UserEntity, UserColumn::Username))));
+    let name = || {
+        Expr::expr(Expr::cust(
+            r#"LOWER("user"."first_name" || ' ' || COALESCE("user"."last_name", ''))"#,
+        ))
+    };
+    // The joined privacy setting, the users without settings and the requester himself have it disabled
+    let setting = |column: PrivacySettingColumn| {
+        let enabled = Expr::expr(Func::coalesce([
+            Expr::col((PrivacySettingEntity, column)).into(),
+            Expr::val(false).into(),
+        ]));
+        match requester {
+            Some(requester) => {
+                Expr::expr(Expr::case(UserColumn::Uuid.eq(requester), false).finally(enabled))
+            }
+            None => enabled,
+        }
+    };
+
+    let username_prefix = username().like(format!("{escaped_query}%"));
+    let name_visible = setting(PrivacySettingColumn::HideRealName).eq(false);
+    let name_prefix = Condition::any()
+        .add(name().like(format!("{escaped_query}%")))
+        .add(name().like(format!("% {escaped_query}%")));
+    let rank = Expr::case(username_prefix.clone(), 0)
+        .case(
+            Condition::all()
+                .add(name_visible.clone())
+                .add(name_prefix.clone()),
+            1,
+        )
+        .finally(2);
+
+    let mut condition = Condition::all()
+        .add(UserColumn::DeletedAt.is_null())
+        .add(setting(PrivacySettingColumn::FriendsOnly).eq(false))
+        .add(
+            Condition::any()
+                .add(username_prefix)
+                .add(Expr::cust_with_values(
+                    r#"LOWER("user"."username") % $1"#,
+                    [query.clone()],
+                ))
+                .add(
+                    Condition::all().add(name_visible).add(
+                        name_prefix.add(Expr::cust_with_values(
+                            r#"$1 <% LOWER("user"."first_name" || ' ' || COALESCE("user"."last_name", ''))"#,
+                            [query.clone()],
+                        )),
+                    ),
+                ),
+        );
+    if let Some(cursor) = cursor {
+        condition = condition.add(
+            Condition::any()
+                .add(Expr::expr(rank.clone()).gt(cursor.rank))
+                .add(
+                    Expr::expr(rank.clone())
+                        .eq(cursor.rank)
+                        .and(username().gt(cursor.username)),
+                ),
+        );
+    }
+
+    let mut select = UserEntity::find()
+        .column_as(Expr::expr(rank), "search_rank")
+        .column_as(
+            setting(PrivacySettingColumn::HideRealName),
+            "search_hide_real_name",
+        )
+        .column_as(
+            setting(PrivacySettingColumn::HideGameHistory),
+            "search_hide_game_history",
+        )
+        .column_as(
+            setting(PrivacySettingColumn::FriendsOnly),
+            "search_friends_only",
+        )
+        .filter(condition)
+        .order_by_asc(Expr::col(sea_orm::sea_query::Alias::new("search_rank")))
+        .order_by_asc(username())
+        .limit(limit);
+    sea_orm::QueryTrait::query(&mut select).join(
+        JoinType::LeftJoin,
+        PrivacySettingEntity,
+        Expr::col((PrivacySettingEntity, PrivacySettingColumn::UserUuid))
+            .equals((UserEntity, UserColumn::Uuid)),
+    );
+
+    Ok(select
+        .into_model::<SearchedUser>()
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|searched| (searched.rank, searched.user, searched.settings))
+        .collect())
+}
+
+/// Returns a condition that excludes the games of the players that hide their games from the requester.
+fn visible_games_condition(requester: Option<Uuid>) -> Condition {
+    let mut hidden_players = sea_orm::sea_query::Query::select();
+    hidden_players
+        .column(PrivacySettingColumn::UserUuid)
+        .from(PrivacySettingEntity)
+        .cond_where(
+            Condition::any()
+                .add(PrivacySettingColumn::HideGameHistory.eq(true))
+                .add(PrivacySettingColumn::FriendsOnly.eq(true)),
+        );
+    if let Some(requester) = requester {
+        hidden_players.and_where(PrivacySettingColumn::UserUuid.ne(requester));
+    }
+
+    Condition::all()
+        .add(GameColumn::XPlayer.not_in_subquery(hidden_players.clone()))
+        .add(GameColumn::OPlayer.not_in_subquery(hidden_players))
+}
+
 /// End a game in the database. This will set the `ended_at` column to the current time and remove the `board` column.
 pub async fn end_game(
     conn: &sea_orm::DatabaseConnection,
@@ -220,6 +1114,8 @@ pub async fn create_game(
         o_player: Set(o_player),
         x_start_chat: Set(false),
         o_start_chat: Set(false),
+        x_auto_plays: Set(0),
+        o_auto_plays: Set(0),
         board: Set(Board::default().to_string()),
         winner: Set(None),
         reason: Set(None),
@@ -252,18 +1148,197 @@ pub async fn get_game<const IS_END: bool>(
         .ok_or(ApiError::GameNotFound)
 }
 
-/// Returns lastest 10 games from the database.
-pub async fn get_lastest_games(conn: &sea_orm::DatabaseConnection) -> ApiResult<Vec<GameModel>> {
-    log::info!("Getting lastest games");
+/// Returns the ended games that match the filter, sorted by the creation date (newest first).
+///
+/// The games will start after the cursor if it's provided, and will be at most `limit` games.
+pub async fn get_games(
+    conn: &sea_orm::DatabaseConnection,
+    filter: &GamesFilterSchema,
+    cursor: Option<GamesCursor>,
+    limit: u64,
+    requester: Option<Uuid>,
+) -> ApiResult<Vec<GameModel>> {
+    log::info!("Getting games");
+
+    let mut condition = Condition::all()
+        .add(GameColumn::EndedAt.is_not_null())
+        .add(visible_games_condition(requester));
+    if let Some(player) = filter.player {
+        condition = condition.add(
+            GameColumn::XPlayer
+                .eq(player)
+                .or(GameColumn::OPlayer.eq(player)),
+        );
+        if let Some(opponent) = filter.opponent {
+            condition = condition.add(
+                Condition::any()
+                    .add(
+                        GameColumn::XPlayer
+                            .eq(player)
+                            .and(GameColumn::OPlayer.eq(opponent)),
+                    )
+                    .add(
+                        GameColumn::OPlayer
+                            .eq(player)
+                            .and(GameColumn::XPlayer.eq(opponent)),
+                    ),
+            );
+        }
+        condition = match filter.result {
+            Some(GameResult::Win) => condition.add(GameColumn::Winner.eq(player)),
+            Some(GameResult::Loss) => condition.add(
+                GameColumn::Winner
+                    .is_not_null()
+                    .and(GameColumn::Winner.ne(player)),
+            ),
+            Some(GameResult::Draw) => condition.add(GameColumn::Winner.is_null()),
+            None => condition,
+        };
+    }
+    if let Some(reason) = &filter.reason {
+        condition = condition.add(GameColumn::Reason.eq(reason.to_string()));
+    }
+    if let Some(from) = filter.from {
+        condition = condition.add(GameColumn::CreatedAt.gte(from));
+    }
+    if let Some(to) = filter.to {
+        condition = condition.add(GameColumn::CreatedAt.lte(to));
+    }
+    if let Some(cursor) = cursor {
+        condition = condition.add(
+            Condition::any()
+                .add(GameColumn::CreatedAt.lt(cursor.created_at))
+                .add(
+                    GameColumn::CreatedAt
+                        .eq(cursor.created_at)
+                        .and(GameColumn::Id.lt(cursor.id)),
+                ),
+        );
+    }
+
+    Ok(GameEntity::find()
+        .filter(condition)
+        .order_by(GameColumn::CreatedAt, Order::Desc)
+        .order_by(GameColumn::Id, Order::Desc)
+        .limit(limit)
+        .all(conn)
+        .await?)
+}
+
+/// Returns the ended games between the two users, sorted by the creation date (newest first).
+pub async fn get_shared_games(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    other_uuid: Uuid,
+) -> ApiResult<Vec<GameModel>> {
+    log::info!("Getting shared games");
 
     Ok(GameEntity::find()
+        .filter(
+            Condition::any()
+                .add(
+                    GameColumn::XPlayer
+                        .eq(user_uuid)
+                        .and(GameColumn::OPlayer.eq(other_uuid)),
+                )
+                .add(
+                    GameColumn::XPlayer
+                        .eq(other_uuid)
+                        .and(GameColumn::OPlayer.eq(user_uuid)),
+                ),
+        )
         .filter(GameColumn::EndedAt.is_not_null())
         .order_by(GameColumn::CreatedAt, Order::Desc)
-        .limit(10)
+        .order_by(GameColumn::Id, Order::Desc)
+        .all(conn)
+        .await?)
+}
+
+/// Returns all the ended games of the player, sorted by the creation date (oldest first).
+pub async fn get_player_games(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+) -> ApiResult<Vec<GameModel>> {
+    log::info!("Getting player games");
+
+    Ok(GameEntity::find()
+        .filter(
+            GameColumn::XPlayer
+                .eq(user_uuid)
+                .or(GameColumn::OPlayer.eq(user_uuid)),
+        )
+        .filter(GameColumn::EndedAt.is_not_null())
+        .order_by(GameColumn::CreatedAt, Order::Asc)
+        .order_by(GameColumn::Id, Order::Asc)
+        .all(conn)
+        .await?)
+}
+
+/// Get a page of the games that the player played, including the running games. Sorted by the id, oldest first.
+pub(crate) async fn get_player_games_after(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    after_id: Option<i32>,
+    limit: u64,
+) -> ApiResult<Vec<GameModel>> {
+    let mut games = GameEntity::find().filter(
+        GameColumn::XPlayer
+            .eq(user_uuid)
+            .or(GameColumn::OPlayer.eq(user_uuid)),
+    );
+    if let Some(after_id) = after_id {
+        games = games.filter(GameColumn::Id.gt(after_id));
+    }
+
+    Ok(games
+        .order_by(GameColumn::Id, Order::Asc)
+        .limit(limit)
+        .all(conn)
+        .await?)
+}
+
+/// Get the user's old usernames, newest first.
+pub(crate) async fn get_username_history(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+) -> ApiResult<Vec<UsernameHistoryModel>> {
+    Ok(UsernameHistoryEntity::find()
+        .filter(UsernameHistoryColumn::UserUuid.eq(user_uuid))
+        .order_by(UsernameHistoryColumn::ChangedAt, Order::Desc)
         .all(conn)
         .await?)
 }
 
+/// Get the identity providers accounts that linked to the user.
+pub(crate) async fn get_user_identities(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+) -> ApiResult<Vec<UserIdentityModel>> {
+    Ok(UserIdentityEntity::find()
+        .filter(UserIdentityColumn::UserUuid.eq(user_uuid))
+        .order_by(UserIdentityColumn::CreatedAt, Order::Asc)
+        .all(conn)
+        .await?)
+}
+
+/// Increase the auto plays of the symbol player in the game.
+pub async fn increase_auto_plays(
+    conn: &sea_orm::DatabaseConnection,
+    game: GameModel,
+    symbol: &XoSymbol,
+) -> ApiResult<()> {
+    log::info!("Increasing auto plays of game: {}", game.uuid);
+
+    let (x_auto_plays, o_auto_plays) = (game.x_auto_plays, game.o_auto_plays);
+    let mut game = game.into_active_model();
+    match symbol {
+        XoSymbol::X => game.x_auto_plays = Set(x_auto_plays + 1),
+        XoSymbol::O => game.o_auto_plays = Set(o_auto_plays + 1),
+    }
+    game.update(conn).await?;
+    Ok(())
+}
+
 /// Returns online games from the database.
 pub async fn get_online_games(conn: &sea_orm::DatabaseConnection) -> ApiResult<Vec<GameModel>> {
     log::info!("Getting online games");
@@ -275,46 +1350,580 @@ pub async fn get_online_games(conn: &sea_orm::DatabaseConnection) -> ApiResult<V
         .await?)
 }
 
+/// Scans all the games in the database, returns [`ApiError::CorruptedGame`] for each corrupted game.
+pub async fn scan_games(conn: &sea_orm::DatabaseConnection) -> ApiResult<Vec<ApiError>> {
+    log::info!("Scanning the games");
+
+    let mut pages = GameEntity::find()
+        .order_by(GameColumn::Id, Order::Asc)
+        .paginate(conn, 500);
+    let mut corrupted_games = Vec::new();
+
+    while let Some(games) = pages.fetch_and_next().await? {
+        corrupted_games.extend(games.iter().filter_map(|game| game.validate().err()));
+    }
+    Ok(corrupted_games)
+}
+
 /// Reset the user's password.
 pub(crate) async fn reset_password(
     conn: &sea_orm::DatabaseConnection,
     user: UserModel,
     new_password: &str,
+    password_hasher: &PasswordHasher,
 ) -> ApiResult<()> {
     log::info!("Resetting password for user: {}", user.username);
     let mut user = user.into_active_model();
 
-    let password_hash = bcrypt::hash(new_password, 4)?;
+    let password_hash = password_hasher.hash_async(new_password).await?;
     user.password_hash = Set(password_hash);
-    user.update(conn).await?;
+    let user = user.update(conn).await?;
+
+    // The old sessions and password reset tokens can't be trusted anymore.
+    PasswordResetTokenEntity::delete_many()
+        .filter(PasswordResetTokenColumn::UserUuid.eq(user.uuid))
+        .exec(conn)
+        .await?;
+    logout_all(conn, user).await?;
+
+    Ok(())
+}
+
+/// Returns an error if the username is used by another user, or reserved for the user that used it recently,
+/// regardless of its case. The user can take back his own reserved usernames.
+async fn check_username_availability(
+    conn: &sea_orm::DatabaseConnection,
+    username: &str,
+    user_uuid: Option<Uuid>,
+) -> ApiResult<()> {
+    let mut users = UserEntity::find().filter(
+        sea_orm::sea_query::Expr::expr(sea_orm::sea_query::Func::lower(
+            sea_orm::sea_query::Expr::col(UserColumn::Username),
+        ))
+        .eq(username.to_lowercase()),
+    );
+    let mut reservations = UsernameHistoryEntity::find()
+        .filter(
+            sea_orm::sea_query::Expr::expr(sea_orm::sea_query::Func::lower(
+                sea_orm::sea_query::Expr::col(UsernameHistoryColumn::Username),
+            ))
+            .eq(username.to_lowercase()),
+        )
+        .filter(UsernameHistoryColumn::ReservedUntil.gt(chrono::Utc::now().naive_utc()));
+    if let Some(user_uuid) = user_uuid {
+        // Changing the case of the username only will not conflict with the user himself
+        users = users.filter(UserColumn::Uuid.ne(user_uuid));
+        reservations = reservations.filter(UsernameHistoryColumn::UserUuid.ne(user_uuid));
+    }
+
+    if users.count(conn).await? != 0 {
+        log::error!("Username already exists: {username}");
+        return Err(ApiError::UsernameAlreadyExists(username.to_owned()));
+    }
+    if reservations.count(conn).await? != 0 {
+        log::error!("Username is reserved: {username}");
+        return Err(ApiError::UsernameReserved(username.to_owned()));
+    }
+    Ok(())
+}
+
+/// Changes the user's username. The old username is kept in the history, and reserved for him for a period.
+pub(crate) async fn change_username(
+    conn: &sea_orm::DatabaseConnection,
+    user: UserModel,
+    username: String,
+    auth_config: &AuthConfig,
+) -> ApiResult<UserActiveModel> {
+    log::info!("Changing the username of user: {}", user.username);
+
+    if user.username == username {
+        return Err(ApiError::UsernameNotChanged);
+    }
+    let now = chrono::Utc::now().naive_utc();
+    if let Some(last_change) = UsernameHistoryEntity::find()
+        .filter(UsernameHistoryColumn::UserUuid.eq(user.uuid))
+        .order_by_desc(UsernameHistoryColumn::ChangedAt)
+        .one(conn)
+        .await?
+    {
+        let retry_after =
+            (last_change.changed_at + auth_config.username_change_cooldown - now).num_seconds();
+        if retry_after > 0 {
+            return Err(ApiError::UsernameChangeCooldown { retry_after });
+        }
+    }
+    check_username_availability(conn, &username, Some(user.uuid)).await?;
+
+    UsernameHistoryActiveModel {
+        user_uuid: Set(user.uuid),
+        username: Set(user.username.clone()),
+        changed_at: Set(now),
+        reserved_until: Set(now + auth_config.username_reservation_ttl),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?;
+    let mut user = user.into_active_model();
+    user.username = Set(username.clone());
+    user.save(conn)
+        .await
+        .map_err(|err| user_unique_violation(err, &username, None))
+}
+
+/// Maps a violation of the user's unique indexes to its error, they catch the concurrent signups
+/// that passed the availability checks. The other errors are returned as is.
+fn user_unique_violation(err: sea_orm::DbErr, username: &str, email: Option<&str>) -> ApiError {
+    if let Some(sea_orm::SqlErr::UniqueConstraintViolation(message)) = err.sql_err() {
+        if let Some(email) = email.filter(|_| message.contains("idx-user-email")) {
+            return ApiError::EmailAlreadyExists(email.to_owned());
+        }
+        if message.contains("username") {
+            return ApiError::UsernameAlreadyExists(username.to_owned());
+        }
+    }
+    err.into()
+}
+
+/// Returns an error if the email is already used by another user, regardless of its case.
+async fn check_email_availability(
+    conn: &sea_orm::DatabaseConnection,
+    email: &str,
+) -> ApiResult<()> {
+    if UserEntity::find()
+        .filter(
+            sea_orm::sea_query::Expr::expr(sea_orm::sea_query::Func::lower(
+                sea_orm::sea_query::Expr::col(UserColumn::Email),
+            ))
+            .eq(email.to_lowercase()),
+        )
+        .count(conn)
+        .await?
+        != 0
+    {
+        log::error!("Email already exists: {email}");
+        return Err(ApiError::EmailAlreadyExists(email.to_owned()));
+    }
+    Ok(())
+}
+
+/// Updates the user's email, `None` removes it. A changed email is unverified. Returns the updated user.
+pub(crate) async fn update_email(
+    conn: &sea_orm::DatabaseConnection,
+    user: UserModel,
+    email: Option<String>,
+) -> ApiResult<UserModel> {
+    log::info!("Updating the email of user: {}", user.username);
+
+    if user.email == email {
+        return Ok(user);
+    }
+    if let Some(email) = &email {
+        // Changing the case of the email only will not conflict with the user himself
+        if user.email.as_deref().map(str::to_lowercase) != Some(email.to_lowercase()) {
+            check_email_availability(conn, email).await?;
+        }
+    }
+
+    let username = user.username.clone();
+    let mut user = user.into_active_model();
+    user.email = Set(email.clone());
+    user.email_verified = Set(false);
+    user.update(conn)
+        .await
+        .map_err(|err| user_unique_violation(err, &username, email.as_deref()))
+}
+
+/// Marks the user's email as verified, only if it's still the email that the verification token was issued for.
+pub(crate) async fn verify_email(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    email: &str,
+) -> ApiResult<()> {
+    log::info!("Verifying the email of user: {user_uuid}");
+
+    let user = get_user(conn, user_uuid)
+        .await
+        .map_err(|_| ApiError::InvalidEmailVerificationToken)?;
+    if !user
+        .email
+        .as_ref()
+        .as_deref()
+        .is_some_and(|user_email| user_email.eq_ignore_ascii_case(email))
+    {
+        return Err(ApiError::InvalidEmailVerificationToken);
+    }
+    if *user.email_verified.as_ref() {
+        return Err(ApiError::EmailAlreadyVerified);
+    }
 
+    let mut user = user;
+    user.email_verified = Set(true);
+    user.update(conn).await?;
     Ok(())
 }
 
-/// Update the user's profile image path. Returns the path of the new profile image.
-pub(crate) fn update_profile_image_path(
+/// Replaces the user's password recovery codes with new ones. Returns the new recovery codes.
+pub(crate) async fn generate_password_recovery_codes(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    password_hasher: &PasswordHasher,
+) -> ApiResult<Vec<String>> {
+    log::info!("Generating new password recovery codes");
+
+    PasswordRecoveryCodeEntity::delete_many()
+        .filter(PasswordRecoveryCodeColumn::UserUuid.eq(user_uuid))
+        .exec(conn)
+        .await?;
+    let recovery_codes = totp::generate_recovery_codes(PASSWORD_RECOVERY_CODES_COUNT);
+    let recovery_codes_models = password_hasher
+        .hash_codes_async(&recovery_codes)
+        .await?
+        .into_iter()
+        .map(|code_hash| PasswordRecoveryCodeActiveModel {
+            user_uuid: Set(user_uuid),
+            code_hash: Set(code_hash),
+            ..Default::default()
+        });
+    PasswordRecoveryCodeEntity::insert_many(recovery_codes_models)
+        .exec(conn)
+        .await?;
+
+    Ok(recovery_codes)
+}
+
+/// Exchanges one of the user's unused password recovery codes with a password reset token.
+/// Returns the token and its expiration date.
+pub(crate) async fn recover_password(
+    conn: &sea_orm::DatabaseConnection,
+    username: String,
+    recovery_code: &str,
+    auth_config: &AuthConfig,
+) -> ApiResult<(String, chrono::NaiveDateTime)> {
+    let user = get_user_by_username(conn, username)
+        .await
+        .map_err(|_| ApiError::InvalidRecoveryCredentials)?
+        .try_into_model()?;
+    log::info!("Recovering the password of user: {}", user.username);
+
+    if !use_password_recovery_code(conn, user.uuid, recovery_code).await? {
+        return Err(ApiError::InvalidRecoveryCredentials);
+    }
+    create_password_reset_token(conn, user.uuid, auth_config).await
+}
+
+/// Marks the password recovery code as used if it's one of the user's unused password recovery codes.
+async fn use_password_recovery_code(
+    conn: &sea_orm::DatabaseConnection,
     user_uuid: Uuid,
-    profile_image: Option<String>,
-) -> ApiResult<String> {
-    log::info!("Updating profile image path");
-
-    utils::validate_user_profile_image(profile_image.as_ref())?;
-
-    let profile_image_path = if let Some(profile_image) = profile_image {
-        log::info!("Writing profile image to disk");
-        fs::write(
-            utils::get_image_disk_path(&user_uuid.to_string()),
-            crate::BASE_64_ENGINE
-                .decode(profile_image)
-                .expect("I'ts hase been validated"),
+    code: &str,
+) -> ApiResult<bool> {
+    let code = code.trim().to_lowercase();
+    let recovery_codes = PasswordRecoveryCodeEntity::find()
+        .filter(PasswordRecoveryCodeColumn::UserUuid.eq(user_uuid))
+        .filter(PasswordRecoveryCodeColumn::UsedAt.is_null())
+        .all(conn)
+        .await?;
+
+    let code_hashes = recovery_codes
+        .iter()
+        .map(|recovery_code| recovery_code.code_hash.clone())
+        .collect();
+    let Some(index) = PasswordHasher::find_code_hash_async(&code, code_hashes).await? else {
+        return Ok(false);
+    };
+    Ok(PasswordRecoveryCodeEntity::update_many()
+        .col_expr(
+            PasswordRecoveryCodeColumn::UsedAt,
+            chrono::Utc::now().naive_utc().into(),
         )
-        .map_err(|_| ApiError::InternalServer)?;
-        format!("/profiles/{user_uuid}")
-    } else {
-        log::info!("Using default profile image");
-        "/profiles/default".to_owned()
+        .filter(PasswordRecoveryCodeColumn::Id.eq(recovery_codes[index].id))
+        .filter(PasswordRecoveryCodeColumn::UsedAt.is_null())
+        .exec(conn)
+        .await?
+        .rows_affected
+        != 0)
+}
+
+/// Creates a new password reset token of the user. Returns the token and its expiration date.
+///
+/// Only the token's SHA-256 is stored, and the user's old tokens are deleted.
+pub(crate) async fn create_password_reset_token(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    auth_config: &AuthConfig,
+) -> ApiResult<(String, chrono::NaiveDateTime)> {
+    log::info!("Creating a new password reset token");
+
+    PasswordResetTokenEntity::delete_many()
+        .filter(PasswordResetTokenColumn::UserUuid.eq(user_uuid))
+        .exec(conn)
+        .await?;
+
+    let mut token = [0u8; PASSWORD_RESET_TOKEN_LEN];
+    rand::thread_rng().fill(&mut token);
+    let token = URL_SAFE_NO_PAD.encode(token);
+    let now = chrono::Utc::now().naive_utc();
+    let expires_at = now + auth_config.password_reset_ttl;
+    PasswordResetTokenActiveModel {
+        user_uuid: Set(user_uuid),
+        token_hash: Set(hash_password_reset_token(&token)),
+        created_at: Set(now),
+        expires_at: Set(expires_at),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?;
+
+    Ok((token, expires_at))
+}
+
+/// Resets the user's password with the password reset token. The token can only be used one time.
+pub(crate) async fn reset_password_with_token(
+    conn: &sea_orm::DatabaseConnection,
+    reset_token: &str,
+    new_password: &str,
+    password_hasher: &PasswordHasher,
+) -> ApiResult<()> {
+    let now = chrono::Utc::now().naive_utc();
+    let reset_token = PasswordResetTokenEntity::find()
+        .filter(PasswordResetTokenColumn::TokenHash.eq(hash_password_reset_token(reset_token)))
+        .filter(PasswordResetTokenColumn::UsedAt.is_null())
+        .filter(PasswordResetTokenColumn::ExpiresAt.gt(now))
+        .one(conn)
+        .await?
+        .ok_or(ApiError::InvalidPasswordResetToken)?;
+    let user = get_user(conn, reset_token.user_uuid)
+        .await?
+        .try_into_model()?;
+
+    if PasswordHasher::verify_async(new_password, &user.password_hash).await? {
+        return Err(ApiError::PasswordNotChanged);
+    }
+    // The condition prevents using the same token in concurrent requests
+    if PasswordResetTokenEntity::update_many()
+        .col_expr(PasswordResetTokenColumn::UsedAt, now.into())
+        .filter(PasswordResetTokenColumn::Id.eq(reset_token.id))
+        .filter(PasswordResetTokenColumn::UsedAt.is_null())
+        .exec(conn)
+        .await?
+        .rows_affected
+        == 0
+    {
+        return Err(ApiError::InvalidPasswordResetToken);
+    }
+
+    reset_password(conn, user, new_password, password_hasher).await
+}
+
+/// Returns the SHA-256 of the password reset token as hex.
+fn hash_password_reset_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Replaces the user's outdated password hash with the new one, see [`PasswordHasher::verify_and_rehash_async`].
+pub(crate) async fn rehash_password(
+    conn: &sea_orm::DatabaseConnection,
+    user_uuid: Uuid,
+    password_hash: &str,
+    new_password_hash: String,
+) -> ApiResult<()> {
+    log::info!("Rehashing the outdated password hash");
+
+    // The condition prevents overwriting a password that changed in the meantime
+    UserEntity::update_many()
+        .col_expr(UserColumn::PasswordHash, new_password_hash.into())
+        .filter(UserColumn::Uuid.eq(user_uuid))
+        .filter(UserColumn::PasswordHash.eq(password_hash))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+/// Creates a new OpenID Connect authorization, it keeps the PKCE code verifier and the nonce until the callback.
+pub(crate) async fn create_oidc_authorization(
+    conn: &sea_orm::DatabaseConnection,
+    provider: &str,
+) -> ApiResult<OidcAuthorizationModel> {
+    log::info!("Creating a new OIDC authorization of provider: {provider}");
+
+    let now = chrono::Utc::now().naive_utc();
+    // The abandoned authorizations are deleted
+    OidcAuthorizationEntity::delete_many()
+        .filter(OidcAuthorizationColumn::ExpiresAt.lte(now))
+        .exec(conn)
+        .await?;
+    Ok(OidcAuthorizationActiveModel {
+        state: Set(oidc::random_token()),
+        provider: Set(provider.to_owned()),
+        code_verifier: Set(oidc::random_token()),
+        nonce: Set(oidc::random_token()),
+        expires_at: Set(now + Duration::minutes(OIDC_AUTHORIZATION_TTL_MINUTES)),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?)
+}
+
+/// Takes the OpenID Connect authorization of the state, it can only be taken one time.
+pub(crate) async fn take_oidc_authorization(
+    conn: &sea_orm::DatabaseConnection,
+    provider: &str,
+    state: &str,
+) -> ApiResult<OidcAuthorizationModel> {
+    log::info!("Taking the OIDC authorization of provider: {provider}");
+
+    let authorization = OidcAuthorizationEntity::find()
+        .filter(OidcAuthorizationColumn::State.eq(state))
+        .filter(OidcAuthorizationColumn::Provider.eq(provider))
+        .filter(OidcAuthorizationColumn::ExpiresAt.gt(chrono::Utc::now().naive_utc()))
+        .one(conn)
+        .await?
+        .ok_or(ApiError::InvalidOidcState)?;
+    // Only one request can delete it, so the concurrent callbacks can't use it twice
+    if OidcAuthorizationEntity::delete_many()
+        .filter(OidcAuthorizationColumn::Id.eq(authorization.id))
+        .exec(conn)
+        .await?
+        .rows_affected
+        == 0
+    {
+        return Err(ApiError::InvalidOidcState);
+    }
+    Ok(authorization)
+}
+
+/// Returns the user of the OpenID Connect identity, and whether the user is created now.
+/// - The linked identity returns its user.
+/// - The identity is linked to the user with the same verified email, if the provider verified it too.
+/// - Otherwise a new user is created with a random password, he can set a password with the recovery codes.
+pub(crate) async fn get_or_create_oidc_user(
+    conn: &sea_orm::DatabaseConnection,
+    provider: &str,
+    claims: IdTokenClaims,
+    password_hasher: &PasswordHasher,
+) -> ApiResult<(UserActiveModel, bool)> {
+    log::info!("Getting the user of an OIDC identity of provider: {provider}");
+
+    if let Some(identity) = UserIdentityEntity::find()
+        .filter(UserIdentityColumn::Provider.eq(provider))
+        .filter(UserIdentityColumn::Subject.eq(&claims.sub))
+        .one(conn)
+        .await?
+    {
+        return Ok((get_user(conn, identity.user_uuid).await?, false));
+    }
+
+    let verified_email_user = match claims.email.as_deref().filter(|_| claims.email_verified) {
+        Some(email) => {
+            UserEntity::find()
+                .filter(
+                    sea_orm::sea_query::Expr::expr(sea_orm::sea_query::Func::lower(
+                        sea_orm::sea_query::Expr::col(UserColumn::Email),
+                    ))
+                    .eq(email.to_lowercase()),
+                )
+                .filter(UserColumn::EmailVerified.eq(true))
+                .one(conn)
+                .await?
+        }
+        None => None,
     };
-    Ok(profile_image_path)
+    let (user_uuid, created) = match verified_email_user {
+        Some(user) => {
+            log::info!("Linking the OIDC identity to user: {}", user.username);
+            (user.uuid, false)
+        }
+        None => (
+            create_oidc_user(conn, &claims, password_hasher).await?,
+            true,
+        ),
+    };
+
+    UserIdentityActiveModel {
+        user_uuid: Set(user_uuid),
+        provider: Set(provider.to_owned()),
+        subject: Set(claims.sub),
+        created_at: Set(chrono::Utc::now().naive_utc()),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?;
+    Ok((get_user(conn, user_uuid).await?, created))
+}
+
+/// Creates a new user from the OpenID Connect claims, and returns his uuid.
+/// The username is made unique, and the email is only used if it's valid and not used by another user.
+async fn create_oidc_user(
+    conn: &sea_orm::DatabaseConnection,
+    claims: &IdTokenClaims,
+    password_hasher: &PasswordHasher,
+) -> ApiResult<Uuid> {
+    let base_username = utils::oidc_username(claims);
+    let mut username = base_username.clone();
+    loop {
+        match check_username_availability(conn, &username, None).await {
+            Ok(()) => break,
+            Err(ApiError::UsernameAlreadyExists(_) | ApiError::UsernameReserved(_)) => {
+                username = format!(
+                    "{base_username}_{:04}",
+                    rand::thread_rng().gen_range(0..10_000)
+                );
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    let mut email = claims
+        .email
+        .clone()
+        .filter(|email| utils::validate_email(email).is_ok());
+    if let Some(address) = &email {
+        if check_email_availability(conn, address).await.is_err() {
+            email = None;
+        }
+    }
+
+    let (first_name, last_name) = utils::oidc_names(claims, &username);
+    let user = create_user(
+        conn,
+        NewUserSchema {
+            first_name,
+            last_name,
+            username,
+            email,
+            password: oidc::random_token(),
+        },
+        password_hasher,
+    )
+    .await?;
+    if user.email.is_some() && claims.email_verified {
+        UserEntity::update_many()
+            .col_expr(UserColumn::EmailVerified, true.into())
+            .filter(UserColumn::Uuid.eq(user.uuid))
+            .exec(conn)
+            .await?;
+    }
+    Ok(user.uuid)
+}
+
+/// Save the user's profile image after normalizing it. Returns the path of the new profile image.
+pub(crate) fn save_profile_image(user_uuid: Uuid, image_bytes: &[u8]) -> ApiResult<String> {
+    log::info!("Saving profile image of user: {user_uuid}");
+
+    let image = utils::normalize_user_profile_image(image_bytes)?;
+    // The thumbnails of the old image are removed, they are generated again from the new one
+    utils::remove_profile_image(&user_uuid.to_string())
+        .and_then(|_| fs::write(utils::get_image_disk_path(&user_uuid.to_string()), image))
+        .map_err(|_| ApiError::InternalServer)?;
+    Ok(format!("/profiles/{user_uuid}"))
+}
+
+/// Delete the user's profile image and its thumbnails. Returns the path of the default profile image.
+pub(crate) fn delete_profile_image(user_uuid: Uuid) -> ApiResult<String> {
+    log::info!("Deleting profile image of user: {user_uuid}");
+
+    utils::remove_profile_image(&user_uuid.to_string()).map_err(|_| ApiError::InternalServer)?;
+    Ok("/profiles/default".to_owned())
 }
 
 pub async fn latest_player_games(
@@ -14,12 +14,86 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use salvo::{hyper::StatusCode, oapi::EndpointOutRegister, Scribe};
+use salvo::{
+    hyper::{header, StatusCode},
+    oapi::EndpointOutRegister,
+    Scribe,
+};
+use uuid::Uuid;
 
-use crate::schemas::MessageSchema;
+use crate::schemas::{MessageSchema, XoSymbol};
 
 pub type ApiResult<T> = std::result::Result<T, ApiError>;
 
+/// Errors of decoding the stored game data. The board, the rounds result and the game over reason.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum GameParseError {
+    #[error("The board `{0}` has no `:` between the cells and the played cells")]
+    MissingBoardSeparator(String),
+    #[error("The board must have 9 cells, found {0}")]
+    InvalidCellsCount(usize),
+    #[error("Invalid cell `{0}`, the cell must be `X`, `O` or `-`")]
+    InvalidCell(char),
+    #[error("The played cells must be at most 9, found {0}")]
+    TooManyPlayedCells(usize),
+    #[error("Invalid played cell `{0}`, the played cell must be a digit between 0 and 8")]
+    InvalidPlayedCell(char),
+    #[error("The played cell {0} is out of the board, the played cell must be between 0 and 8")]
+    PlayedCellOutOfRange(u32),
+    #[error("The played cell {0} is empty in the board cells")]
+    EmptyPlayedCell(u8),
+    #[error("The cell {0} is played more than once")]
+    DuplicatePlayedCell(u8),
+    #[error("The cell {cell} is played by `{found}` while it's `{expected}` turn")]
+    WrongTurn {
+        cell: u8,
+        expected: XoSymbol,
+        found: XoSymbol,
+    },
+    #[error("The cell {0} is played after the board is ended")]
+    PlayedAfterEnd(u8),
+    #[error("The cell {0} is not empty but it's not in the played cells")]
+    UnplayedCell(u8),
+    #[error("The board is not ended")]
+    UnendedBoard,
+    #[error("The game is ended but its board `{0}` is not cleared")]
+    EndedGameBoard(String),
+    #[error("The rounds result `{0}` has no space between the results and the boards")]
+    MissingRoundsSeparator(String),
+    #[error("Invalid round result `{0}`, the round result must be `X`, `O` or `-`")]
+    InvalidRoundResult(char),
+    #[error("The game can't have more than 3 rounds, found {0}")]
+    TooManyRounds(usize),
+    #[error("The rounds result doesn't match the rounds boards, the boards have {x_player} X wins, {o_player} O wins and {draws} draws")]
+    RoundsResultMismatch {
+        x_player: usize,
+        o_player: usize,
+        draws: usize,
+    },
+    #[error("Invalid board of round {round}: {source}")]
+    InvalidRoundBoard {
+        round: usize,
+        source: Box<GameParseError>,
+    },
+    #[error("Invalid game over reason `{0}`")]
+    InvalidGameOverReason(String),
+}
+
+/// Errors of loading the JWT keys.
+#[derive(Debug, thiserror::Error)]
+pub enum JwtKeyError {
+    #[error("Invalid PEM: {0}")]
+    InvalidPem(String),
+    #[error("Unsupported key, only the RSA and Ed25519 public keys are supported")]
+    UnsupportedKey,
+    #[error(
+        "The algorithm `{0:?}` is not supported, use `HS256`, `HS384`, `HS512`, `RS256` or `EdDSA`"
+    )]
+    UnsupportedAlgorithm(jsonwebtoken::Algorithm),
+    #[error("{0}")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ApiError {
     #[error("{0}")]
@@ -29,10 +103,34 @@ pub enum ApiError {
     #[error("{0}")]
     Bcrypt(#[from] bcrypt::BcryptError),
     #[error("{0}")]
+    PasswordHash(#[from] argon2::password_hash::Error),
+    #[error("{0}")]
     Salvo(#[from] salvo::http::StatusError),
+    #[error("Failed to send the mail: {0}")]
+    Mail(String),
+    #[error("Failed to reach the OIDC provider: {0}")]
+    Oidc(String),
 
     #[error("Username `{0}` already exists")]
     UsernameAlreadyExists(String),
+    #[error("Username `{0}` is reserved, it was recently used by another user")]
+    UsernameReserved(String),
+    #[error("The new username is same as the current username")]
+    UsernameNotChanged,
+    #[error("The username has been changed recently, try again after {retry_after} seconds")]
+    UsernameChangeCooldown { retry_after: i64 },
+    #[error("Email `{0}` already exists")]
+    EmailAlreadyExists(String),
+    #[error("The user does not have an email")]
+    EmailNotSet,
+    #[error("The email is already verified")]
+    EmailAlreadyVerified,
+    #[error("The account is already deleted, it will be purged after the grace period")]
+    AccountAlreadyDeleted,
+    #[error("The account is not deleted")]
+    AccountNotDeleted,
+    #[error("The account is deleted, restore it first")]
+    AccountDeleted,
     #[error("The token is not a refresh token")]
     NotRefreshToken,
     #[error("The token is not user jwt")]
@@ -41,10 +139,20 @@ pub enum ApiError {
     UnActiveRefreshToken,
     #[error("The token is expired")]
     ExpiredToken,
+    #[error("The token has been revoked, signin again")]
+    RevokedToken,
+    #[error("The refresh token has been revoked, signin again")]
+    RevokedRefreshToken,
+    #[error("The refresh token has already been used, all the refresh tokens of this signin have been revoked")]
+    ReusedRefreshToken,
     #[error("User not found")]
     UserNotFound,
+    #[error("The user's profile is private")]
+    PrivateProfile,
     #[error("Game not found")]
     GameNotFound,
+    #[error("Session not found")]
+    SessionNotFound,
     #[error("Invalid first name: The first name must be between 1 and 32 characters and not contain spaces")]
     InvalidFirstName,
     #[error("Invalid last name: The last name must be between 1 and 32 characters and not contain spaces")]
@@ -53,8 +161,12 @@ pub enum ApiError {
     InvalidUsername,
     #[error("Invalid password: {0}")]
     InvalidPassword(String),
+    #[error("Invalid email: {0}")]
+    InvalidEmail(String),
     #[error("Invalid signin credentials: The username or password is incorrect")]
     InvalidSigninCredentials,
+    #[error("The account is locked because of too many failed signin attempts, try again after {retry_after} seconds")]
+    AccountLocked { retry_after: i64 },
     #[error("Captcha token is invalid or expired")]
     InvalidCaptchaToken,
     #[error("The captcha answer is incorrect")]
@@ -65,8 +177,48 @@ pub enum ApiError {
     UnProvidedCaptchaAnswer,
     #[error("{0}")]
     InvalidProfileImage(String),
+    #[error("Invalid request body: {0}")]
+    InvalidRequestBody(String),
+    #[error("The request body is too large")]
+    PayloadTooLarge,
+    #[error("The image size must be one of 32, 64 or 128 pixels")]
+    InvalidImageSize,
     #[error("The old password is same as the new password, the new password must be different from the old password")]
     PasswordNotChanged,
+    #[error("The two users must be different")]
+    SameVersusUsers,
+    #[error("Invalid games filter: {0}")]
+    InvalidGamesFilter(String),
+    #[error("Invalid users search: {0}")]
+    InvalidUsersSearch(String),
+    #[error("The cursor is invalid, get it from the `next_cursor` of the previous page")]
+    InvalidCursor,
+    #[error("The game `{uuid}` is corrupted: {source}")]
+    CorruptedGame { uuid: Uuid, source: GameParseError },
+
+    #[error("The two-factor authentication is already enabled")]
+    TotpAlreadyEnabled,
+    #[error("The two-factor authentication is not enabled")]
+    TotpNotEnabled,
+    #[error("The two-factor authentication is not enrolled, enroll it first")]
+    TotpNotEnrolled,
+    #[error("The two-factor authentication code is incorrect")]
+    InvalidTotpCode,
+    #[error("The two-factor authentication challenge is invalid or expired, signin again")]
+    InvalidTotpChallenge,
+
+    #[error("The password reset token is invalid, expired or already used")]
+    InvalidPasswordResetToken,
+    #[error("Invalid recovery credentials: The username or recovery code is incorrect")]
+    InvalidRecoveryCredentials,
+    #[error("Invalid email verification token: The token is invalid, expired or the email has been changed")]
+    InvalidEmailVerificationToken,
+    #[error("Invalid OIDC state: The state is invalid or expired, start the login again")]
+    InvalidOidcState,
+    #[error("Invalid OIDC login: {0}")]
+    InvalidOidcLogin(String),
+    #[error("OIDC provider `{0}` not found")]
+    OidcProviderNotFound(String),
 
     #[error("Internal server error")]
     InternalServer,
@@ -103,6 +255,29 @@ impl Scribe for ApiError {
                     MessageSchema::new(format!("Internal server error: {err}")),
                 );
             }
+            ApiError::PasswordHash(err) => {
+                res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+                crate::api::write_json_body(
+                    res,
+                    MessageSchema::new(format!("Internal server error: {err}")),
+                );
+            }
+            ApiError::Mail(_) => {
+                res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+                crate::api::write_json_body(
+                    res,
+                    MessageSchema::new("Internal server error: Failed to send the mail".to_owned()),
+                );
+            }
+            ApiError::Oidc(_) => {
+                res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+                crate::api::write_json_body(
+                    res,
+                    MessageSchema::new(
+                        "Internal server error: Failed to reach the OIDC provider".to_owned(),
+                    ),
+                );
+            }
             ApiError::InternalServer => {
                 res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
                 crate::api::write_json_body(
@@ -110,33 +285,80 @@ impl Scribe for ApiError {
                     MessageSchema::new("Internal server error".to_owned()),
                 );
             }
+            ApiError::CorruptedGame { .. } => {
+                res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+                crate::api::write_json_body(res, MessageSchema::new(self.to_string()));
+            }
             ApiError::UsernameAlreadyExists(_)
+            | ApiError::UsernameReserved(_)
+            | ApiError::UsernameNotChanged
+            | ApiError::EmailAlreadyExists(_)
+            | ApiError::EmailNotSet
+            | ApiError::EmailAlreadyVerified
+            | ApiError::AccountAlreadyDeleted
+            | ApiError::AccountNotDeleted
             | ApiError::NotRefreshToken
             | ApiError::NotUserJwt
             | ApiError::InvalidFirstName
             | ApiError::InvalidLastName
             | ApiError::InvalidUsername
             | ApiError::InvalidPassword(_)
+            | ApiError::InvalidEmail(_)
             | ApiError::UnProvidedCaptchaToken
             | ApiError::UnProvidedCaptchaAnswer
             | ApiError::PasswordNotChanged
-            | ApiError::InvalidProfileImage(_) => {
+            | ApiError::InvalidProfileImage(_)
+            | ApiError::InvalidRequestBody(_)
+            | ApiError::InvalidImageSize
+            | ApiError::InvalidGamesFilter(_)
+            | ApiError::InvalidUsersSearch(_)
+            | ApiError::InvalidCursor
+            | ApiError::TotpAlreadyEnabled
+            | ApiError::TotpNotEnabled
+            | ApiError::TotpNotEnrolled
+            | ApiError::SameVersusUsers => {
                 res.status_code(StatusCode::BAD_REQUEST);
                 crate::api::write_json_body(res, MessageSchema::new(self.to_string()));
             }
-            ApiError::UserNotFound | ApiError::GameNotFound => {
+            ApiError::UserNotFound
+            | ApiError::GameNotFound
+            | ApiError::SessionNotFound
+            | ApiError::OidcProviderNotFound(_) => {
                 res.status_code(StatusCode::NOT_FOUND);
                 crate::api::write_json_body(res, MessageSchema::new(self.to_string()));
             }
-            ApiError::ExpiredToken => {
+            ApiError::ExpiredToken
+            | ApiError::RevokedToken
+            | ApiError::RevokedRefreshToken
+            | ApiError::ReusedRefreshToken => {
                 res.status_code(StatusCode::UNAUTHORIZED);
                 crate::api::write_json_body(res, MessageSchema::new(self.to_string()));
             }
             ApiError::UnActiveRefreshToken
             | ApiError::InvalidSigninCredentials
             | ApiError::InvalidCaptchaAnswer
-            | ApiError::InvalidCaptchaToken => {
+            | ApiError::InvalidCaptchaToken
+            | ApiError::InvalidTotpCode
+            | ApiError::InvalidTotpChallenge
+            | ApiError::InvalidPasswordResetToken
+            | ApiError::InvalidRecoveryCredentials
+            | ApiError::InvalidEmailVerificationToken
+            | ApiError::InvalidOidcState
+            | ApiError::InvalidOidcLogin(_)
+            | ApiError::AccountDeleted
+            | ApiError::PrivateProfile => {
+                res.status_code(StatusCode::FORBIDDEN);
+                crate::api::write_json_body(res, MessageSchema::new(self.to_string()));
+            }
+            ApiError::AccountLocked { retry_after }
+            | ApiError::UsernameChangeCooldown { retry_after } => {
                 res.status_code(StatusCode::FORBIDDEN);
+                res.headers_mut()
+                    .insert(header::RETRY_AFTER, (*retry_after).into());
+                crate::api::write_json_body(res, MessageSchema::new(self.to_string()));
+            }
+            ApiError::PayloadTooLarge => {
+                res.status_code(StatusCode::PAYLOAD_TOO_LARGE);
                 crate::api::write_json_body(res, MessageSchema::new(self.to_string()));
             }
             ApiError::Salvo(err) => {
@@ -17,9 +17,14 @@
 use base64::engine::GeneralPurpose;
 
 pub mod api;
+pub mod config;
 pub mod db_utils;
 pub mod errors;
+pub mod mailer;
+pub mod oidc;
+pub mod password;
 pub mod schemas;
+pub mod totp;
 pub mod utils;
 
 pub const BASE_64_ENGINE: GeneralPurpose = GeneralPurpose::new(
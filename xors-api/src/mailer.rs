@@ -0,0 +1,190 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The mails delivery. The mails are sent with SMTP, or written to a directory for the local testing.
+
+use std::{env, path::Path, sync::Arc};
+
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::errors::{ApiError, ApiResult};
+
+/// The default sender of the mails.
+const DEFAULT_FROM: &str = "XORS <noreply@localhost>";
+/// The default directory of the file mailer.
+const DEFAULT_MAIL_DIR: &str = "mails";
+
+/// A mail to send.
+#[derive(Debug, Clone, derive_new::new)]
+pub struct Mail {
+    /// The recipient's email address.
+    pub to: String,
+    /// The mail subject.
+    pub subject: String,
+    /// The mail plain text body.
+    pub body: String,
+}
+
+/// The mails sender.
+#[async_trait::async_trait]
+pub trait Mailer: Send + Sync {
+    /// Sends the mail.
+    async fn send(&self, mail: Mail) -> ApiResult<()>;
+}
+
+/// Sends the mails with SMTP.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+/// Writes the mails to a directory as `.eml` files and logs them. It's for the local testing.
+pub struct FileMailer {
+    transport: AsyncFileTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mail {
+    /// Returns the mail as a message from the sender.
+    fn into_message(self, from: &Mailbox) -> ApiResult<Message> {
+        Message::builder()
+            .from(from.clone())
+            .to(self.to.parse().map_err(mail_error)?)
+            .subject(self.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(self.body)
+            .map_err(mail_error)
+    }
+}
+
+impl SmtpMailer {
+    /// Create a new [`SmtpMailer`] that sends the mails with TLS to the relay.
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        credentials: Option<Credentials>,
+        from: Mailbox,
+    ) -> ApiResult<Self> {
+        let mut transport =
+            AsyncSmtpTransport::<Tokio1Executor>::relay(host).map_err(mail_error)?;
+        if let Some(port) = port {
+            transport = transport.port(port);
+        }
+        if let Some(credentials) = credentials {
+            transport = transport.credentials(credentials);
+        }
+        Ok(Self {
+            transport: transport.build(),
+            from,
+        })
+    }
+}
+
+impl FileMailer {
+    /// Create a new [`FileMailer`] that writes the mails to the directory. The directory must exist.
+    pub fn new(dir: impl AsRef<Path>, from: Mailbox) -> Self {
+        Self {
+            transport: AsyncFileTransport::new(dir),
+            from,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: Mail) -> ApiResult<()> {
+        log::info!("Sending a mail with SMTP: {}", mail.subject);
+        self.transport
+            .send(mail.into_message(&self.from)?)
+            .await
+            .map_err(mail_error)?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> ApiResult<()> {
+        log::info!("New mail to {}: {}\n{}", mail.to, mail.subject, mail.body);
+        let id = self
+            .transport
+            .send(mail.into_message(&self.from)?)
+            .await
+            .map_err(mail_error)?;
+        log::info!("The mail is written to `{id}.eml`");
+        Ok(())
+    }
+}
+
+/// Returns the mailer from the environment variables.
+///
+/// The environment variables:
+/// - `XORS_API_MAILER`: The mailer, `smtp` or `file`. Default `file`.
+/// - `XORS_API_MAIL_FROM`: The sender of the mails. Default `XORS <noreply@localhost>`.
+/// - `XORS_API_MAIL_DIR`: The directory of the `file` mailer. Default `mails`.
+/// - `XORS_API_SMTP_HOST`: The SMTP relay host, required with the `smtp` mailer.
+/// - `XORS_API_SMTP_PORT`: The SMTP relay port. Default 465.
+/// - `XORS_API_SMTP_USERNAME` and `XORS_API_SMTP_PASSWORD`: The SMTP credentials. Optional.
+///
+/// ### Panics
+/// If a variable is invalid, or the mail directory can't be created.
+pub fn from_env() -> Arc<dyn Mailer> {
+    let from = env::var("XORS_API_MAIL_FROM")
+        .unwrap_or_else(|_| DEFAULT_FROM.to_owned())
+        .parse::<Mailbox>()
+        .expect("`XORS_API_MAIL_FROM` environment variable must be a valid mailbox");
+
+    match env::var("XORS_API_MAILER").as_deref() {
+        Err(_) | Ok("file") => {
+            let dir = env::var("XORS_API_MAIL_DIR").unwrap_or_else(|_| DEFAULT_MAIL_DIR.to_owned());
+            std::fs::create_dir_all(&dir).expect("Failed to create the mail directory");
+            Arc::new(FileMailer::new(dir, from))
+        }
+        Ok("smtp") => {
+            let host = env::var("XORS_API_SMTP_HOST").expect(
+                "`XORS_API_SMTP_HOST` environment variable must be set with the `smtp` mailer",
+            );
+            let port = env::var("XORS_API_SMTP_PORT").ok().map(|port| {
+                port.parse()
+                    .expect("`XORS_API_SMTP_PORT` environment variable must be a port number")
+            });
+            let credentials = env::var("XORS_API_SMTP_USERNAME")
+                .ok()
+                .map(|username| {
+                    Credentials::new(
+                        username,
+                        env::var("XORS_API_SMTP_PASSWORD").expect(
+                            "`XORS_API_SMTP_PASSWORD` environment variable must be set with the username",
+                        ),
+                    )
+                });
+            Arc::new(
+                SmtpMailer::new(&host, port, credentials, from)
+                    .expect("`XORS_API_SMTP_HOST` environment variable must be a valid host"),
+            )
+        }
+        _ => panic!("`XORS_API_MAILER` environment variable must be `smtp` or `file`"),
+    }
+}
+
+/// Returns the mail error as [`ApiError::Mail`].
+fn mail_error(err: impl std::fmt::Display) -> ApiError {
+    ApiError::Mail(err.to_string())
+}
@@ -0,0 +1,354 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//! The OpenID Connect login with the external identity providers, with the authorization code flow and PKCE (RFC 7636).
+
+use std::env;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::errors::{ApiError, ApiResult};
+
+/// The default scopes of the authorization request.
+const DEFAULT_SCOPES: &str = "openid email profile";
+/// The length of the random state, nonce and code verifier in bytes.
+const RANDOM_TOKEN_LEN: usize = 32;
+
+/// The HTTP client of the providers requests.
+static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .expect("Failed to build the HTTP client")
+});
+
+/// An OpenID Connect identity provider.
+#[derive(Debug, Clone)]
+pub struct OidcProvider {
+    /// The provider name, it's used in the endpoints path and to link the identities.
+    pub name: String,
+    /// The provider issuer, the discovery document is at `{issuer}/.well-known/openid-configuration`.
+    pub issuer: String,
+    /// The client id that registered in the provider.
+    pub client_id: String,
+    /// The client secret. Optional for the public clients, the PKCE protects the code exchange.
+    pub client_secret: Option<String>,
+    /// The callback URL that registered in the provider.
+    pub redirect_url: String,
+    /// The requested scopes, separated by spaces.
+    pub scopes: String,
+    /// The allowed ID token signing algorithms. `None` allows the algorithms that the provider supports,
+    /// from its discovery document.
+    pub id_token_algorithms: Option<Vec<Algorithm>>,
+}
+
+/// The provider metadata, from its discovery document.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: Option<String>,
+    /// The ID token signing algorithms that the provider supports, `RS256` if it's missing.
+    #[serde(default = "default_id_token_algorithms")]
+    pub id_token_signing_alg_values_supported: Vec<String>,
+}
+
+/// The token endpoint response, only the ID token is used.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The ID token claims that are used to link or create the local user.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    /// The user's identifier at the provider.
+    pub sub: String,
+    /// The nonce of the authorization request.
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+    pub preferred_username: Option<String>,
+}
+
+impl OidcProvider {
+    /// Fetches the provider metadata from its discovery document.
+    pub async fn metadata(&self) -> ApiResult<ProviderMetadata> {
+        let issuer = self.issuer.trim_end_matches('/');
+        let metadata: ProviderMetadata = HTTP_CLIENT
+            .get(format!("{issuer}/.well-known/openid-configuration"))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(oidc_error)?
+            .json()
+            .await
+            .map_err(oidc_error)?;
+
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            return Err(ApiError::Oidc(format!(
+                "The discovery document issuer `{}` doesn't match `{issuer}`",
+                metadata.issuer
+            )));
+        }
+        Ok(metadata)
+    }
+
+    /// Returns the authorization URL that the user is redirected to.
+    pub fn authorization_url(
+        &self,
+        metadata: &ProviderMetadata,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> ApiResult<String> {
+        reqwest::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            [
+                ("response_type", "code"),
+                ("client_id", &self.client_id),
+                ("redirect_uri", &self.redirect_url),
+                ("scope", &self.scopes),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", &code_challenge(code_verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map(String::from)
+        .map_err(oidc_error)
+    }
+
+    /// Exchanges the authorization code with the ID token, and returns its verified claims.
+    pub async fn exchange_code(
+        &self,
+        metadata: &ProviderMetadata,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> ApiResult<IdTokenClaims> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_url),
+            ("client_id", &self.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(client_secret) = &self.client_secret {
+            form.push(("client_secret", client_secret));
+        }
+        let response = HTTP_CLIENT
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(oidc_error)?;
+        if response.status().is_client_error() {
+            return Err(ApiError::InvalidOidcLogin(
+                "The provider rejected the authorization code".to_owned(),
+            ));
+        }
+        let token_response: TokenResponse = response
+            .error_for_status()
+            .map_err(oidc_error)?
+            .json()
+            .await
+            .map_err(oidc_error)?;
+
+        let claims = self
+            .verify_id_token(metadata, &token_response.id_token)
+            .await?;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(ApiError::InvalidOidcLogin(
+                "The ID token nonce doesn't match the authorization request".to_owned(),
+            ));
+        }
+        Ok(claims)
+    }
+
+    /// Returns the allowed ID token signing algorithms, the configured ones or the ones that the provider supports.
+    fn allowed_id_token_algorithms(&self, metadata: &ProviderMetadata) -> Vec<Algorithm> {
+        self.id_token_algorithms.clone().unwrap_or_else(|| {
+            // The unsupported algorithms are ignored, like `none`
+            metadata
+                .id_token_signing_alg_values_supported
+                .iter()
+                .filter_map(|algorithm| algorithm.parse().ok())
+                .collect()
+        })
+    }
+
+    /// Verifies the ID token signature, issuer, audience and expiration, and returns its claims.
+    ///
+    /// The token algorithm must be one of the allowed algorithms, see [`OidcProvider::id_token_algorithms`].
+    /// The HMAC tokens are verified with the client secret, and the others with the provider keys.
+    async fn verify_id_token(
+        &self,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+    ) -> ApiResult<IdTokenClaims> {
+        let header = jsonwebtoken::decode_header(id_token).map_err(invalid_id_token)?;
+        if !self
+            .allowed_id_token_algorithms(metadata)
+            .contains(&header.alg)
+        {
+            return Err(ApiError::InvalidOidcLogin(format!(
+                "The ID token algorithm `{:?}` is not allowed",
+                header.alg
+            )));
+        }
+        let decoding_key = match header.alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => DecodingKey::from_secret(
+                self.client_secret
+                    .as_ref()
+                    .ok_or_else(|| {
+                        ApiError::InvalidOidcLogin(
+                            "The ID token is signed with the client secret, but there is no secret"
+                                .to_owned(),
+                        )
+                    })?
+                    .as_bytes(),
+            ),
+            _ => {
+                let jwks_uri = metadata.jwks_uri.as_ref().ok_or_else(|| {
+                    ApiError::Oidc("The provider doesn't have a `jwks_uri`".to_owned())
+                })?;
+                let jwks: JwkSet = HTTP_CLIENT
+                    .get(jwks_uri)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(oidc_error)?
+                    .json()
+                    .await
+                    .map_err(oidc_error)?;
+                let jwk = match &header.kid {
+                    Some(kid) => jwks.find(kid),
+                    None => jwks.keys.first(),
+                }
+                .ok_or_else(|| {
+                    ApiError::InvalidOidcLogin("The ID token key is not found".to_owned())
+                })?;
+                DecodingKey::from_jwk(jwk).map_err(invalid_id_token)?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.client_id]);
+        jsonwebtoken::decode::<IdTokenClaims>(id_token, &decoding_key, &validation)
+            .map(|token| token.claims)
+            .map_err(invalid_id_token)
+    }
+}
+
+/// Returns a new random URL safe token, used as the state, the nonce and the code verifier.
+pub fn random_token() -> String {
+    let mut token = [0u8; RANDOM_TOKEN_LEN];
+    rand::thread_rng().fill(&mut token);
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+/// Returns the S256 code challenge of the code verifier.
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Returns the providers from the environment variables.
+///
+/// The providers names are in `XORS_API_OIDC_PROVIDERS` separated by commas, and every provider is configured with:
+/// - `XORS_API_OIDC_<NAME>_ISSUER`: The provider issuer.
+/// - `XORS_API_OIDC_<NAME>_CLIENT_ID`: The client id.
+/// - `XORS_API_OIDC_<NAME>_CLIENT_SECRET`: The client secret. Optional.
+/// - `XORS_API_OIDC_<NAME>_SCOPES`: The requested scopes. Default `openid email profile`.
+/// - `XORS_API_OIDC_<NAME>_ID_TOKEN_ALGORITHMS`: The allowed ID token signing algorithms, separated by commas.
+///   Default the algorithms in the provider's discovery document.
+///
+/// The callback URL is `{public_url}/auth/oidc/<name>/callback`.
+///
+/// ### Panics
+/// If a provider name is invalid, its issuer or client id is not set, or an ID token algorithm is invalid.
+pub fn providers_from_env(public_url: &str) -> Vec<OidcProvider> {
+    let Ok(names) = env::var("XORS_API_OIDC_PROVIDERS") else {
+        return Vec::new();
+    };
+
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            if !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                panic!("The OIDC provider name `{name}` must only contain English letters, numbers, `_` and `-`");
+            }
+            let prefix = format!("XORS_API_OIDC_{}", name.to_uppercase().replace('-', "_"));
+            let required_var = |suffix: &str| {
+                env::var(format!("{prefix}_{suffix}")).unwrap_or_else(|_| {
+                    panic!("`{prefix}_{suffix}` environment variable must be set")
+                })
+            };
+
+            OidcProvider {
+                name: name.to_owned(),
+                issuer: required_var("ISSUER"),
+                client_id: required_var("CLIENT_ID"),
+                client_secret: env::var(format!("{prefix}_CLIENT_SECRET")).ok(),
+                redirect_url: format!("{public_url}/auth/oidc/{name}/callback"),
+                scopes: env::var(format!("{prefix}_SCOPES"))
+                    .unwrap_or_else(|_| DEFAULT_SCOPES.to_owned()),
+                id_token_algorithms: env::var(format!("{prefix}_ID_TOKEN_ALGORITHMS")).ok().map(
+                    |algorithms| {
+                        algorithms
+                            .split(',')
+                            .map(|algorithm| {
+                                algorithm.trim().parse().unwrap_or_else(|_| {
+                                    panic!("`{prefix}_ID_TOKEN_ALGORITHMS` environment variable has an invalid algorithm `{algorithm}`")
+                                })
+                            })
+                            .collect()
+                    },
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Returns the default ID token signing algorithms of the providers, `RS256` is required by the specification.
+fn default_id_token_algorithms() -> Vec<String> {
+    vec!["RS256".to_owned()]
+}
+
+/// Returns the provider error as [`ApiError::Oidc`].
+fn oidc_error(err: impl std::fmt::Display) -> ApiError {
+    ApiError::Oidc(err.to_string())
+}
+
+/// Returns the ID token error as [`ApiError::InvalidOidcLogin`].
+fn invalid_id_token(err: jsonwebtoken::errors::Error) -> ApiError {
+    ApiError::InvalidOidcLogin(format!("Invalid ID token: {err}"))
+}
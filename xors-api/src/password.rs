@@ -0,0 +1,177 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The password hashing. The hashes of the both algorithms can be verified, whatever the configured one is,
+//! so the old hashes can be rehashed with the configured algorithm after a successful signin.
+
+use argon2::{
+    password_hash::{self, SaltString},
+    Argon2, Params, PasswordHash, PasswordHasher as _, PasswordVerifier, Version,
+};
+
+use crate::errors::{ApiError, ApiResult};
+
+/// The password hashing algorithm with its parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordHasher {
+    /// The bcrypt algorithm.
+    Bcrypt {
+        /// The cost, the hashing does `2^cost` rounds.
+        cost: u32,
+    },
+    /// The Argon2id algorithm.
+    Argon2id {
+        /// The memory size in KiB.
+        memory_cost: u32,
+        /// The number of iterations.
+        time_cost: u32,
+        /// The degree of parallelism.
+        parallelism: u32,
+    },
+}
+
+impl Default for PasswordHasher {
+    /// Argon2id with the OWASP recommended parameters, 19 MiB of memory, 2 iterations and 1 degree of parallelism.
+    fn default() -> Self {
+        Self::Argon2id {
+            memory_cost: Params::DEFAULT_M_COST,
+            time_cost: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl PasswordHasher {
+    /// Returns the password hash.
+    pub fn hash(&self, password: &str) -> ApiResult<String> {
+        match *self {
+            Self::Bcrypt { cost } => Ok(bcrypt::hash(password, cost)?),
+            Self::Argon2id {
+                memory_cost,
+                time_cost,
+                parallelism,
+            } => Ok(Argon2::new(
+                argon2::Algorithm::Argon2id,
+                Version::V0x13,
+                Params::new(memory_cost, time_cost, parallelism, None)
+                    .map_err(password_hash::Error::from)?,
+            )
+            .hash_password(
+                password.as_bytes(),
+                &SaltString::generate(&mut rand::thread_rng()),
+            )?
+            .to_string()),
+        }
+    }
+
+    /// Verifies the password with its hash. The hash algorithm is detected from the hash.
+    pub fn verify(password: &str, hash: &str) -> ApiResult<bool> {
+        if hash.starts_with("$argon2") {
+            match Argon2::default().verify_password(password.as_bytes(), &PasswordHash::new(hash)?)
+            {
+                Ok(()) => Ok(true),
+                Err(password_hash::Error::Password) => Ok(false),
+                Err(err) => Err(err.into()),
+            }
+        } else {
+            Ok(bcrypt::verify(password, hash)?)
+        }
+    }
+
+    /// Returns whether the hash is created with another algorithm or other parameters, and it must be rehashed.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        match *self {
+            Self::Bcrypt { cost } => !hash
+                .parse::<bcrypt::HashParts>()
+                .is_ok_and(|parts| parts.get_cost() == cost),
+            Self::Argon2id {
+                memory_cost,
+                time_cost,
+                parallelism,
+            } => !PasswordHash::new(hash).is_ok_and(|hash| {
+                hash.algorithm == argon2::Algorithm::Argon2id.ident()
+                    && hash.version == Some(Version::V0x13.into())
+                    && Params::try_from(&hash).is_ok_and(|params| {
+                        params.m_cost() == memory_cost
+                            && params.t_cost() == time_cost
+                            && params.p_cost() == parallelism
+                    })
+            }),
+        }
+    }
+
+    /// Returns the password hash, in a blocking thread to not block the async runtime.
+    /// See [`PasswordHasher::hash`].
+    pub async fn hash_async(&self, password: &str) -> ApiResult<String> {
+        let (hasher, password) = (*self, password.to_owned());
+        spawn_blocking(move || hasher.hash(&password)).await
+    }
+
+    /// Verifies the password with its hash, in a blocking thread to not block the async runtime.
+    /// See [`PasswordHasher::verify`].
+    pub async fn verify_async(password: &str, hash: &str) -> ApiResult<bool> {
+        let (password, hash) = (password.to_owned(), hash.to_owned());
+        spawn_blocking(move || Self::verify(&password, &hash)).await
+    }
+
+    /// Verifies the password with its hash, then rehashes it if the hash needs a rehash, in one blocking thread.
+    /// Returns whether the password is correct, with its new hash if it's rehashed.
+    pub async fn verify_and_rehash_async(
+        &self,
+        password: &str,
+        hash: &str,
+    ) -> ApiResult<(bool, Option<String>)> {
+        let (hasher, password, hash) = (*self, password.to_owned(), hash.to_owned());
+        spawn_blocking(move || {
+            if !Self::verify(&password, &hash)? {
+                return Ok((false, None));
+            }
+            let new_hash = hasher
+                .needs_rehash(&hash)
+                .then(|| hasher.hash(&password))
+                .transpose()?;
+            Ok((true, new_hash))
+        })
+        .await
+    }
+
+    /// Returns the hashes of the recovery codes, in one blocking thread to not block the async runtime.
+    pub async fn hash_codes_async(&self, codes: &[String]) -> ApiResult<Vec<String>> {
+        let (hasher, codes) = (*self, codes.to_vec());
+        spawn_blocking(move || codes.iter().map(|code| hasher.hash(code)).collect()).await
+    }
+
+    /// Returns the index of the first hash that matches the code, in one blocking thread to not block the async runtime.
+    /// The invalid hashes are skipped.
+    pub async fn find_code_hash_async(code: &str, hashes: Vec<String>) -> ApiResult<Option<usize>> {
+        let code = code.to_owned();
+        spawn_blocking(move || {
+            Ok(hashes
+                .iter()
+                .position(|hash| Self::verify(&code, hash).unwrap_or_default()))
+        })
+        .await
+    }
+}
+
+/// Runs the hashing function in a blocking thread, the hashing is CPU heavy.
+async fn spawn_blocking<T: Send + 'static>(
+    hashing: impl FnOnce() -> ApiResult<T> + Send + 'static,
+) -> ApiResult<T> {
+    tokio::task::spawn_blocking(hashing)
+        .await
+        .map_err(|_| ApiError::InternalServer)?
+}
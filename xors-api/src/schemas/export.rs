@@ -0,0 +1,129 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use entity::prelude::*;
+use salvo::oapi::ToSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{api::exts::GameModelExt, errors::ApiResult};
+
+use super::*;
+
+/// The user's data export. It's streamed as a single JSON document, the games are written page by page.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "UserExportSchema", example = json!(UserExportSchema::default())))]
+pub struct UserExportSchema {
+    /// The user's data, with the fields that only returned to the user himself.
+    pub user: UserSchema,
+    /// The user's profile image, base64 encoded. Will be null if the user uses the default profile image.
+    pub profile_image: Option<String>,
+    /// The user's active sessions.
+    pub sessions: Vec<SessionSchema>,
+    /// The user's old usernames, newest first.
+    pub username_history: Vec<UsernameHistorySchema>,
+    /// The identity providers accounts that linked to the user.
+    pub identities: Vec<IdentitySchema>,
+    /// All the games that the user played, oldest first.
+    pub games: Vec<ExportedGameSchema>,
+}
+
+/// An old username of the user.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[salvo(schema(symbol = "UsernameHistorySchema"))]
+pub struct UsernameHistorySchema {
+    /// The old username.
+    pub username: String,
+    /// The date the username was changed at.
+    pub changed_at: chrono::NaiveDateTime,
+}
+
+/// An identity provider account that linked to the user.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[salvo(schema(symbol = "IdentitySchema"))]
+pub struct IdentitySchema {
+    /// The identity provider name.
+    pub provider: String,
+    /// The user's id in the identity provider.
+    pub subject: String,
+    /// The date the account was linked at.
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// A game of the user's data export. The players are referenced by their uuids.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "ExportedGameSchema"))]
+pub struct ExportedGameSchema {
+    /// The game's uuid.
+    pub uuid: Uuid,
+    /// The X player's uuid.
+    pub x_player: Uuid,
+    /// The O player's uuid.
+    pub o_player: Uuid,
+    /// The game's rounds results, with the board of each round.
+    pub rounds_results: RoundsResult,
+    /// The game's winner. Will be null if the game ended with a draw or still running.
+    pub winner: Option<Uuid>,
+    /// The won reason. Will be null if the game ended with a draw or still running.
+    pub won_reason: Option<GameOverReason>,
+    /// The game's creation date.
+    pub created_at: chrono::NaiveDateTime,
+    /// The game's end date. Will be null if the game still running.
+    pub ended_at: Option<chrono::NaiveDateTime>,
+}
+
+impl Default for UserExportSchema {
+    fn default() -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        let user = UserSchema::default();
+        Self {
+            profile_image: None,
+            sessions: vec![SessionSchema::default()],
+            username_history: vec![UsernameHistorySchema {
+                username: "OldUsername".to_owned(),
+                changed_at: now,
+            }],
+            identities: Vec::new(),
+            games: vec![ExportedGameSchema {
+                uuid: Uuid::new_v4(),
+                x_player: user.uuid,
+                o_player: Uuid::new_v4(),
+                rounds_results: RoundsResult::default(),
+                winner: Some(user.uuid),
+                won_reason: Some(GameOverReason::PlayerWon),
+                created_at: now,
+                ended_at: Some(now),
+            }],
+            user,
+        }
+    }
+}
+
+impl ExportedGameSchema {
+    /// Create new [`ExportedGameSchema`] instance from [`GameModel`]
+    pub(crate) fn from_game(game: GameModel) -> ApiResult<Self> {
+        Ok(Self {
+            uuid: game.uuid,
+            x_player: game.x_player,
+            o_player: game.o_player,
+            rounds_results: game.parse_rounds_result()?,
+            winner: game.winner,
+            won_reason: game.parse_reason()?,
+            created_at: game.created_at,
+            ended_at: game.ended_at,
+        })
+    }
+}
@@ -14,12 +14,16 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::{fmt, str::FromStr};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use entity::prelude::*;
-use salvo::oapi::ToSchema;
+use salvo::oapi::{ToParameters, ToSchema};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
+    api::exts::GameModelExt,
     db_utils,
     errors::{ApiError, ApiResult},
 };
@@ -46,6 +50,61 @@ pub struct GameSchema {
     pub created_at: chrono::NaiveDateTime,
 }
 
+/// The game result from the player side.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[salvo(schema(symbol = "GameResultSchema", example = json!(GameResult::Win)))]
+pub enum GameResult {
+    /// The player won the game.
+    Win,
+    /// The player lost the game.
+    Loss,
+    /// The game ended with a draw.
+    Draw,
+}
+
+/// The games filter. It's used to filter and paginate the games history.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToParameters)]
+#[salvo(parameters(default_parameter_in = Query))]
+pub struct GamesFilterSchema {
+    /// Only the games of this player.
+    pub player: Option<Uuid>,
+    /// Only the games against this opponent. Requires the `player` filter.
+    pub opponent: Option<Uuid>,
+    /// Only the games with this result, from the player side. Requires the `player` filter.
+    pub result: Option<GameResult>,
+    /// Only the games that ended with this reason.
+    pub reason: Option<GameOverReason>,
+    /// Only the games created at or after this date.
+    pub from: Option<chrono::NaiveDateTime>,
+    /// Only the games created at or before this date.
+    pub to: Option<chrono::NaiveDateTime>,
+    /// The cursor of the page. Get it from the `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// The page size. Default is 10.
+    #[salvo(parameter(minimum = 1, maximum = 50))]
+    pub limit: Option<u64>,
+}
+
+/// The games page schema. It's used to return a page of the games history.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "GamesPageSchema", example = json!(GamesPageSchema::default())))]
+pub struct GamesPageSchema {
+    /// The page games, sorted by the creation date (newest first).
+    pub games: Vec<GameSchema>,
+    /// The cursor of the next page. Will be null if there is no more games.
+    pub next_cursor: Option<String>,
+}
+
+/// The games pagination cursor. It's the creation date and the id of the last game in the page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GamesCursor {
+    /// The creation date of the last game in the page.
+    pub created_at: chrono::NaiveDateTime,
+    /// The id of the last game in the page.
+    pub id: i32,
+}
+
 impl Default for GameSchema {
     fn default() -> Self {
         Self {
@@ -61,27 +120,86 @@ impl Default for GameSchema {
 }
 
 impl GameSchema {
+    /// Create new [`GameSchema`] instance from [`GameModel`], the players are shown as the requester sees them.
     pub(crate) async fn from_game(
         conn: &sea_orm::DatabaseConnection,
         game: GameModel,
+        requester: Option<Uuid>,
     ) -> ApiResult<Self> {
-        let get_player = |player_uuid| async move {
+        // The players that deleted their accounts are shown with their frozen usernames
+        let get_player = |player_uuid, player_name| async move {
             match db_utils::get_user(conn, player_uuid).await {
-                Ok(user) => UserSchema::from_active_model(conn, user).await,
-                Err(ApiError::UserNotFound) => Ok(UserSchema::deleted_user()),
+                Ok(user) if user.deleted_at.as_ref().is_some() => {
+                    Ok(UserSchema::deleted_user(Some(user.username.unwrap())))
+                }
+                Ok(user) => UserSchema::visible_from_active_model(conn, user, requester).await,
+                Err(ApiError::UserNotFound) => Ok(UserSchema::deleted_user(player_name)),
                 Err(err) => Err(err),
             }
         };
 
         Ok(Self::new(
             game.uuid,
-            get_player(game.x_player).await?,
-            get_player(game.o_player).await?,
-            game.rounds_result.parse().expect("Is valid rounds result"),
+            get_player(game.x_player, game.x_player_name.clone()).await?,
+            get_player(game.o_player, game.o_player_name.clone()).await?,
+            game.parse_rounds_result()?,
             game.winner,
-            game.reason
-                .map(|reason| reason.parse().expect("Is valid game over reason")),
+            game.parse_reason()?,
             game.created_at,
         ))
     }
 }
+
+impl Default for GamesPageSchema {
+    fn default() -> Self {
+        Self {
+            games: vec![GameSchema::default()],
+            next_cursor: Some("<NEXT_CURSOR>".to_owned()),
+        }
+    }
+}
+
+impl From<&GameModel> for GamesCursor {
+    fn from(game: &GameModel) -> Self {
+        Self {
+            created_at: game.created_at,
+            id: game.id,
+        }
+    }
+}
+
+impl fmt::Display for GamesCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            URL_SAFE_NO_PAD.encode(format!(
+                "{}:{}",
+                self.created_at.timestamp_micros(),
+                self.id
+            ))
+        )
+    }
+}
+
+impl FromStr for GamesCursor {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cursor = URL_SAFE_NO_PAD
+            .decode(s)
+            .ok()
+            .and_then(|cursor| String::from_utf8(cursor).ok())
+            .ok_or(ApiError::InvalidCursor)?;
+        let (created_at, id) = cursor.split_once(':').ok_or(ApiError::InvalidCursor)?;
+
+        Ok(Self {
+            created_at: created_at
+                .parse()
+                .ok()
+                .and_then(chrono::NaiveDateTime::from_timestamp_micros)
+                .ok_or(ApiError::InvalidCursor)?,
+            id: id.parse().map_err(|_| ApiError::InvalidCursor)?,
+        })
+    }
+}
@@ -19,7 +19,11 @@ use salvo::oapi::ToSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{db_utils, errors::ApiResult};
+use crate::{
+    db_utils,
+    errors::{ApiError, ApiResult},
+    schemas::PrivacySettingsSchema,
+};
 
 /// The user's schema. It's used to return the user's data.
 #[derive(PartialEq, Eq, Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -41,10 +45,19 @@ pub struct UserSchema {
     pub losts: i64,
     /// The user's draw games.
     pub draw: i64,
-    /// The last 10 games the player has played.
+    /// The last 10 games the player has played. Use `/games?player=<uuid>` to get the full history.
     pub latest_games: Vec<Uuid>,
     /// The user's creation date. Joined date.
     pub created_at: chrono::NaiveDateTime,
+    /// The user's email. Only returned to the user himself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Whether the user's email is verified. Only returned to the user himself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+    /// The account's deletion date, it can be restored until the grace period ends. Only returned to the user himself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<chrono::NaiveDateTime>,
 }
 
 /// The new user's schema. It's used to create a new user.
@@ -61,6 +74,10 @@ pub struct NewUserSchema {
     /// It can only contain English letters, numbers, and underscores.
     #[salvo(schema(min_length = 3, max_length = 32))]
     pub username: String,
+    /// The user's email. It must be unique, regardless of its case. Optional, a verification link will be sent to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[salvo(schema(max_length = 254))]
+    pub email: Option<String>,
     /// The user's password.
     /// - It must be between 8 and 64 characters.
     /// - It can't contain spaces.
@@ -91,11 +108,31 @@ pub struct UserSigninSchema {
     #[serde(flatten)]
     pub user: UserSchema,
     /// The JWT token. It must be sent in the `Authorization` header.
-    /// Will expire in 1 hour.
+    /// Will expire in 1 hour by default.
     pub jwt: String,
     /// The refresh token. It must be sent in the `Authorization` header.
-    /// Will be available after 58 minutes and will expire in 3 hours.
+    /// Will be available after 58 minutes and will expire in 3 hours by default. It can only be used one time.
     pub refresh_token: String,
+    /// The failed signin attempts since the last signin. Only returned by the signin when there are any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_signins: Option<FailedSigninsSchema>,
+    /// The password recovery codes. Only returned by the signup, they will not be shown again, so they must be saved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+/// The failed signin attempts schema. It's used to warn the user about the failed attempts to signin to the account.
+#[derive(PartialEq, Eq, Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "FailedSigninsSchema", example = json!(FailedSigninsSchema::default())))]
+pub struct FailedSigninsSchema {
+    /// The number of the failed attempts.
+    pub count: u64,
+    /// The date of the last failed attempt.
+    pub last_attempted_at: chrono::NaiveDateTime,
+    /// The IP address of the last failed attempt.
+    pub last_ip: Option<String>,
+    /// The user agent of the last failed attempt.
+    pub last_user_agent: Option<String>,
 }
 
 /// The captcha schema. It's used to return the captcha token and the captcha image.
@@ -110,23 +147,91 @@ pub struct CaptchaSchema {
     pub expired_at: chrono::NaiveDateTime,
 }
 
+/// The JSON Web Key schema. It's a public key that verifies the XORS tokens.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[salvo(schema(symbol = "JwkSchema", example = json!(JwkSchema::default())))]
+pub struct JwkSchema {
+    /// The key type, `RSA` or `OKP`.
+    pub kty: String,
+    /// The key usage, it's always `sig`.
+    #[serde(rename = "use")]
+    pub key_use: String,
+    /// The key algorithm, `RS256` or `EdDSA`.
+    pub alg: String,
+    /// The key id, it's the `kid` header of the tokens signed by this key.
+    pub kid: String,
+    /// The curve of the `OKP` key, it's always `Ed25519`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    /// The public key of the `OKP` key, base64url encoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    /// The modulus of the `RSA` key, base64url encoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    /// The exponent of the `RSA` key, base64url encoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+}
+
+/// The JSON Web Key Set schema. It's the active public keys.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[salvo(schema(symbol = "JwksSchema", example = json!(JwksSchema { keys: vec![JwkSchema::default()] })))]
+pub struct JwksSchema {
+    /// The public keys.
+    pub keys: Vec<JwkSchema>,
+}
+
+impl JwkSchema {
+    /// Create a new `RSA` public key, the modulus and the exponent must be base64url encoded.
+    pub(crate) fn rsa(kid: String, n: String, e: String) -> Self {
+        Self {
+            kty: "RSA".to_owned(),
+            key_use: "sig".to_owned(),
+            alg: "RS256".to_owned(),
+            kid,
+            crv: None,
+            x: None,
+            n: Some(n),
+            e: Some(e),
+        }
+    }
+
+    /// Create a new `Ed25519` public key, the key must be base64url encoded.
+    pub(crate) fn ed25519(kid: String, x: String) -> Self {
+        Self {
+            kty: "OKP".to_owned(),
+            key_use: "sig".to_owned(),
+            alg: "EdDSA".to_owned(),
+            kid,
+            crv: Some("Ed25519".to_owned()),
+            x: Some(x),
+            n: None,
+            e: None,
+        }
+    }
+}
+
 impl UserSchema {
-    /// Returns a deleted user.
+    /// Returns a deleted user, with the player's frozen placeholder name if the game has it.
     ///
     /// This only used when the game's player is deleted, so we can still return the game's data. (It's not saving the real player's data)
-    pub(crate) fn deleted_user() -> Self {
+    pub(crate) fn deleted_user(username: Option<String>) -> Self {
         let nil_uuid = Uuid::nil();
         Self {
             uuid: nil_uuid,
             first_name: "Deleted".to_owned(),
             last_name: Some("Player".to_owned()),
-            username: "Deleted".to_owned(),
-            profile_image_path: format!("profiles/{nil_uuid}"),
+            username: username.unwrap_or_else(|| "Deleted".to_owned()),
+            profile_image_path: "/profiles/default".to_owned(),
             wins: 0,
             losts: 0,
             draw: 0,
             latest_games: Vec::new(),
             created_at: chrono::Utc::now().naive_utc(),
+            email: None,
+            verified: None,
+            deleted_at: None,
         }
     }
 
@@ -152,6 +257,66 @@ impl UserSchema {
                 .map(|g| g.uuid)
                 .collect(),
             created_at: user.created_at.unwrap(),
+            email: None,
+            verified: None,
+            deleted_at: None,
+        })
+    }
+
+    /// Create new [`UserSchema`] instance from [`UserActiveModel`], as the requester sees it.
+    /// The user's privacy settings are applied, except if the requester is the user himself.
+    pub async fn visible_from_active_model(
+        conn: &sea_orm::DatabaseConnection,
+        user: UserActiveModel,
+        requester: Option<Uuid>,
+    ) -> ApiResult<Self> {
+        let user_uuid = *user.uuid.as_ref();
+        if requester == Some(user_uuid) {
+            return Self::from_active_model(conn, user).await;
+        }
+
+        let settings = db_utils::get_privacy_settings(conn, user_uuid).await?;
+        Self::with_privacy_settings(conn, user, settings).await
+    }
+
+    /// Create new [`UserSchema`] instance from [`UserActiveModel`], with the given privacy settings applied.
+    /// It's used when the user's privacy settings are already fetched.
+    pub async fn with_privacy_settings(
+        conn: &sea_orm::DatabaseConnection,
+        user: UserActiveModel,
+        settings: PrivacySettingsSchema,
+    ) -> ApiResult<Self> {
+        if settings.friends_only {
+            return Err(ApiError::PrivateProfile);
+        }
+        let mut user = Self::from_active_model(conn, user).await?;
+        if settings.hide_real_name {
+            user.first_name = user.username.clone();
+            user.last_name = None;
+        }
+        if settings.hide_game_history {
+            user.wins = 0;
+            user.losts = 0;
+            user.draw = 0;
+            user.latest_games.clear();
+        }
+        Ok(user)
+    }
+
+    /// Create new [`UserSchema`] instance from [`UserActiveModel`], with the fields that only returned to the user himself.
+    pub async fn owner_from_active_model(
+        conn: &sea_orm::DatabaseConnection,
+        user: UserActiveModel,
+    ) -> ApiResult<Self> {
+        let email = user.email.clone().unwrap();
+        let verified = *user.email_verified.as_ref();
+        let deleted_at = *user.deleted_at.as_ref();
+
+        Ok(Self {
+            email,
+            verified: Some(verified),
+            deleted_at,
+            ..Self::from_active_model(conn, user).await?
         })
     }
 }
@@ -169,6 +334,9 @@ impl Default for UserSchema {
             draw: 0,
             latest_games: vec![Uuid::new_v4()],
             created_at: chrono::Utc::now().naive_utc(),
+            email: Some("user@example.com".to_owned()),
+            verified: Some(true),
+            deleted_at: None,
         }
     }
 }
@@ -179,6 +347,7 @@ impl Default for NewUserSchema {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "Username".to_owned(),
+            email: Some("user@example.com".to_owned()),
             password: "Password".to_owned(),
         }
     }
@@ -190,10 +359,34 @@ impl Default for UserSigninSchema {
             user: UserSchema::default(),
             jwt: "<JWT>".to_owned(),
             refresh_token: "<REFRESH_TOKEN>".to_owned(),
+            failed_signins: None,
+            recovery_codes: None,
+        }
+    }
+}
+
+impl Default for FailedSigninsSchema {
+    fn default() -> Self {
+        Self {
+            count: 3,
+            last_attempted_at: chrono::Utc::now().naive_utc(),
+            last_ip: Some("127.0.0.1".to_owned()),
+            last_user_agent: Some(
+                "Mozilla/5.0 (X11; Linux x86_64; rv:122.0) Gecko/20100101 Firefox/122.0".to_owned(),
+            ),
         }
     }
 }
 
+impl Default for JwkSchema {
+    fn default() -> Self {
+        Self::ed25519(
+            "2024-01".to_owned(),
+            "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo".to_owned(),
+        )
+    }
+}
+
 impl Default for SigninSchema {
     fn default() -> Self {
         Self {
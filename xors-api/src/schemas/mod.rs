@@ -17,12 +17,17 @@
 use salvo::oapi::ToSchema;
 use serde::{Deserialize, Serialize};
 
+mod export;
 mod game;
 mod jwt;
+mod oidc;
+mod recovery;
+mod session;
+mod totp;
 mod user;
 mod xo;
 
-pub use {game::*, jwt::*, user::*, xo::*};
+pub use {export::*, game::*, jwt::*, oidc::*, recovery::*, session::*, totp::*, user::*, xo::*};
 
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
 #[salvo(schema(symbol = "MessageSchema", example = json!(MessageSchema::new("Message".to_owned()))))]
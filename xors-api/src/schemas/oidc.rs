@@ -0,0 +1,37 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use salvo::oapi::ToSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "OidcAuthorizationSchema", example = json!(OidcAuthorizationSchema::default())))]
+pub struct OidcAuthorizationSchema {
+    /// The provider authorization URL, redirect the user to it. The provider redirects back to the callback endpoint.
+    pub authorization_url: String,
+    /// The expiration date of the authorization, the callback must be reached before it.
+    pub expired_at: chrono::NaiveDateTime,
+}
+
+impl Default for OidcAuthorizationSchema {
+    fn default() -> Self {
+        Self {
+            authorization_url:
+                "https://accounts.example.com/authorize?response_type=code&client_id=xors&..."
+                    .to_owned(),
+            expired_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
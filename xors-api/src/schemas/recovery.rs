@@ -0,0 +1,132 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use salvo::oapi::ToSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "RegenerateRecoveryCodesSchema", example = json!(RegenerateRecoveryCodesSchema::default())))]
+pub struct RegenerateRecoveryCodesSchema {
+    /// The user's password.
+    #[salvo(schema(min_length = 8, max_length = 64))]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "RecoveryEmailSchema", example = json!(RecoveryEmailSchema::default())))]
+pub struct RecoveryEmailSchema {
+    /// The email address that the password reset tokens are sent to, it's the user's email. Null to remove it.
+    #[salvo(schema(max_length = 254))]
+    pub email: Option<String>,
+    /// The user's password.
+    #[salvo(schema(min_length = 8, max_length = 64))]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "ForgotPasswordSchema", example = json!(ForgotPasswordSchema::default())))]
+pub struct ForgotPasswordSchema {
+    /// The user's username.
+    #[salvo(schema(min_length = 3, max_length = 32))]
+    pub username: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "RecoverPasswordSchema", example = json!(RecoverPasswordSchema::default())))]
+pub struct RecoverPasswordSchema {
+    /// The user's username.
+    #[salvo(schema(min_length = 3, max_length = 32))]
+    pub username: String,
+    /// One of the password recovery codes, returned by the signup.
+    pub recovery_code: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "PasswordResetTokenSchema", example = json!(PasswordResetTokenSchema::default())))]
+pub struct PasswordResetTokenSchema {
+    /// The password reset token. Send it with the new password to the `/auth/reset_password` endpoint.
+    pub reset_token: String,
+    /// The expiration date of the reset token.
+    pub expired_at: chrono::NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "CompletePasswordResetSchema", example = json!(CompletePasswordResetSchema::default())))]
+pub struct CompletePasswordResetSchema {
+    /// The password reset token, sent to the verified email or returned by the `/auth/recover_password` endpoint.
+    pub reset_token: String,
+    /// The user's new password.
+    /// - It must be between 8 and 64 characters.
+    /// - It can't contain spaces.
+    /// - It must contain at least one uppercase and lowercase letter.
+    /// - It must contain at least one number.
+    /// - It must contain at least one symbol.
+    /// - It can't be a common password.
+    #[salvo(schema(min_length = 8, max_length = 64))]
+    pub new_password: String,
+}
+
+impl Default for RegenerateRecoveryCodesSchema {
+    fn default() -> Self {
+        Self {
+            password: "Password".to_owned(),
+        }
+    }
+}
+
+impl Default for RecoveryEmailSchema {
+    fn default() -> Self {
+        Self {
+            email: Some("user@example.com".to_owned()),
+            password: "Password".to_owned(),
+        }
+    }
+}
+
+impl Default for ForgotPasswordSchema {
+    fn default() -> Self {
+        Self {
+            username: "Username".to_owned(),
+        }
+    }
+}
+
+impl Default for RecoverPasswordSchema {
+    fn default() -> Self {
+        Self {
+            username: "Username".to_owned(),
+            recovery_code: "a1b2c-3d4e5".to_owned(),
+        }
+    }
+}
+
+impl Default for PasswordResetTokenSchema {
+    fn default() -> Self {
+        Self {
+            reset_token: "<RESET_TOKEN>".to_owned(),
+            expired_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+impl Default for CompletePasswordResetSchema {
+    fn default() -> Self {
+        Self {
+            reset_token: "<RESET_TOKEN>".to_owned(),
+            new_password: "Password".to_owned(),
+        }
+    }
+}
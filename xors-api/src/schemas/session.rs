@@ -0,0 +1,85 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use entity::prelude::*;
+use salvo::oapi::ToSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The session's schema. Every signin creates a new session.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[salvo(schema(symbol = "SessionSchema", example = json!(SessionSchema::default())))]
+pub struct SessionSchema {
+    /// The session's id.
+    pub id: Uuid,
+    /// The user agent of the client that signed in.
+    pub user_agent: Option<String>,
+    /// The IP address of the client that signed in.
+    pub ip: Option<String>,
+    /// The session's creation date. The signin date.
+    pub created_at: chrono::NaiveDateTime,
+    /// The last time the session's refresh token was used.
+    pub last_refresh_at: Option<chrono::NaiveDateTime>,
+    /// The session's expiration date, if it's not refreshed before.
+    pub expires_at: chrono::NaiveDateTime,
+    /// Whether the session is the one of the request's token.
+    pub current: bool,
+}
+
+/// The new session's schema. It's used to create a new session.
+#[derive(Clone, Debug, Default)]
+pub struct NewSessionSchema {
+    /// The user agent of the client.
+    pub user_agent: Option<String>,
+    /// The IP address of the client.
+    pub ip: Option<String>,
+}
+
+impl Default for SessionSchema {
+    fn default() -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: Uuid::new_v4(),
+            user_agent: Some(
+                "Mozilla/5.0 (X11; Linux x86_64; rv:122.0) Gecko/20100101 Firefox/122.0".to_owned(),
+            ),
+            ip: Some("127.0.0.1".to_owned()),
+            created_at: now,
+            last_refresh_at: None,
+            expires_at: now + chrono::Duration::hours(3),
+            current: true,
+        }
+    }
+}
+
+impl SessionSchema {
+    /// Create new [`SessionSchema`] instance from [`SessionModel`], the session expires with its last refresh token.
+    pub(crate) fn from_session(
+        session: SessionModel,
+        expires_at: chrono::NaiveDateTime,
+        current_session: Option<Uuid>,
+    ) -> Self {
+        Self {
+            current: current_session == Some(session.uuid),
+            id: session.uuid,
+            user_agent: session.user_agent,
+            ip: session.ip,
+            created_at: session.created_at,
+            last_refresh_at: session.last_refresh_at,
+            expires_at,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use salvo::oapi::ToSchema;
+use serde::{Deserialize, Serialize};
+
+use super::UserSigninSchema;
+
+/// The two-factor authentication enrollment schema. It's used to add the account to an authenticator app.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "TotpEnrollmentSchema", example = json!(TotpEnrollmentSchema::default())))]
+pub struct TotpEnrollmentSchema {
+    /// The TOTP secret as base32, to be entered manually in the authenticator app.
+    pub secret: String,
+    /// The provisioning URI, the `otpauth://` URI of the account.
+    pub provisioning_uri: String,
+    /// The provisioning URI QR code. It's a base64 PNG image.
+    pub qr_code: String,
+}
+
+/// The two-factor authentication code schema.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "TotpCodeSchema", example = json!(TotpCodeSchema::default())))]
+pub struct TotpCodeSchema {
+    /// The 6 digits code of the authenticator app.
+    #[salvo(schema(min_length = 6, max_length = 6))]
+    pub code: String,
+}
+
+/// The recovery codes schema. Each recovery code can be used one time instead of the authenticator app code.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "RecoveryCodesSchema", example = json!(RecoveryCodesSchema::default())))]
+pub struct RecoveryCodesSchema {
+    /// The recovery codes. They will not be shown again, so they must be saved.
+    pub recovery_codes: Vec<String>,
+}
+
+/// The disable two-factor authentication schema.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "DisableTotpSchema", example = json!(DisableTotpSchema::default())))]
+pub struct DisableTotpSchema {
+    /// The user's password.
+    #[salvo(schema(min_length = 8, max_length = 64))]
+    pub password: String,
+    /// The authenticator app code or a recovery code.
+    pub code: String,
+}
+
+/// The two-factor authentication challenge schema. It's returned by the signin when the two-factor authentication is enabled.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "TotpChallengeSchema", example = json!(TotpChallengeSchema::default())))]
+pub struct TotpChallengeSchema {
+    /// The challenge token. Send it with the code to the `/auth/signin/totp` endpoint to complete the signin.
+    pub challenge_token: String,
+    /// The expiration date of the challenge token.
+    pub expired_at: chrono::NaiveDateTime,
+}
+
+/// The two-factor authentication signin schema. It's used to complete the signin.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "TotpSigninSchema", example = json!(TotpSigninSchema::default())))]
+pub struct TotpSigninSchema {
+    /// The challenge token, returned by the signin.
+    pub challenge_token: String,
+    /// The authenticator app code or a recovery code.
+    pub code: String,
+}
+
+/// The signin response schema. It's the user's signin, or a challenge if the two-factor authentication is enabled.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(untagged)]
+#[salvo(schema(symbol = "SigninResponseSchema"))]
+pub enum SigninResponseSchema {
+    /// The user is signed in.
+    Signin(Box<UserSigninSchema>),
+    /// The user must complete the signin with the two-factor authentication code.
+    TotpChallenge(TotpChallengeSchema),
+}
+
+impl Default for TotpEnrollmentSchema {
+    fn default() -> Self {
+        Self {
+            secret: "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP".to_owned(),
+            provisioning_uri: "otpauth://totp/XORS:Username?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&issuer=XORS&algorithm=SHA1&digits=6&period=30".to_owned(),
+            qr_code: "<QR_CODE_BASE64>".to_owned(),
+        }
+    }
+}
+
+impl Default for TotpCodeSchema {
+    fn default() -> Self {
+        Self {
+            code: "123456".to_owned(),
+        }
+    }
+}
+
+impl Default for RecoveryCodesSchema {
+    fn default() -> Self {
+        Self {
+            recovery_codes: vec!["a1b2c-3d4e5".to_owned(), "f6g7h-8i9j0".to_owned()],
+        }
+    }
+}
+
+impl Default for DisableTotpSchema {
+    fn default() -> Self {
+        Self {
+            password: "Password".to_owned(),
+            code: "123456".to_owned(),
+        }
+    }
+}
+
+impl Default for TotpChallengeSchema {
+    fn default() -> Self {
+        Self {
+            challenge_token: "<CHALLENGE_TOKEN>".to_owned(),
+            expired_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+impl Default for TotpSigninSchema {
+    fn default() -> Self {
+        Self {
+            challenge_token: "<CHALLENGE_TOKEN>".to_owned(),
+            code: "123456".to_owned(),
+        }
+    }
+}
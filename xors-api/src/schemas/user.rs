@@ -14,8 +14,20 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use salvo::oapi::ToSchema;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use entity::prelude::*;
+use salvo::oapi::{ToParameters, ToSchema};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    api::exts::GameModelExt,
+    errors::{ApiError, ApiResult},
+};
+
+use super::{GameOverReason, UserSchema, XoSymbol};
 
 /// The delete user schema.
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
@@ -35,12 +47,48 @@ pub struct UpdateUserSchema {
     /// The user's last name.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_name: Option<String>,
-    /// Profile png image base64 encoded. If it's not provided, the profile image will be deleted.
-    /// Must be 128x128 pixels and less than 1MB in size.
+    /// Profile png, jpeg or webp image base64 encoded. If it's not provided, the profile image will not be changed.
+    /// Must be less than 5MB in size and at most 4096x4096 pixels, it will be center-cropped and resized to 128x128 pixels.
+    /// Prefer the `/user/profile_image` endpoints, they upload the image without base64 encoding it.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub profile_image: Option<String>,
 }
 
+/// The profile image upload schema. It's the `multipart/form-data` form of the profile image upload.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "ProfileImageUploadSchema"))]
+pub struct ProfileImageUploadSchema {
+    /// The png, jpeg or webp image file.
+    #[salvo(schema(value_type = String, format = Binary))]
+    pub image: String,
+}
+
+/// The update username schema. It's used to change the user's username.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "UpdateUsernameSchema", example = json!(UpdateUsernameSchema::default())))]
+pub struct UpdateUsernameSchema {
+    /// The user's new username.
+    /// - It must be between 3 and 32 characters.
+    /// - It must start with a letter and only contain English letters, numbers, and underscores.
+    #[salvo(schema(min_length = 3, max_length = 32))]
+    pub username: String,
+    /// The user's password.
+    #[salvo(schema(min_length = 8, max_length = 64))]
+    pub password: String,
+}
+
+/// The update email schema. It's used to change the user's email.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "UpdateEmailSchema", example = json!(UpdateEmailSchema::default())))]
+pub struct UpdateEmailSchema {
+    /// The user's new email. It must be unique, regardless of its case. Null to remove it.
+    #[salvo(schema(max_length = 254))]
+    pub email: Option<String>,
+    /// The user's password.
+    #[salvo(schema(min_length = 8, max_length = 64))]
+    pub password: String,
+}
+
 /// Reset password schema. It's used to reset the user's password.
 #[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[salvo(schema(symbol = "ResetPasswordSchema", example = json!(ResetPasswordSchema::default())))]
@@ -60,6 +108,126 @@ pub struct ResetPasswordSchema {
     pub new_password: String,
 }
 
+/// The user's privacy settings schema. The settings are applied to everyone except the user himself.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[salvo(schema(symbol = "PrivacySettingsSchema", example = json!(PrivacySettingsSchema::default())))]
+pub struct PrivacySettingsSchema {
+    /// Hide the user's first and last name, the username is shown as the first name.
+    pub hide_real_name: bool,
+    /// Hide the user's games, latest games and statistics.
+    pub hide_game_history: bool,
+    /// Show the user's profile and games to his friends only.
+    ///
+    /// There are no friends yet, so it currently means private: the profile and games are hidden from everyone.
+    pub friends_only: bool,
+}
+
+impl From<PrivacySettingModel> for PrivacySettingsSchema {
+    fn from(settings: PrivacySettingModel) -> Self {
+        Self {
+            hide_real_name: settings.hide_real_name,
+            hide_game_history: settings.hide_game_history,
+            friends_only: settings.friends_only,
+        }
+    }
+}
+
+/// The users search schema. It's used to search and paginate the users.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToParameters)]
+#[salvo(parameters(default_parameter_in = Query))]
+pub struct UsersSearchSchema {
+    /// The search query, matched with the username and the first and last name.
+    #[salvo(parameter(min_length = 1, max_length = 50))]
+    pub q: String,
+    /// The cursor of the page. Get it from the `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    /// The page size. Default is 10.
+    #[salvo(parameter(minimum = 1, maximum = 50))]
+    pub limit: Option<u64>,
+}
+
+/// The users page schema. It's used to return a page of the users search results.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "UsersPageSchema", example = json!(UsersPageSchema::default())))]
+pub struct UsersPageSchema {
+    /// The page users, the username prefix matches first, then the name prefix matches, then the fuzzy matches.
+    pub users: Vec<UserSchema>,
+    /// The cursor of the next page. Will be null if there is no more users.
+    pub next_cursor: Option<String>,
+}
+
+/// The users search pagination cursor. It's the match rank and the lowercase username of the last user in the page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UsersCursor {
+    /// The match rank of the last user in the page.
+    pub rank: i32,
+    /// The lowercase username of the last user in the page.
+    pub username: String,
+}
+
+/// The wins, losses and draws schema.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[salvo(schema(symbol = "ResultsSchema", example = json!(ResultsSchema::default())))]
+pub struct ResultsSchema {
+    /// The wins count.
+    pub wins: u64,
+    /// The losses count.
+    pub losses: u64,
+    /// The draws count.
+    pub draws: u64,
+}
+
+/// The versus schema. It's used to return the head-to-head statistics between two users.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[salvo(schema(symbol = "VersusSchema", example = json!(VersusSchema::default())))]
+pub struct VersusSchema {
+    /// The user's uuid. The statistics are from this user side.
+    pub user: Uuid,
+    /// The other user's uuid.
+    pub other_user: Uuid,
+    /// The games results between the two users.
+    pub games: ResultsSchema,
+    /// The rounds results between the two users.
+    pub rounds: ResultsSchema,
+    /// The last shared games between the two users, newest first.
+    pub latest_games: Vec<Uuid>,
+}
+
+/// The user's statistics as one symbol.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[salvo(schema(symbol = "SymbolStatsSchema", example = json!(SymbolStatsSchema::default())))]
+pub struct SymbolStatsSchema {
+    /// The games results.
+    pub games: ResultsSchema,
+    /// The win rate, between 0 and 1. Will be null if there is no games.
+    pub win_rate: Option<f64>,
+}
+
+/// The user's statistics schema. It's computed from the user's ended games.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+#[salvo(schema(symbol = "UserStatsSchema", example = json!(UserStatsSchema::default())))]
+pub struct UserStatsSchema {
+    /// The user's uuid.
+    pub user: Uuid,
+    /// The statistics of the games that the user played as X.
+    pub as_x: SymbolStatsSchema,
+    /// The statistics of the games that the user played as O.
+    pub as_o: SymbolStatsSchema,
+    /// The rounds results of all the games.
+    pub rounds: ResultsSchema,
+    /// The most common opening cell, the first cell the user played when he starts the round as X.
+    /// Will be null if the user never started a round.
+    pub most_common_opening: Option<u32>, // HACK: The `u32` is used because the `u8` looks like binary in the swagger ui.
+    /// The moves that the server played for the user, because he took too long to play.
+    pub auto_played_moves: u64,
+    /// The games that the user lost because he disconnected.
+    pub disconnect_losses: u64,
+    /// The current wins streak.
+    pub current_win_streak: u64,
+    /// The best wins streak.
+    pub best_win_streak: u64,
+}
+
 impl Default for DeleteUserSchema {
     fn default() -> Self {
         Self {
@@ -78,6 +246,24 @@ impl Default for UpdateUserSchema {
     }
 }
 
+impl Default for UpdateUsernameSchema {
+    fn default() -> Self {
+        Self {
+            username: "NewUsername".to_owned(),
+            password: "Password".to_owned(),
+        }
+    }
+}
+
+impl Default for UpdateEmailSchema {
+    fn default() -> Self {
+        Self {
+            email: Some("user@example.com".to_owned()),
+            password: "Password".to_owned(),
+        }
+    }
+}
+
 impl Default for ResetPasswordSchema {
     fn default() -> Self {
         Self {
@@ -86,3 +272,167 @@ impl Default for ResetPasswordSchema {
         }
     }
 }
+
+impl Default for VersusSchema {
+    fn default() -> Self {
+        Self {
+            user: Uuid::new_v4(),
+            other_user: Uuid::new_v4(),
+            games: ResultsSchema::default(),
+            rounds: ResultsSchema::default(),
+            latest_games: vec![Uuid::new_v4()],
+        }
+    }
+}
+
+impl Default for UsersPageSchema {
+    fn default() -> Self {
+        Self {
+            users: vec![UserSchema::default()],
+            next_cursor: Some("<NEXT_CURSOR>".to_owned()),
+        }
+    }
+}
+
+impl UsersCursor {
+    /// Create a new [`UsersCursor`] from the match rank and the user's username.
+    pub fn new(rank: i32, username: &str) -> Self {
+        Self {
+            rank,
+            username: username.to_lowercase(),
+        }
+    }
+}
+
+impl fmt::Display for UsersCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            URL_SAFE_NO_PAD.encode(format!("{}:{}", self.rank, self.username))
+        )
+    }
+}
+
+impl FromStr for UsersCursor {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cursor = URL_SAFE_NO_PAD
+            .decode(s)
+            .ok()
+            .and_then(|cursor| String::from_utf8(cursor).ok())
+            .ok_or(ApiError::InvalidCursor)?;
+        let (rank, username) = cursor.split_once(':').ok_or(ApiError::InvalidCursor)?;
+
+        Ok(Self {
+            rank: rank.parse().map_err(|_| ApiError::InvalidCursor)?,
+            username: username.to_owned(),
+        })
+    }
+}
+
+impl Default for UserStatsSchema {
+    fn default() -> Self {
+        Self {
+            user: Uuid::new_v4(),
+            as_x: SymbolStatsSchema::default(),
+            as_o: SymbolStatsSchema::default(),
+            rounds: ResultsSchema::default(),
+            most_common_opening: Some(4),
+            auto_played_moves: 0,
+            disconnect_losses: 0,
+            current_win_streak: 0,
+            best_win_streak: 0,
+        }
+    }
+}
+
+impl UserStatsSchema {
+    /// Computes the user's statistics from his ended games. The games must be sorted by the creation date (oldest first).
+    pub(crate) fn from_games(user_uuid: Uuid, games: &[GameModel]) -> ApiResult<Self> {
+        let mut stats = Self {
+            user: user_uuid,
+            as_x: SymbolStatsSchema::default(),
+            as_o: SymbolStatsSchema::default(),
+            rounds: ResultsSchema::default(),
+            most_common_opening: None,
+            auto_played_moves: 0,
+            disconnect_losses: 0,
+            current_win_streak: 0,
+            best_win_streak: 0,
+        };
+        let mut openings: HashMap<u32, u64> = HashMap::new();
+
+        for game in games {
+            let (symbol, other_symbol) = if game.x_player == user_uuid {
+                (XoSymbol::X, XoSymbol::O)
+            } else {
+                (XoSymbol::O, XoSymbol::X)
+            };
+            let symbol_stats = match symbol {
+                XoSymbol::X => &mut stats.as_x,
+                XoSymbol::O => &mut stats.as_o,
+            };
+
+            match game.winner {
+                Some(winner) if winner == user_uuid => {
+                    symbol_stats.games.wins += 1;
+                    stats.current_win_streak += 1;
+                    stats.best_win_streak = stats.best_win_streak.max(stats.current_win_streak);
+                }
+                Some(_) => {
+                    symbol_stats.games.losses += 1;
+                    stats.current_win_streak = 0;
+                    if matches!(
+                        game.parse_reason()?,
+                        Some(GameOverReason::PlayerDisconnected)
+                    ) {
+                        stats.disconnect_losses += 1;
+                    }
+                }
+                None => {
+                    symbol_stats.games.draws += 1;
+                    stats.current_win_streak = 0;
+                }
+            }
+
+            let rounds_result = game.parse_rounds_result()?;
+            stats.rounds.wins += rounds_result.wins(&symbol) as u64;
+            stats.rounds.losses += rounds_result.wins(&other_symbol) as u64;
+            stats.rounds.draws += rounds_result.draws as u64;
+
+            stats.auto_played_moves += match symbol {
+                XoSymbol::X => game.x_auto_plays,
+                XoSymbol::O => game.o_auto_plays,
+            } as u64;
+
+            // The X player always starts the round
+            if symbol == XoSymbol::X {
+                for opening in rounds_result
+                    .boards()
+                    .iter()
+                    .filter_map(|board| board.played_cells().first())
+                {
+                    *openings.entry(*opening).or_default() += 1;
+                }
+            }
+        }
+
+        for symbol_stats in [&mut stats.as_x, &mut stats.as_o] {
+            let games =
+                symbol_stats.games.wins + symbol_stats.games.losses + symbol_stats.games.draws;
+            symbol_stats.win_rate =
+                (games != 0).then(|| symbol_stats.games.wins as f64 / games as f64);
+        }
+        // The smallest cell wins the tie, so the result is stable.
+        stats.most_common_opening = openings
+            .into_iter()
+            .max_by(|(cell, count), (other_cell, other_count)| {
+                count.cmp(other_count).then(other_cell.cmp(cell))
+            })
+            .map(|(cell, _)| cell);
+
+        Ok(stats)
+    }
+}
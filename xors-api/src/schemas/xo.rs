@@ -18,12 +18,14 @@ pub use self::api::*;
 pub use self::websocket::*;
 
 mod websocket {
-    use std::str::FromStr;
+    use std::{fmt, str::FromStr};
 
     use salvo::{oapi::ToSchema, websocket::Message};
     use serde::{Deserialize, Serialize};
     use uuid::Uuid;
 
+    use crate::errors::GameParseError;
+
     /// The Xo websocket server event.
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct XoServerEvent {
@@ -262,12 +264,12 @@ mod websocket {
         }
     }
 
-    impl ToString for GameOverReason {
-        fn to_string(&self) -> String {
+    impl fmt::Display for GameOverReason {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             match self {
-                GameOverReason::PlayerWon => "Player Won".to_owned(),
-                GameOverReason::Draw => "Draw".to_owned(),
-                GameOverReason::PlayerDisconnected => "Player Disconnected".to_owned(),
+                GameOverReason::PlayerWon => write!(f, "Player Won"),
+                GameOverReason::Draw => write!(f, "Draw"),
+                GameOverReason::PlayerDisconnected => write!(f, "Player Disconnected"),
             }
         }
     }
@@ -282,14 +284,14 @@ mod websocket {
     }
 
     impl FromStr for GameOverReason {
-        type Err = ();
+        type Err = GameParseError;
 
         fn from_str(s: &str) -> Result<Self, Self::Err> {
             match s {
                 "Player Won" => Ok(Self::PlayerWon),
                 "Draw" => Ok(Self::Draw),
                 "Player Disconnected" => Ok(Self::PlayerDisconnected),
-                _ => Err(()),
+                _ => Err(GameParseError::InvalidGameOverReason(s.to_owned())),
             }
         }
     }
@@ -297,11 +299,13 @@ mod websocket {
 
 /// API schemas.
 mod api {
-    use std::str::FromStr;
+    use std::{fmt, str::FromStr};
 
     use salvo::prelude::*;
     use serde::{Deserialize, Serialize};
 
+    use crate::errors::GameParseError;
+
     /// The Xo symbol.
     #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, ToSchema)]
     #[salvo(schema(symbol = "XoSymbolSchema", example = json!{XoSymbol::X}))]
@@ -313,7 +317,7 @@ mod api {
     }
 
     /// The XO game board.
-    #[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default, ToSchema)]
     #[salvo(schema(symbol = "BoardSchema", example = json!(Board::default())))]
     pub struct Board {
         /// The board cells.
@@ -325,7 +329,7 @@ mod api {
     }
 
     /// The XO rounds result.
-    #[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default, ToSchema)]
     #[salvo(schema(symbol = "RoundsResultSchema"))]
     pub struct RoundsResult {
         /// The X player rounds wins.
@@ -347,6 +351,11 @@ mod api {
             }
         }
 
+        /// Returns the rounds boards.
+        pub fn boards(&self) -> &[Board] {
+            &self.boards
+        }
+
         /// Add a win to the symbol.
         pub fn add_win(&mut self, symbol: &XoSymbol) {
             match symbol {
@@ -355,6 +364,49 @@ mod api {
             }
         }
 
+        /// Validates the rounds result. Will check that:
+        /// - The rounds are at most 3.
+        /// - Each round board is valid and ended, see [`Board::validate`].
+        /// - The wins and draws are matching the rounds boards.
+        pub fn validate(&self) -> Result<(), GameParseError> {
+            if self.boards.len() > 3 {
+                return Err(GameParseError::TooManyRounds(self.boards.len()));
+            }
+
+            let (mut x_player, mut o_player, mut draws) = (0, 0, 0);
+            for (index, board) in self.boards.iter().enumerate() {
+                board
+                    .validate()
+                    .and_then(|_| {
+                        board
+                            .is_end()
+                            .then_some(())
+                            .ok_or(GameParseError::UnendedBoard)
+                    })
+                    .map_err(|err| GameParseError::InvalidRoundBoard {
+                        round: index + 1,
+                        source: Box::new(err),
+                    })?;
+
+                if board.is_win(&XoSymbol::X) {
+                    x_player += 1;
+                } else if board.is_win(&XoSymbol::O) {
+                    o_player += 1;
+                } else {
+                    draws += 1;
+                }
+            }
+
+            if (x_player, o_player, draws) != (self.x_player, self.o_player, self.draws) {
+                return Err(GameParseError::RoundsResultMismatch {
+                    x_player,
+                    o_player,
+                    draws,
+                });
+            }
+            Ok(())
+        }
+
         /// Add board to the rounds result.
         /// The board must be of an ended round.
         ///
@@ -381,6 +433,11 @@ mod api {
             self.played_cells.push(index as u32);
         }
 
+        /// Returns the played cells in order.
+        pub fn played_cells(&self) -> &[u32] {
+            &self.played_cells
+        }
+
         /// Check if the cell is empty.
         pub fn is_empty_cell(&self, index: u8) -> bool {
             self.cells[index as usize].is_none()
@@ -423,6 +480,48 @@ mod api {
             self.is_full() && !self.is_win(&XoSymbol::X) && !self.is_win(&XoSymbol::O)
         }
 
+        /// Validates the board by replaying the played cells. Will check that:
+        /// - The played cells are between 0 and 8 and each cell is played once.
+        /// - The symbols are alternating, starting from the X symbol.
+        /// - There is no played cell after the board is ended.
+        /// - The board cells are exactly the played cells.
+        pub fn validate(&self) -> Result<(), GameParseError> {
+            let mut replayed = Self::default();
+
+            for &cell in &self.played_cells {
+                let index = u8::try_from(cell)
+                    .ok()
+                    .filter(|&index| index <= 8)
+                    .ok_or(GameParseError::PlayedCellOutOfRange(cell))?;
+
+                if !replayed.is_empty_cell(index) {
+                    return Err(GameParseError::DuplicatePlayedCell(index));
+                }
+                if replayed.is_end() {
+                    return Err(GameParseError::PlayedAfterEnd(index));
+                }
+                let turn = replayed.turn();
+                match self.cells[index as usize] {
+                    None => return Err(GameParseError::EmptyPlayedCell(index)),
+                    Some(symbol) if symbol != turn => {
+                        return Err(GameParseError::WrongTurn {
+                            cell: index,
+                            expected: turn,
+                            found: symbol,
+                        })
+                    }
+                    Some(_) => replayed.set_cell(index, turn),
+                }
+            }
+
+            if let Some(index) = (0..=8).find(|&index| {
+                self.cells[index as usize].is_some() && replayed.is_empty_cell(index)
+            }) {
+                return Err(GameParseError::UnplayedCell(index));
+            }
+            Ok(())
+        }
+
         /// Check if the symbol is win.
         pub fn is_win(&self, symbol: &XoSymbol) -> bool {
             crate::api::xo::WINNING_COMBINATIONS
@@ -435,39 +534,35 @@ mod api {
         }
     }
 
-    impl ToString for XoSymbol {
-        fn to_string(&self) -> String {
+    impl fmt::Display for XoSymbol {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             match self {
-                XoSymbol::X => "X".to_owned(),
-                XoSymbol::O => "O".to_owned(),
+                XoSymbol::X => write!(f, "X"),
+                XoSymbol::O => write!(f, "O"),
             }
         }
     }
 
-    impl ToString for Board {
-        fn to_string(&self) -> String {
-            format!(
-                "{}:{}",
-                self.cells
-                    .iter()
-                    .map(|cell| match cell {
-                        Some(symbol) => symbol.to_string(),
-                        None => "-".to_owned(),
-                    })
-                    .collect::<Vec<_>>()
-                    .join(""),
-                self.played_cells
-                    .iter()
-                    .map(|cell| cell.to_string())
-                    .collect::<Vec<_>>()
-                    .join("")
-            )
+    impl fmt::Display for Board {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for cell in &self.cells {
+                match cell {
+                    Some(symbol) => write!(f, "{symbol}")?,
+                    None => write!(f, "-")?,
+                }
+            }
+            write!(f, ":")?;
+            for cell in &self.played_cells {
+                write!(f, "{cell}")?;
+            }
+            Ok(())
         }
     }
 
-    impl ToString for RoundsResult {
-        fn to_string(&self) -> String {
-            format!(
+    impl fmt::Display for RoundsResult {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
                 "{}{}{} {}",
                 "X".repeat(self.x_player),
                 "O".repeat(self.o_player),
@@ -481,58 +576,71 @@ mod api {
         }
     }
 
-    /// The XO symbol. The symbol is either "X" or "O" and "-" for empty cell.
+    /// The XO board. The cells is either "X" or "O" and "-" for empty cell, followed by `:` and the played cells.
+    ///
+    /// The board is decoded strictly, the played cells are replayed and must match the cells. See [`Board::validate`].
     ///
     /// # Errors
-    /// - Will return `Err(())`  if there is an invalid symbol or the string length is not 9.
-    /// - Will return `Err(())` if the played cells is invalid
+    /// - Will return [`GameParseError`] if there is an invalid cell or the cells count is not 9.
+    /// - Will return [`GameParseError`] if the played cells is invalid or not matching the cells.
     ///
     /// # Examples
     /// - "XOXOXOXOX:03214658" is valid.
     impl FromStr for Board {
-        type Err = ();
+        type Err = GameParseError;
 
         fn from_str(s: &str) -> Result<Self, Self::Err> {
-            let (str_board, played_cells) = s.split_once(':').ok_or(())?;
-            let board_chars = str_board.chars().collect::<Vec<_>>();
+            let (str_board, played_cells) = s
+                .split_once(':')
+                .ok_or_else(|| GameParseError::MissingBoardSeparator(s.to_owned()))?;
 
-            if board_chars.len() != 9 {
-                return Err(());
+            if str_board.chars().count() != 9 {
+                return Err(GameParseError::InvalidCellsCount(str_board.chars().count()));
             }
-            if played_cells.chars().count() > 9 || played_cells.chars().any(|c| !c.is_ascii_digit())
-            {
-                return Err(());
+            if played_cells.chars().count() > 9 {
+                return Err(GameParseError::TooManyPlayedCells(
+                    played_cells.chars().count(),
+                ));
             }
 
             let mut board = Self::default();
-            for index in played_cells
-                .chars()
-                .map(|c| c.to_digit(10).expect("The digit is valid") as usize)
-            {
-                if index > 8 {
-                    return Err(());
-                }
-
-                let symbol = match board_chars
-                    .get(index)
-                    .expect("The index is valid because the played cells is valid")
-                {
-                    'X' => XoSymbol::X,
-                    'O' => XoSymbol::O,
-                    _ => return Err(()),
+            for (index, cell) in str_board.chars().enumerate() {
+                board.cells[index] = match cell {
+                    'X' => Some(XoSymbol::X),
+                    'O' => Some(XoSymbol::O),
+                    '-' => None,
+                    _ => return Err(GameParseError::InvalidCell(cell)),
                 };
-                board.set_cell(index as u8, symbol);
             }
+            board.played_cells = played_cells
+                .chars()
+                .map(|cell| {
+                    cell.to_digit(10)
+                        .filter(|&index| index <= 8)
+                        .ok_or(GameParseError::InvalidPlayedCell(cell))
+                })
+                .collect::<Result<_, _>>()?;
+
+            board.validate()?;
             Ok(board)
         }
     }
 
     impl FromStr for RoundsResult {
-        type Err = ();
+        type Err = GameParseError;
 
         fn from_str(s: &str) -> Result<Self, Self::Err> {
-            let (rounds_result, boards) = s.split_once(' ').ok_or(())?;
-            Ok(Self {
+            let (rounds_result, boards) = s
+                .split_once(' ')
+                .ok_or_else(|| GameParseError::MissingRoundsSeparator(s.to_owned()))?;
+            if let Some(result) = rounds_result
+                .chars()
+                .find(|c| !matches!(c, 'X' | 'O' | '-'))
+            {
+                return Err(GameParseError::InvalidRoundResult(result));
+            }
+
+            let rounds_result = Self {
                 x_player: rounds_result.chars().filter(|c| c == &'X').count(),
                 o_player: rounds_result.chars().filter(|c| c == &'O').count(),
                 draws: rounds_result.chars().filter(|c| c == &'-').count(),
@@ -542,10 +650,21 @@ mod api {
                     boards
                         .trim()
                         .split(',')
-                        .map(|board| Board::from_str(board).expect("The board is valid"))
-                        .collect()
+                        .enumerate()
+                        .map(|(index, board)| {
+                            Board::from_str(board).map_err(|err| {
+                                GameParseError::InvalidRoundBoard {
+                                    round: index + 1,
+                                    source: Box::new(err),
+                                }
+                            })
+                        })
+                        .collect::<Result<_, _>>()?
                 },
-            })
+            };
+
+            rounds_result.validate()?;
+            Ok(rounds_result)
         }
     }
 }
@@ -0,0 +1,158 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Time-based one-time passwords (RFC 6238), used by the two-factor authentication.
+
+use std::io::Cursor;
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use image::{ImageOutputFormat, Luma};
+use qrcode::{Color, QrCode};
+use rand::{distributions::Alphanumeric, Rng};
+use sha1::Sha1;
+
+/// The issuer of the provisioning URI, it's shown in the authenticator apps.
+pub const ISSUER: &str = "XORS";
+/// The length of the secret in bytes.
+const SECRET_LEN: usize = 20;
+/// The period of the code in seconds.
+const PERIOD: i64 = 30;
+/// The number of the code digits.
+const DIGITS: u32 = 6;
+/// The accepted steps before and after the current step, to tolerate the clock drift.
+const DRIFT_STEPS: i64 = 1;
+/// The size of the QR code module in pixels.
+const QR_MODULE_SIZE: u32 = 8;
+/// The quiet zone around the QR code, in modules.
+const QR_QUIET_ZONE: u32 = 4;
+/// The RFC 4648 base32 alphabet.
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Returns a new random secret.
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_LEN];
+    rand::thread_rng().fill(secret.as_mut_slice());
+    secret
+}
+
+/// Returns the secret as unpadded base32, the format of the authenticator apps.
+pub fn encode_secret(secret: &[u8]) -> String {
+    let mut encoded = String::with_capacity((secret.len() * 8).div_ceil(5));
+    for chunk in secret.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = buffer
+            .iter()
+            .fold(0u64, |bits, byte| (bits << 8) | *byte as u64);
+        for idx in 0..(chunk.len() * 8).div_ceil(5) {
+            encoded.push(BASE32_ALPHABET[((bits >> (35 - idx * 5)) & 0x1f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+/// Returns the provisioning URI of the user's secret, the authenticator apps use it to add the account.
+pub fn provisioning_uri(username: &str, secret: &[u8]) -> String {
+    format!(
+        "otpauth://totp/{ISSUER}:{username}?secret={}&issuer={ISSUER}&algorithm=SHA1&digits={DIGITS}&period={PERIOD}",
+        encode_secret(secret)
+    )
+}
+
+/// Returns the QR code of the provisioning URI as PNG.
+pub fn qr_code_png(provisioning_uri: &str) -> Vec<u8> {
+    let code = QrCode::new(provisioning_uri).expect("The provisioning URI fits in a QR code");
+    let width = code.width() as u32;
+    let colors = code.to_colors();
+    let size = (width + QR_QUIET_ZONE * 2) * QR_MODULE_SIZE;
+
+    let image = image::GrayImage::from_fn(size, size, |x, y| {
+        let (x, y) = (x / QR_MODULE_SIZE, y / QR_MODULE_SIZE);
+        let is_dark = (QR_QUIET_ZONE..width + QR_QUIET_ZONE).contains(&x)
+            && (QR_QUIET_ZONE..width + QR_QUIET_ZONE).contains(&y)
+            && colors[((y - QR_QUIET_ZONE) * width + (x - QR_QUIET_ZONE)) as usize] == Color::Dark;
+        Luma([if is_dark { 0 } else { 255 }])
+    });
+
+    let mut png = Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, ImageOutputFormat::Png)
+        .expect("Writing to a vector can't fail");
+    png.into_inner()
+}
+
+/// Returns the QR code of the provisioning URI as PNG data URL.
+pub fn qr_code_data_url(provisioning_uri: &str) -> String {
+    format!(
+        "data:image/png;base64,{}",
+        crate::BASE_64_ENGINE.encode(qr_code_png(provisioning_uri))
+    )
+}
+
+/// Returns the code of the step. The step is the unix timestamp divided by the period.
+pub fn code_at(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // The dynamic truncation of RFC 4226
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Returns the current step.
+pub fn current_step() -> i64 {
+    chrono::Utc::now().timestamp() / PERIOD
+}
+
+/// Verifies the code, and returns its step if it's valid.
+///
+/// The code must be for a step after `last_step`, so the same code can't be used twice.
+pub fn verify(secret: &[u8], code: &str, last_step: Option<i64>) -> Option<i64> {
+    if code.len() != DIGITS as usize || !code.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let step = current_step();
+    ((step - DRIFT_STEPS)..=(step + DRIFT_STEPS))
+        .filter(|step| last_step.map_or(true, |last_step| *step > last_step))
+        .find(|step| code_at(secret, *step) == code)
+}
+
+/// Returns new random recovery codes, in `xxxxx-xxxxx` format.
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..count)
+        .map(|_| {
+            let code: String = (&mut rng)
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .map(|c| (c as char).to_ascii_lowercase())
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
@@ -14,9 +14,8 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::cmp::Ordering;
+use std::{cmp::Ordering, fs, path::PathBuf};
 
-use base64::Engine;
 use image::GenericImageView;
 use passwords::{analyzer, scorer};
 use uuid::Uuid;
@@ -24,9 +23,18 @@ use uuid::Uuid;
 use crate::{
     api::xo::PlayerData,
     errors::{ApiError, ApiResult},
+    oidc::IdTokenClaims,
     schemas::*,
 };
 
+/// The maximum size of the uploaded profile images, in bytes.
+pub const MAX_PROFILE_IMAGE_BYTES: usize = 5_000_000;
+/// The maximum size of the update user request body, in bytes. It's bigger than the global JSON body limit,
+/// so the base64 encoded profile image fits in it.
+pub(crate) const MAX_UPDATE_USER_BODY_BYTES: usize = MAX_PROFILE_IMAGE_BYTES / 3 * 4 + 64 * 1024;
+/// The maximum width and height of the uploaded profile images, in pixels.
+const MAX_PROFILE_IMAGE_DIMENSION: u32 = 4096;
+
 /// Validates a user name. not the user signin, but the first name and last name.
 #[must_use = "This function returns a `ApiResult<()>` instead of panicking"]
 pub fn validate_user_name<const IS_FIRST_NAME: bool>(name: &str) -> ApiResult<()> {
@@ -117,6 +125,66 @@ pub fn validate_user_signin(usersignin: &str) -> ApiResult<()> {
     Ok(())
 }
 
+/// Validates an email address.
+///
+/// This will be check for:
+/// - The email's length. It must be at most 254 characters.
+/// - The email's format. It must be a valid address, like `user@example.com`.
+#[must_use = "This function returns a `ApiResult<()>` instead of panicking"]
+pub fn validate_email(email: &str) -> ApiResult<()> {
+    if email.chars().count() > 254 {
+        return Err(ApiError::InvalidEmail(
+            "The email must be at most 254 characters".to_owned(),
+        ));
+    }
+    if email.parse::<lettre::Address>().is_err() {
+        return Err(ApiError::InvalidEmail(
+            "The email is not a valid address".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns a valid username from the OpenID Connect claims, it's the base of the new user's username.
+/// It's made from the preferred username or the email local part, and it's at most 27 characters
+/// so a suffix can be added to make it unique.
+pub fn oidc_username(claims: &IdTokenClaims) -> String {
+    let candidate = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| claims.email.as_deref()?.split('@').next())
+        .unwrap_or_default();
+    let username: String = candidate
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .take(27)
+        .collect();
+
+    if username.starts_with(|c: char| c.is_ascii_alphabetic()) && username.len() >= 3 {
+        username
+    } else {
+        format!("user_{username}").chars().take(27).collect()
+    }
+}
+
+/// Returns the valid first name and last name from the OpenID Connect claims, the first word of each one.
+/// The username is the first name if the claims don't have a valid one.
+pub fn oidc_names(claims: &IdTokenClaims, username: &str) -> (String, Option<String>) {
+    let first_word = |name: Option<&str>| -> Option<String> {
+        name?
+            .split_whitespace()
+            .next()
+            .map(|word| word.chars().take(32).collect())
+    };
+
+    (
+        first_word(claims.given_name.as_deref())
+            .or_else(|| first_word(claims.name.as_deref()))
+            .unwrap_or_else(|| username.to_owned()),
+        first_word(claims.family_name.as_deref()),
+    )
+}
+
 /// Validates a user registration.
 ///
 /// This will validate:
@@ -124,6 +192,7 @@ pub fn validate_user_signin(usersignin: &str) -> ApiResult<()> {
 /// - The user's last name.
 /// - The user's username.
 /// - The user's password.
+/// - The user's email, if provided.
 #[must_use = "This function returns a `ApiResult<()>` instead of panicking"]
 pub fn validate_user_registration(user: &NewUserSchema) -> ApiResult<()> {
     validate_user_name::<true>(&user.first_name)?;
@@ -133,6 +202,48 @@ pub fn validate_user_registration(user: &NewUserSchema) -> ApiResult<()> {
         .transpose()?;
     validate_password(&user.password)?;
     validate_user_signin(&user.username)?;
+    user.email.as_deref().map(validate_email).transpose()?;
+    Ok(())
+}
+
+/// Validates the games filter.
+///
+/// This will be check for:
+/// - The `opponent` and `result` filters. They require the `player` filter.
+/// - The page size. It must be between 1 and 50.
+/// - The date range. The `from` date must be before the `to` date.
+#[must_use = "This function returns a `ApiResult<()>` instead of panicking"]
+pub fn validate_games_filter(filter: &GamesFilterSchema) -> ApiResult<()> {
+    if filter.player.is_none() && (filter.opponent.is_some() || filter.result.is_some()) {
+        return Err(ApiError::InvalidGamesFilter(
+            "The `opponent` and `result` filters require the `player` filter".to_owned(),
+        ));
+    }
+    if filter.limit.is_some_and(|limit| !(1..=50).contains(&limit)) {
+        return Err(ApiError::InvalidGamesFilter(
+            "The `limit` must be between 1 and 50".to_owned(),
+        ));
+    }
+    if matches!((filter.from, filter.to), (Some(from), Some(to)) if from > to) {
+        return Err(ApiError::InvalidGamesFilter(
+            "The `from` date must be before the `to` date".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Validate the users search query and page size.
+pub fn validate_users_search(search: &UsersSearchSchema) -> ApiResult<()> {
+    if !(1..=50).contains(&search.q.trim().chars().count()) {
+        return Err(ApiError::InvalidUsersSearch(
+            "The `q` must be between 1 and 50 characters".to_owned(),
+        ));
+    }
+    if search.limit.is_some_and(|limit| !(1..=50).contains(&limit)) {
+        return Err(ApiError::InvalidUsersSearch(
+            "The `limit` must be between 1 and 50".to_owned(),
+        ));
+    }
     Ok(())
 }
 
@@ -204,40 +315,198 @@ pub(crate) fn handle_captcha_state(captcha_state: &salvo_captcha::CaptchaState)
     Err(err)
 }
 
-/// Validates a user profile image.
+/// Parses the JSON request body with the given size limit, instead of the global limit.
+pub(crate) async fn parse_json_with_max_size<T: serde::de::DeserializeOwned>(
+    req: &mut salvo::Request,
+    max_size: usize,
+) -> ApiResult<T> {
+    req.parse_json_with_max_size(max_size)
+        .await
+        .map_err(|err| match err {
+            salvo::http::ParseError::Other(err) if err.is::<http_body_util::LengthLimitError>() => {
+                ApiError::PayloadTooLarge
+            }
+            err => ApiError::InvalidRequestBody(err.to_string()),
+        })
+}
+
+/// Reads the file of the given field from the `multipart/form-data` request body.
 ///
-/// Checks
-/// - The image's size. It must be less than 1MB.
-/// - The image's format. only png format is allowed.
-/// - The image's dimensions. It must be 128x128 pixels.
-pub(crate) fn validate_user_profile_image(profile_image: Option<&String>) -> ApiResult<()> {
-    log::info!("Validating user profile image");
-
-    if let Some(profile_image) = profile_image {
-        let image_bytes = crate::BASE_64_ENGINE
-            .decode(profile_image)
-            .map_err(|_| ApiError::InvalidProfileImage("Invalid base64 string".to_owned()))?;
-        if image_bytes.len() >= 1_000_000 {
-            // 1MB
-            return Err(ApiError::InvalidProfileImage(
-                "The image's size must be less than 1MB".to_owned(),
-            ));
+/// The file is read while its size is checked, so it's rejected as soon as it exceeds the size limit,
+/// even if the request has no `Content-Length` header.
+pub(crate) async fn read_multipart_file(
+    req: &mut salvo::Request,
+    field_name: &str,
+    max_size: usize,
+) -> ApiResult<Vec<u8>> {
+    use futures_util::StreamExt;
+
+    let multipart_error = |err| match err {
+        multer::Error::FieldSizeExceeded { .. } | multer::Error::StreamSizeExceeded { .. } => {
+            ApiError::PayloadTooLarge
         }
-        if image::guess_format(&image_bytes).is_ok_and(|f| f != image::ImageFormat::Png) {
-            return Err(ApiError::InvalidProfileImage(
-                "The image's format must be png".to_owned(),
-            ));
+        err => ApiError::InvalidRequestBody(err.to_string()),
+    };
+
+    let boundary = req
+        .headers()
+        .get(salvo::http::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .ok_or_else(|| {
+            ApiError::InvalidRequestBody("The `Content-Type` header is required".to_owned())
+        })
+        .and_then(|content_type| multer::parse_boundary(content_type).map_err(multipart_error))?;
+    let body = req
+        .take_body()
+        .map(|frame| frame.map(|frame| frame.into_data().unwrap_or_default()));
+    // The other fields are small, so the whole body has a small overhead on top of the file
+    let constraints = multer::Constraints::new().size_limit(
+        multer::SizeLimit::new()
+            .whole_stream(max_size as u64 + 64 * 1024)
+            .for_field(field_name, max_size as u64),
+    );
+
+    let mut multipart = multer::Multipart::with_constraints(body, boundary, constraints);
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() == Some(field_name) {
+            return field
+                .bytes()
+                .await
+                .map(|bytes| bytes.to_vec())
+                .map_err(multipart_error);
         }
+    }
+    Err(ApiError::InvalidRequestBody(format!(
+        "The `{field_name}` file is required"
+    )))
+}
+
+/// Normalizes a user profile image, returns the normalized PNG image.
+///
+/// The image can be png, jpeg or webp, less than 5MB in size and at most 4096x4096 pixels.
+/// Its dimensions are checked before decoding it, then it's center-cropped to a square and resized
+/// to 128x128 pixels, and re-encoded to png, without the original metadata.
+pub(crate) fn normalize_user_profile_image(image_bytes: &[u8]) -> ApiResult<Vec<u8>> {
+    log::info!("Normalizing user profile image");
+
+    if image_bytes.len() >= MAX_PROFILE_IMAGE_BYTES {
+        return Err(ApiError::InvalidProfileImage(
+            "The image's size must be less than 5MB".to_owned(),
+        ));
+    }
+    let format = image::guess_format(image_bytes)
+        .ok()
+        .filter(|format| {
+            matches!(
+                format,
+                image::ImageFormat::Png | image::ImageFormat::Jpeg | image::ImageFormat::WebP
+            )
+        })
+        .ok_or_else(|| {
+            ApiError::InvalidProfileImage("The image's format must be png, jpeg or webp".to_owned())
+        })?;
+    let invalid_image = |_| ApiError::InvalidProfileImage("Invalid image".to_owned());
+
+    // Only the header is read, so the decompression bombs are rejected before decoding them
+    let (width, height) = image::io::Reader::with_format(std::io::Cursor::new(image_bytes), format)
+        .into_dimensions()
+        .map_err(invalid_image)?;
+    if width > MAX_PROFILE_IMAGE_DIMENSION || height > MAX_PROFILE_IMAGE_DIMENSION {
+        return Err(ApiError::InvalidProfileImage(format!(
+            "The image's dimensions must be at most {MAX_PROFILE_IMAGE_DIMENSION}x{MAX_PROFILE_IMAGE_DIMENSION} pixels"
+        )));
+    }
+    let mut limits = image::io::Limits::default();
+    limits.max_image_width = Some(MAX_PROFILE_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_PROFILE_IMAGE_DIMENSION);
+    let mut reader = image::io::Reader::with_format(std::io::Cursor::new(image_bytes), format);
+    reader.limits(limits);
+    let image = reader.decode().map_err(invalid_image)?;
+
+    let (width, height) = image.dimensions();
+    let side = width.min(height);
+    let size = PROFILE_IMAGE_SIZES[2];
+    let image = image
+        .crop_imm((width - side) / 2, (height - side) / 2, side, side)
+        .resize_exact(size, size, image::imageops::FilterType::Lanczos3);
+
+    let mut normalized = Vec::new();
+    image::DynamicImage::ImageRgba8(image.to_rgba8())
+        .write_to(
+            &mut std::io::Cursor::new(&mut normalized),
+            image::ImageOutputFormat::Png,
+        )
+        .map_err(|_| ApiError::InternalServer)?;
+    log::info!("User profile image is normalized");
+    Ok(normalized)
+}
+
+/// The profile image sizes that can be served, in pixels. The stored profile images are the biggest size,
+/// the smaller sizes are thumbnails generated from them.
+pub const PROFILE_IMAGE_SIZES: [u32; 3] = [32, 64, 128];
+
+/// Returns the disk path of the profile image in the given size, by the profile image file name.
+///
+/// The thumbnail is generated when it's missing or older than the profile image.
+pub(crate) fn get_profile_image_disk_path(file_name: &str, size: u32) -> ApiResult<PathBuf> {
+    if !PROFILE_IMAGE_SIZES.contains(&size) {
+        return Err(ApiError::InvalidImageSize);
+    }
+    let image_path = profile_image_size_path(file_name, PROFILE_IMAGE_SIZES[2]);
+    let thumbnail_path = profile_image_size_path(file_name, size);
+    if thumbnail_path == image_path {
+        return Ok(image_path);
+    }
+
+    let modified = |path: &PathBuf| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    if modified(&thumbnail_path).is_some_and(|thumbnail| Some(thumbnail) >= modified(&image_path)) {
+        return Ok(thumbnail_path);
+    }
+
+    log::info!("Generating the {size}px thumbnail of the profile image: {file_name}");
+    let thumbnail = fs::read(&image_path)
+        .ok()
+        .and_then(|image_bytes| image::load_from_memory(&image_bytes).ok())
+        .ok_or(ApiError::InternalServer)?
+        .resize_exact(size, size, image::imageops::FilterType::Lanczos3);
+    // Written to a temporary file then renamed, so a concurrent request never reads a partial thumbnail
+    let temp_path = thumbnail_path.with_extension(Uuid::new_v4().to_string());
+    thumbnail_path
+        .parent()
+        .map(fs::create_dir_all)
+        .transpose()
+        .and_then(|_| {
+            thumbnail
+                .save_with_format(&temp_path, image::ImageFormat::Png)
+                .map_err(std::io::Error::other)
+        })
+        .and_then(|_| fs::rename(&temp_path, &thumbnail_path))
+        .map_err(|err| {
+            log::error!("Failed to write the thumbnail of the profile image {file_name}: {err}");
+            ApiError::InternalServer
+        })?;
+    Ok(thumbnail_path)
+}
+
+/// Returns the disk path of the profile image in the given size, without generating it.
+fn profile_image_size_path(file_name: &str, size: u32) -> PathBuf {
+    if size == PROFILE_IMAGE_SIZES[2] {
+        PathBuf::from(get_image_disk_path(file_name))
+    } else {
+        PathBuf::from(get_image_disk_path(&format!(
+            "thumbnails/{file_name}-{size}"
+        )))
+    }
+}
 
-        // Safe to unwrap because we already checked the image's format and size.
-        let image = image::load_from_memory(&image_bytes).unwrap();
-        let dimensions = image.dimensions();
-        if dimensions.0 != 128 || dimensions.1 != 128 {
-            return Err(ApiError::InvalidProfileImage(
-                "The image's dimensions must be 128x128 pixels".to_owned(),
-            ));
+/// Removes the profile image and its thumbnails by the profile image file name.
+pub(crate) fn remove_profile_image(file_name: &str) -> std::io::Result<()> {
+    for size in PROFILE_IMAGE_SIZES {
+        if let Err(err) = fs::remove_file(profile_image_size_path(file_name, size)) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(err);
+            }
         }
-        log::info!("User profile image is valid");
     }
     Ok(())
 }
@@ -29,10 +29,12 @@ mod get_game {
             &conn,
             NewUserSchema {
                 username: "get_game_succses_x_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player x");
@@ -40,10 +42,12 @@ mod get_game {
             &conn,
             NewUserSchema {
                 username: "get_game_succses_o_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player x");
@@ -126,10 +130,12 @@ mod get_game {
             &conn,
             NewUserSchema {
                 username: "deleted_user_x_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player x");
@@ -138,10 +144,12 @@ mod get_game {
             &conn,
             NewUserSchema {
                 username: "deleted_user_o_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player o");
@@ -185,6 +193,54 @@ mod get_game {
         assert_eq!(res_game.o_player.username, "Deleted");
     }
 
+    #[tokio::test]
+    async fn corrupted_game() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let game = db_utils::create_game(&conn, Uuid::new_v4(), Uuid::new_v4(), 10)
+            .await
+            .expect("Failed to create game");
+        let mut game = GameEntity::find()
+            .filter(GameColumn::Uuid.eq(*game.uuid.as_ref()))
+            .one(&conn)
+            .await
+            .expect("Failed to get game")
+            .expect("The game should be exist")
+            .into_active_model();
+        game.rounds_result = Set("X XXX------:0A".to_owned());
+        game.reason = Set(Some(GameOverReason::PlayerWon.to_string()));
+        game.ended_at = Set(Some(chrono::Utc::now().naive_utc()));
+        // Keep it out of the lastest games, the other tests are using them.
+        game.created_at = Set(chrono::NaiveDateTime::UNIX_EPOCH);
+        let game = game.save(&conn).await.expect("Failed to save game");
+
+        let mut res = send(
+            &service,
+            &format!("game/{}", game.uuid.as_ref()),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await;
+
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::INTERNAL_SERVER_ERROR),
+            "Status code should be 500 {res:?}"
+        );
+        let message = res.take_string().await.unwrap();
+        assert!(
+            message.contains(&game.uuid.as_ref().to_string()),
+            "The message should contain the game uuid {message}"
+        );
+
+        GameEntity::delete(game)
+            .exec(&conn)
+            .await
+            .expect("Failed to delete game");
+    }
+
     #[tokio::test]
     async fn unend_game() {
         let service = get_service().await.expect("Failed to get service");
@@ -194,10 +250,12 @@ mod get_game {
             &conn,
             NewUserSchema {
                 username: "unend_game_x_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player x");
@@ -205,10 +263,12 @@ mod get_game {
             &conn,
             NewUserSchema {
                 username: "unend_game_o_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player x");
@@ -247,10 +307,12 @@ mod lastest_games {
             &conn,
             NewUserSchema {
                 username: "lastest_games_x_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player x");
@@ -259,10 +321,12 @@ mod lastest_games {
             &conn,
             NewUserSchema {
                 username: "lastest_games_o_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player o");
@@ -280,10 +344,18 @@ mod lastest_games {
             .await
             .expect("Failed to end game");
 
-        let mut res = send(&service, "games", Method::GET, None::<&()>, vec![]).await;
+        let mut res = send(
+            &service,
+            &format!("games?player={}", player_x.uuid),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await;
 
-        let res_games: Vec<GameSchema> = serde_json::from_str(&res.take_string().await.unwrap())
-            .expect("Failed to parse game schema");
+        let res_games = serde_json::from_str::<GamesPageSchema>(&res.take_string().await.unwrap())
+            .expect("Failed to parse games page schema")
+            .games;
 
         assert_eq!(
             res.status_code,
@@ -310,10 +382,12 @@ mod lastest_games {
             &conn,
             NewUserSchema {
                 username: "unend_games_x_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player x");
@@ -322,10 +396,12 @@ mod lastest_games {
             &conn,
             NewUserSchema {
                 username: "unend_games_o_player".to_owned(),
+                email: None,
                 first_name: "Player".to_owned(),
                 password: "fdkDFLKJL4859#$&".to_owned(),
                 ..Default::default()
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create player o");
@@ -333,17 +409,25 @@ mod lastest_games {
         let game1 = db_utils::create_game(&conn, player_x.uuid, player_o.uuid, 10)
             .await
             .expect("Failed to create game");
-        let game2 = db_utils::create_game(&conn, Uuid::new_v4(), Uuid::new_v4(), 10)
+        let game2 = db_utils::create_game(&conn, player_x.uuid, Uuid::new_v4(), 10)
             .await
             .expect("Failed to create game");
         db_utils::end_game(&conn, game1.uuid.as_ref(), None, &GameOverReason::Draw)
             .await
             .expect("Failed to end game");
 
-        let mut res = send(&service, "games", Method::GET, None::<&()>, vec![]).await;
+        let mut res = send(
+            &service,
+            &format!("games?player={}", player_x.uuid),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await;
 
-        let res_games: Vec<GameSchema> = serde_json::from_str(&res.take_string().await.unwrap())
-            .expect("Failed to parse game schema");
+        let res_games = serde_json::from_str::<GamesPageSchema>(&res.take_string().await.unwrap())
+            .expect("Failed to parse games page schema")
+            .games;
 
         assert_eq!(
             res.status_code,
@@ -361,3 +445,290 @@ mod lastest_games {
         );
     }
 }
+
+#[cfg(test)]
+mod games_filters {
+    use super::*;
+
+    async fn create_ended_game(
+        conn: &sea_orm::DatabaseConnection,
+        x_player: &UserSchema,
+        o_player: &UserSchema,
+        winner: Option<Uuid>,
+        reason: GameOverReason,
+    ) -> Uuid {
+        let game = db_utils::create_game(conn, x_player.uuid, o_player.uuid, 10)
+            .await
+            .expect("Failed to create game");
+        db_utils::end_game(conn, game.uuid.as_ref(), winner, &reason)
+            .await
+            .expect("Failed to end game");
+        *game.uuid.as_ref()
+    }
+
+    async fn get_games(service: &Service, query: &str) -> (Response, Option<GamesPageSchema>) {
+        let mut res = send(
+            service,
+            &format!("games?{query}"),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await;
+        let page = serde_json::from_str(&res.take_string().await.unwrap()).ok();
+        (res, page)
+    }
+
+    #[tokio::test]
+    async fn pagination() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let player_x = create_player(&conn, "pagination_x_player").await;
+        let player_o = create_player(&conn, "pagination_o_player").await;
+        let mut games = Vec::new();
+        for _ in 0..3 {
+            games.push(
+                create_ended_game(&conn, &player_x, &player_o, None, GameOverReason::Draw).await,
+            );
+        }
+
+        let (res, page) = get_games(&service, &format!("player={}&limit=2", player_x.uuid)).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "Status code should be 200 {res:?}"
+        );
+        let first_page = page.expect("Failed to parse games page schema");
+        assert_eq!(first_page.games.len(), 2);
+        let cursor = first_page
+            .next_cursor
+            .expect("The first page should have a next cursor");
+
+        let (res, page) = get_games(
+            &service,
+            &format!("player={}&limit=2&cursor={cursor}", player_x.uuid),
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "Status code should be 200 {res:?}"
+        );
+        let second_page = page.expect("Failed to parse games page schema");
+        assert_eq!(second_page.games.len(), 1);
+        assert!(
+            second_page.next_cursor.is_none(),
+            "The last page should not have a next cursor"
+        );
+
+        let mut page_games = first_page
+            .games
+            .iter()
+            .chain(second_page.games.iter())
+            .map(|game| game.uuid)
+            .collect::<Vec<_>>();
+        // Newest first
+        games.reverse();
+        assert_eq!(page_games, games);
+        page_games.dedup();
+        assert_eq!(page_games.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn result_filter() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let player_x = create_player(&conn, "result_filter_x_player").await;
+        let player_o = create_player(&conn, "result_filter_o_player").await;
+        let won_game = create_ended_game(
+            &conn,
+            &player_x,
+            &player_o,
+            Some(player_x.uuid),
+            GameOverReason::PlayerWon,
+        )
+        .await;
+        let lost_game = create_ended_game(
+            &conn,
+            &player_x,
+            &player_o,
+            Some(player_o.uuid),
+            GameOverReason::PlayerDisconnected,
+        )
+        .await;
+        let draw_game =
+            create_ended_game(&conn, &player_x, &player_o, None, GameOverReason::Draw).await;
+
+        for (result, game) in [("win", won_game), ("loss", lost_game), ("draw", draw_game)] {
+            let (res, page) = get_games(
+                &service,
+                &format!("player={}&result={result}", player_x.uuid),
+            )
+            .await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::OK),
+                "Status code should be 200 {res:?}"
+            );
+            let games = page.expect("Failed to parse games page schema").games;
+            assert_eq!(
+                games.iter().map(|game| game.uuid).collect::<Vec<_>>(),
+                vec![game],
+                "Only the {result} game should be returned"
+            );
+        }
+
+        let (_, page) = get_games(
+            &service,
+            &format!("player={}&reason=player_disconnected", player_o.uuid),
+        )
+        .await;
+        let games = page.expect("Failed to parse games page schema").games;
+        assert_eq!(
+            games.iter().map(|game| game.uuid).collect::<Vec<_>>(),
+            vec![lost_game],
+            "Only the disconnected game should be returned"
+        );
+    }
+
+    #[tokio::test]
+    async fn opponent_and_date_filters() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let player = create_player(&conn, "opponent_filter_player").await;
+        let opponent = create_player(&conn, "opponent_filter_opponent").await;
+        let other = create_player(&conn, "opponent_filter_other").await;
+        let from = chrono::Utc::now().naive_utc();
+        let opponent_game =
+            create_ended_game(&conn, &opponent, &player, None, GameOverReason::Draw).await;
+        create_ended_game(&conn, &player, &other, None, GameOverReason::Draw).await;
+
+        let (_, page) = get_games(
+            &service,
+            &format!("player={}&opponent={}", player.uuid, opponent.uuid),
+        )
+        .await;
+        let games = page.expect("Failed to parse games page schema").games;
+        assert_eq!(
+            games.iter().map(|game| game.uuid).collect::<Vec<_>>(),
+            vec![opponent_game],
+            "Only the games against the opponent should be returned"
+        );
+
+        let (_, page) = get_games(
+            &service,
+            &format!(
+                "player={}&from={}",
+                player.uuid,
+                from.format("%Y-%m-%dT%H:%M:%S%.f")
+            ),
+        )
+        .await;
+        assert_eq!(
+            page.expect("Failed to parse games page schema").games.len(),
+            2
+        );
+
+        let (_, page) = get_games(
+            &service,
+            &format!(
+                "player={}&to={}",
+                player.uuid,
+                from.format("%Y-%m-%dT%H:%M:%S%.f")
+            ),
+        )
+        .await;
+        assert!(page
+            .expect("Failed to parse games page schema")
+            .games
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_filters() {
+        let service = get_service().await.expect("Failed to get service");
+
+        for query in [
+            format!("opponent={}", Uuid::new_v4()),
+            "result=win".to_owned(),
+            "limit=0".to_owned(),
+            "limit=51".to_owned(),
+            "from=2024-02-01T00:00:00&to=2024-01-01T00:00:00".to_owned(),
+            "cursor=invalid".to_owned(),
+        ] {
+            let (res, _) = get_games(&service, &query).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::BAD_REQUEST),
+                "Status code should be 400 for `{query}` {res:?}"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod scan_games {
+    use super::*;
+    use xors_api::errors::ApiError;
+
+    #[tokio::test]
+    async fn scan_corrupted_game() {
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let valid_game = db_utils::create_game(&conn, Uuid::new_v4(), Uuid::new_v4(), 10)
+            .await
+            .expect("Failed to create game");
+        let mut corrupted_game = db_utils::create_game(&conn, Uuid::new_v4(), Uuid::new_v4(), 10)
+            .await
+            .expect("Failed to create game");
+        // The O player played first
+        corrupted_game.board = Set("----O----:4".to_owned());
+        let corrupted_game = corrupted_game
+            .save(&conn)
+            .await
+            .expect("Failed to save game");
+        let mut corrupted_ended_game =
+            db_utils::create_game(&conn, Uuid::new_v4(), Uuid::new_v4(), 10)
+                .await
+                .expect("Failed to create game");
+        // The board of an ended game is cleared
+        corrupted_ended_game.board = Set("X--------:0".to_owned());
+        corrupted_ended_game.ended_at = Set(Some(chrono::Utc::now().naive_utc()));
+        let corrupted_ended_game = corrupted_ended_game
+            .save(&conn)
+            .await
+            .expect("Failed to save game");
+
+        let corrupted_games = db_utils::scan_games(&conn)
+            .await
+            .expect("Failed to scan the games");
+
+        let is_found = |game_uuid: &Uuid| {
+            corrupted_games
+                .iter()
+                .any(|err| matches!(err, ApiError::CorruptedGame { uuid, .. } if uuid == game_uuid))
+        };
+        assert!(
+            is_found(corrupted_game.uuid.as_ref()),
+            "The corrupted game should be found {corrupted_games:?}"
+        );
+        assert!(
+            is_found(corrupted_ended_game.uuid.as_ref()),
+            "The corrupted ended game should be found {corrupted_games:?}"
+        );
+        assert!(
+            !is_found(valid_game.uuid.as_ref()),
+            "The valid game should not be found {corrupted_games:?}"
+        );
+
+        for game in [valid_game, corrupted_game, corrupted_ended_game] {
+            GameEntity::delete(game)
+                .exec(&conn)
+                .await
+                .expect("Failed to delete game");
+        }
+    }
+}
@@ -28,6 +28,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "Username".to_owned(),
+            email: None,
             password: "fdlkFDLKF#$3213!".to_owned(),
         };
 
@@ -67,6 +68,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "Username001".to_owned(),
+            email: None,
             password: "fdlkFDLKF#$3213!".to_owned(),
         };
 
@@ -101,6 +103,88 @@ mod signup {
         );
     }
 
+    #[tokio::test]
+    async fn already_exist_username_different_case() {
+        let service = get_service().await.expect("Failed to get service");
+
+        let signup_schema = NewUserSchema {
+            first_name: "First".to_owned(),
+            last_name: Some("Last".to_owned()),
+            username: "CaseUsername".to_owned(),
+            email: None,
+            password: "fdlkFDLKF#$3213!".to_owned(),
+        };
+
+        let res = send(
+            &service,
+            "auth/signup",
+            Method::POST,
+            Some(&signup_schema),
+            vec![],
+        )
+        .await;
+
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        let res = send(
+            &service,
+            "auth/signup",
+            Method::POST,
+            Some(&NewUserSchema {
+                username: "caseusername".to_owned(),
+                ..signup_schema
+            }),
+            vec![],
+        )
+        .await;
+
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The response should have a `400 BAD_REQUEST` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_signups_same_username() {
+        let conn = get_connection().await.expect("Failed to get connection");
+        let password_hasher = get_auth_config().password_hasher;
+
+        let results = futures_util::future::join_all(
+            ["ConcurrentUser", "concurrentuser", "CONCURRENTUSER"].map(|username| {
+                db_utils::create_user(
+                    &conn,
+                    NewUserSchema {
+                        first_name: "First".to_owned(),
+                        last_name: None,
+                        username: username.to_owned(),
+                        email: None,
+                        password: "fdlkFDLKF#$3213!".to_owned(),
+                    },
+                    &password_hasher,
+                )
+            }),
+        )
+        .await;
+
+        assert_eq!(
+            results.iter().filter(|result| result.is_ok()).count(),
+            1,
+            "Only one signup should succeed {results:?}"
+        );
+        assert!(
+            results.iter().all(|result| matches!(
+                result,
+                Ok(_) | Err(xors_api::errors::ApiError::UsernameAlreadyExists(_))
+            )),
+            "The other signups should fail with `UsernameAlreadyExists` {results:?}"
+        );
+    }
+
     #[tokio::test]
     async fn invalid_first_name() {
         let service = get_service().await.expect("Failed to get service");
@@ -109,6 +193,7 @@ mod signup {
             first_name: "".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "Username".to_owned(),
+            email: None,
             password: "fdlkFDLKF#$3213!".to_owned(),
         };
 
@@ -136,6 +221,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "1user".to_owned(),
+            email: None,
             password: "fdlkFDLKF#$3213!".to_owned(),
         };
 
@@ -163,6 +249,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "_user".to_owned(),
+            email: None,
             password: "fdlkFDLKF#$3213!".to_owned(),
         };
 
@@ -190,6 +277,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "مستخدم".to_owned(),
+            email: None,
             password: "fdlkFDLKF#$3213!".to_owned(),
         };
 
@@ -217,6 +305,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "us".to_owned(),
+            email: None,
             password: "fdlkFDLKF#$3213!".to_owned(),
         };
 
@@ -246,6 +335,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username,
+            email: None,
             password: "fdlkFDLKF#$3213!".to_owned(),
         };
 
@@ -272,6 +362,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "user".to_owned(),
+            email: None,
             password: "1234567".to_owned(),
         };
         let res = send(
@@ -297,6 +388,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "user".to_owned(),
+            email: None,
             password: "123".repeat(10),
         };
         let res = send(
@@ -322,6 +414,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "user".to_owned(),
+            email: None,
             password: "KJHD74397$#&KDH".to_owned(),
         };
         let res = send(
@@ -347,6 +440,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "user".to_owned(),
+            email: None,
             password: "kjhdf74397$#&kdh".to_owned(),
         };
         let res = send(
@@ -372,6 +466,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "user".to_owned(),
+            email: None,
             password: "kjhdfKJHDKH$#&kdh".to_owned(),
         };
         let res = send(
@@ -397,6 +492,7 @@ mod signup {
             first_name: "First".to_owned(),
             last_name: Some("Last".to_owned()),
             username: "user".to_owned(),
+            email: None,
             password: "kjhdfKJHDKH1234".to_owned(),
         };
         let res = send(
@@ -430,8 +526,10 @@ mod signin {
                 first_name: "First".to_owned(),
                 last_name: Some("Last".to_owned()),
                 username: "Username348939843".to_owned(),
+                email: None,
                 password: "fdkjhKFHDKH347(#*&".to_owned(),
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create user");
@@ -480,8 +578,10 @@ mod signin {
                 first_name: "First".to_owned(),
                 last_name: Some("Last".to_owned()),
                 username: "Username3489398423".to_owned(),
+                email: None,
                 password: "fdkjhKFHDKH347(#*&".to_owned(),
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create user");
@@ -518,8 +618,10 @@ mod signin {
                 first_name: "First".to_owned(),
                 last_name: Some("Last".to_owned()),
                 username: "Username3489398431".to_owned(),
+                email: None,
                 password: "fdkjhKFHDKH347(#*&".to_owned(),
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create user");
@@ -578,7 +680,11 @@ mod refresh {
     async fn refresh_with_valid_refresh_token() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        // The refresh token will be available after 3 seconds
+        let auth_config = AuthConfig {
+            refresh_token_active_after: Duration::seconds(3),
+            ..get_auth_config()
+        };
 
         let user = xors_api::db_utils::create_user(
             &conn,
@@ -586,16 +692,23 @@ mod refresh {
                 first_name: "First".to_owned(),
                 last_name: Some("Last".to_owned()),
                 username: "Username3489239".to_owned(),
+                email: None,
                 password: "fdkjhKFHDKH347(#*&".to_owned(),
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create user");
 
-        let (jwt, refresh_token) = xors_api::db_utils::signin_user(user.clone(), &secret_key)
-            .await
-            .map(|user| (user.jwt, user.refresh_token))
-            .expect("Failed to signin user");
+        let (jwt, refresh_token) = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &auth_config,
+            NewSessionSchema::default(),
+        )
+        .await
+        .map(|user| (user.jwt, user.refresh_token))
+        .expect("Failed to signin user");
 
         // Sleep until the refresh token available
         tokio::time::sleep(Duration::seconds(3).to_std().unwrap()).await;
@@ -646,7 +759,7 @@ mod refresh {
     async fn unavailable_refresh_token() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::create_user(
             &conn,
@@ -654,16 +767,23 @@ mod refresh {
                 first_name: "First".to_owned(),
                 last_name: Some("Last".to_owned()),
                 username: "Username3489238".to_owned(),
+                email: None,
                 password: "fdkjhKFHDKH347(#*&".to_owned(),
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create user");
 
-        let refresh_token = xors_api::db_utils::signin_user(user.clone(), &secret_key)
-            .await
-            .map(|user| user.refresh_token)
-            .expect("Failed to signin user");
+        let refresh_token = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &auth_config,
+            NewSessionSchema::default(),
+        )
+        .await
+        .map(|user| user.refresh_token)
+        .expect("Failed to signin user");
 
         let res = send(
             &service,
@@ -689,7 +809,12 @@ mod refresh {
     async fn expired_refresh_token() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        // The refresh token will be expired after 5 seconds
+        let auth_config = AuthConfig {
+            refresh_token_ttl: Duration::seconds(5),
+            refresh_token_active_after: Duration::seconds(3),
+            ..get_auth_config()
+        };
 
         let user = xors_api::db_utils::create_user(
             &conn,
@@ -697,16 +822,23 @@ mod refresh {
                 first_name: "First".to_owned(),
                 last_name: Some("Last".to_owned()),
                 username: "Username3489237".to_owned(),
+                email: None,
                 password: "fdkjhKFHDKH347(#*&".to_owned(),
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create user");
 
-        let refresh_token = xors_api::db_utils::signin_user(user.clone(), &secret_key)
-            .await
-            .map(|user| user.refresh_token)
-            .expect("Failed to signin user");
+        let refresh_token = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &auth_config,
+            NewSessionSchema::default(),
+        )
+        .await
+        .map(|user| user.refresh_token)
+        .expect("Failed to signin user");
 
         // Sleep until the refresh token expired
         tokio::time::sleep(Duration::seconds(5).to_std().unwrap()).await;
@@ -796,7 +928,7 @@ mod refresh {
     async fn refresh_with_jwt() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::create_user(
             &conn,
@@ -804,19 +936,23 @@ mod refresh {
                 first_name: "First".to_owned(),
                 last_name: Some("Last".to_owned()),
                 username: "Username3489236".to_owned(),
+                email: None,
                 password: "fdkjhKFHDKH347(#*&".to_owned(),
             },
+            &get_auth_config().password_hasher,
         )
         .await
         .expect("Failed to create user");
 
-        let jwt = xors_api::db_utils::signin_user(user.clone(), &secret_key)
-            .await
-            .map(|user| user.jwt)
-            .expect("Failed to signin user");
-
-        // Sleep until the refresh token available
-        tokio::time::sleep(Duration::seconds(3).to_std().unwrap()).await;
+        let jwt = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &auth_config,
+            NewSessionSchema::default(),
+        )
+        .await
+        .map(|user| user.jwt)
+        .expect("Failed to signin user");
 
         let res = send(
             &service,
@@ -837,4 +973,2659 @@ mod refresh {
             "The response should have a `400 BAD_REQUEST` status code {res:?}"
         );
     }
+
+    async fn send_refresh(service: &Service, refresh_token: &str) -> Response {
+        send(
+            service,
+            "auth/refresh",
+            Method::GET,
+            None::<&str>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", refresh_token))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn reuse_rotated_refresh_token() {
+        let conn = get_connection().await.expect("Failed to get connection");
+        // The refresh token will be available after 3 seconds
+        let auth_config = AuthConfig {
+            refresh_token_active_after: Duration::seconds(3),
+            ..get_auth_config()
+        };
+        // The service issues the new refresh token, so it has to be available too
+        let service = xors_api::api::service(
+            get_connection().await.expect("Failed to get connection"),
+            100,
+            10,
+            auth_config.clone(),
+            get_mailer(),
+        )
+        .0;
+
+        let user = xors_api::db_utils::create_user(
+            &conn,
+            NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: Some("Last".to_owned()),
+                username: "reuse_rotated_refresh_token".to_owned(),
+                email: None,
+                password: "fdkjhKFHDKH347(#*&".to_owned(),
+            },
+            &get_auth_config().password_hasher,
+        )
+        .await
+        .expect("Failed to create user");
+
+        let refresh_token = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &auth_config,
+            NewSessionSchema::default(),
+        )
+        .await
+        .map(|user| user.refresh_token)
+        .expect("Failed to signin user");
+
+        // Sleep until the refresh token available
+        tokio::time::sleep(Duration::seconds(3).to_std().unwrap()).await;
+
+        let mut res = send_refresh(&service, &refresh_token).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let new_refresh_token = serde_json::from_str::<UserSigninSchema>(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body")
+        .refresh_token;
+
+        // The rotated refresh token can't be used again
+        let res = send_refresh(&service, &refresh_token).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The response should have a `401 UNAUTHORIZED` status code {res:?}"
+        );
+
+        // Sleep until the new refresh token available
+        tokio::time::sleep(Duration::seconds(3).to_std().unwrap()).await;
+
+        // The reuse revokes the whole family, so the new refresh token is revoked too
+        let res = send_refresh(&service, &new_refresh_token).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The response should have a `401 UNAUTHORIZED` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_after_password_reset() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        // The refresh token will be available after 3 seconds
+        let auth_config = AuthConfig {
+            refresh_token_active_after: Duration::seconds(3),
+            ..get_auth_config()
+        };
+
+        let user = xors_api::db_utils::signin_user(
+            &conn,
+            xors_api::db_utils::create_user(
+                &conn,
+                NewUserSchema {
+                    first_name: "First".to_owned(),
+                    last_name: Some("Last".to_owned()),
+                    username: "refresh_after_password_reset".to_owned(),
+                    email: None,
+                    password: "fdkjhKFHDKH347(#*&".to_owned(),
+                },
+                &get_auth_config().password_hasher,
+            )
+            .await
+            .expect("Failed to create user"),
+            &auth_config,
+            NewSessionSchema::default(),
+        )
+        .await
+        .expect("Failed to signin user");
+
+        let res = send(
+            &service,
+            "user/reset_password",
+            Method::POST,
+            Some(&ResetPasswordSchema {
+                old_password: "fdkjhKFHDKH347(#*&".to_owned(),
+                new_password: "fdkjhKFHDKH347(#*f".to_owned(),
+            }),
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        // Sleep until the refresh token available
+        tokio::time::sleep(Duration::seconds(3).to_std().unwrap()).await;
+
+        let res = send_refresh(&service, &user.refresh_token).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The response should have a `401 UNAUTHORIZED` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_token_without_jti() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let now = chrono::Utc::now().timestamp();
+
+        let user = xors_api::db_utils::create_user(
+            &conn,
+            NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: Some("Last".to_owned()),
+                username: "refresh_token_without_jti".to_owned(),
+                email: None,
+                password: "fdkjhKFHDKH347(#*&".to_owned(),
+            },
+            &get_auth_config().password_hasher,
+        )
+        .await
+        .expect("Failed to create user");
+
+        // A stateless refresh token, like the ones issued before the rotation
+        let refresh_token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &xors_api::api::jwt::JwtClaims::new(user.uuid, Some(now), None, None, 0, now + 60),
+            &jsonwebtoken::EncodingKey::from_secret(get_secret_key().as_bytes()),
+        )
+        .expect("JWT encode failed");
+
+        let res = send_refresh(&service, &refresh_token).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The response should have a `401 UNAUTHORIZED` status code {res:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod logout {
+    use super::*;
+
+    async fn send_with_token(
+        service: &Service,
+        path: &str,
+        method: Method,
+        token: &str,
+    ) -> Response {
+        send(
+            service,
+            path,
+            method,
+            None::<&str>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", token))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn logout_current_session() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "logout_current_session").await;
+        // The refresh token will be available after 3 seconds
+        let session = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &AuthConfig {
+                refresh_token_active_after: Duration::seconds(3),
+                ..get_auth_config()
+            },
+            NewSessionSchema::default(),
+        )
+        .await
+        .expect("Failed to signin player");
+        let other_session = signin_player(&conn, &user).await;
+
+        let res = send_with_token(&service, "auth/logout", Method::POST, &session.jwt).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        let res = send_with_token(&service, "user/me", Method::GET, &session.jwt).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The logged out JWT should be rejected {res:?}"
+        );
+        let res = send_with_token(&service, "user/me", Method::GET, &other_session.jwt).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The other session should still work {res:?}"
+        );
+
+        // Sleep until the refresh token available
+        tokio::time::sleep(Duration::seconds(3).to_std().unwrap()).await;
+
+        let res = send_with_token(
+            &service,
+            "auth/refresh",
+            Method::GET,
+            &session.refresh_token,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The logged out refresh token should be rejected {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn logout_all_sessions() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "logout_all_sessions").await;
+        let session = signin_player(&conn, &user).await;
+        let other_session = signin_player(&conn, &user).await;
+
+        let res = send_with_token(&service, "auth/logout_all", Method::POST, &session.jwt).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        for jwt in [&session.jwt, &other_session.jwt] {
+            let res = send_with_token(&service, "user/me", Method::GET, jwt).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::UNAUTHORIZED),
+                "All the JWTs should be rejected {res:?}"
+            );
+        }
+
+        let res = send_with_token(
+            &service,
+            "user/me",
+            Method::GET,
+            &signin_player(&conn, &user).await.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The new session should work {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn password_reset_logout_all() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "password_reset_logout_all").await;
+        let session = signin_player(&conn, &user).await;
+        let other_session = signin_player(&conn, &user).await;
+
+        let res = send(
+            &service,
+            "user/reset_password",
+            Method::POST,
+            Some(&ResetPasswordSchema {
+                old_password: PLAYER_PASSWORD.to_owned(),
+                new_password: "fdkjhKFHDKH347(#*f".to_owned(),
+            }),
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", session.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        for jwt in [&session.jwt, &other_session.jwt] {
+            let res = send_with_token(&service, "user/me", Method::GET, jwt).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::UNAUTHORIZED),
+                "All the JWTs should be rejected {res:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn logout_with_refresh_token() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "logout_with_refresh_token").await;
+        let session = signin_player(&conn, &user).await;
+
+        let res = send_with_token(
+            &service,
+            "auth/logout",
+            Method::POST,
+            &session.refresh_token,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The response should have a `400 BAD_REQUEST` status code {res:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod sessions {
+    use super::*;
+
+    async fn send_with_token(
+        service: &Service,
+        path: &str,
+        method: Method,
+        token: &str,
+    ) -> Response {
+        send(
+            service,
+            path,
+            method,
+            None::<&str>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", token))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await
+    }
+
+    async fn get_sessions(service: &Service, jwt: &str) -> Vec<SessionSchema> {
+        let mut res = send_with_token(service, "auth/sessions", Method::GET, jwt).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body")
+    }
+
+    #[tokio::test]
+    async fn list_sessions() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "list_sessions").await;
+
+        let old_session = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &get_auth_config(),
+            NewSessionSchema {
+                user_agent: Some("Old device".to_owned()),
+                ip: Some("127.0.0.1".to_owned()),
+            },
+        )
+        .await
+        .expect("Failed to signin user");
+
+        let mut res = send(
+            &service,
+            "auth/signin",
+            Method::POST,
+            Some(&SigninSchema {
+                username: user.username.clone(),
+                password: PLAYER_PASSWORD.to_owned(),
+            }),
+            vec![(header::USER_AGENT, HeaderValue::from_static("New device"))],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let new_session: UserSigninSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+
+        let sessions = get_sessions(&service, &new_session.jwt).await;
+        assert_eq!(sessions.len(), 2, "The user should have two sessions");
+        assert_eq!(sessions[0].user_agent.as_deref(), Some("New device"));
+        assert_eq!(sessions[0].ip.as_deref(), Some("127.1.1.2"));
+        assert!(sessions[0].current, "The newest session is the current one");
+        assert_eq!(sessions[1].user_agent.as_deref(), Some("Old device"));
+        assert!(!sessions[1].current);
+        assert_eq!(sessions[1].last_refresh_at, None);
+
+        let sessions = get_sessions(&service, &old_session.jwt).await;
+        assert!(!sessions[0].current && sessions[1].current);
+    }
+
+    #[tokio::test]
+    async fn revoke_other_session() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "revoke_other_session").await;
+        let session = signin_player(&conn, &user).await;
+        let other_session = signin_player(&conn, &user).await;
+
+        let other_session_id = get_sessions(&service, &session.jwt)
+            .await
+            .into_iter()
+            .find(|session| !session.current)
+            .expect("The other session should be listed")
+            .id;
+
+        let res = send_with_token(
+            &service,
+            &format!("auth/sessions/{other_session_id}"),
+            Method::DELETE,
+            &session.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        let res = send_with_token(&service, "user/me", Method::GET, &other_session.jwt).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The revoked session's JWT should be rejected {res:?}"
+        );
+        let sessions = get_sessions(&service, &session.jwt).await;
+        assert_eq!(
+            sessions.len(),
+            1,
+            "The revoked session should not be listed"
+        );
+
+        // Revoking it again
+        let res = send_with_token(
+            &service,
+            &format!("auth/sessions/{other_session_id}"),
+            Method::DELETE,
+            &session.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::NOT_FOUND),
+            "The response should have a `404 NOT_FOUND` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn revoke_another_user_session() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "revoke_another_user_session").await;
+        let other_user = create_player(&conn, "revoke_another_user_session2").await;
+
+        let session = signin_player(&conn, &user).await;
+        let other_session = signin_player(&conn, &other_user).await;
+        let other_session_id = get_sessions(&service, &other_session.jwt).await[0].id;
+
+        let res = send_with_token(
+            &service,
+            &format!("auth/sessions/{other_session_id}"),
+            Method::DELETE,
+            &session.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::NOT_FOUND),
+            "The response should have a `404 NOT_FOUND` status code {res:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod auth_config {
+    use super::*;
+    use jsonwebtoken::Algorithm;
+    use xors_api::config::{JwtSigningKey, JwtVerificationKey};
+
+    /// Returns the auth config that signs with the given private key, and verifies with all the test public keys.
+    fn asymmetric_auth_config(kid: &str, algorithm: Algorithm, private_key: &[u8]) -> AuthConfig {
+        AuthConfig {
+            signing_key: JwtSigningKey::from_pem(kid.to_owned(), algorithm, private_key)
+                .expect("Failed to create the signing key"),
+            verification_keys: [
+                ("ed-1", include_str!("keys/ed25519_1.pub.pem")),
+                ("ed-2", include_str!("keys/ed25519_2.pub.pem")),
+                ("rsa-1", include_str!("keys/rsa.pub.pem")),
+            ]
+            .into_iter()
+            .map(|(kid, pem)| {
+                JwtVerificationKey::from_pem(kid.to_owned(), pem)
+                    .expect("Failed to create the verification key")
+            })
+            .collect(),
+            ..get_auth_config()
+        }
+    }
+
+    async fn get_me(service: &Service, jwt: &str) -> Response {
+        send(
+            service,
+            "user/me",
+            Method::GET,
+            None::<&str>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", jwt))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn configured_algorithm() {
+        let conn = get_connection().await.expect("Failed to get connection");
+        let auth_config = AuthConfig {
+            signing_key: JwtSigningKey::from_secret(Algorithm::HS512, &get_secret_key())
+                .expect("Failed to create the signing key"),
+            verification_keys: vec![JwtVerificationKey::from_secret(
+                Algorithm::HS512,
+                &get_secret_key(),
+            )
+            .expect("Failed to create the verification key")],
+            ..get_auth_config()
+        };
+        let service = xors_api::api::service(
+            get_connection().await.expect("Failed to get connection"),
+            100,
+            10,
+            auth_config.clone(),
+            get_mailer(),
+        )
+        .0;
+
+        let user = xors_api::db_utils::create_user(
+            &conn,
+            NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: Some("Last".to_owned()),
+                username: "configured_algorithm".to_owned(),
+                email: None,
+                password: "fdkjhKFHDKH347(#*&".to_owned(),
+            },
+            &get_auth_config().password_hasher,
+        )
+        .await
+        .expect("Failed to create user");
+
+        for (config, status_code) in [
+            (&auth_config, StatusCode::OK),
+            (&get_auth_config(), StatusCode::FORBIDDEN),
+        ] {
+            let jwt = xors_api::db_utils::signin_user(
+                &conn,
+                user.clone(),
+                config,
+                NewSessionSchema::default(),
+            )
+            .await
+            .expect("Failed to signin user")
+            .jwt;
+
+            let res = send(
+                &service,
+                "user/me",
+                Method::GET,
+                None::<&str>,
+                vec![(
+                    header::AUTHORIZATION,
+                    HeaderValue::from_str(&format!("Bearer {}", jwt))
+                        .expect("Failed to create header value"),
+                )],
+            )
+            .await;
+            assert_eq!(
+                res.status_code,
+                Some(status_code),
+                "The token's algorithm should be the configured one {res:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn configured_lifetimes() {
+        let conn = get_connection().await.expect("Failed to get connection");
+        let auth_config = AuthConfig {
+            jwt_ttl: Duration::minutes(10),
+            refresh_token_ttl: Duration::minutes(30),
+            refresh_token_active_after: Duration::minutes(20),
+            ..get_auth_config()
+        };
+
+        let user = xors_api::db_utils::create_user(
+            &conn,
+            NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: Some("Last".to_owned()),
+                username: "configured_lifetimes".to_owned(),
+                email: None,
+                password: "fdkjhKFHDKH347(#*&".to_owned(),
+            },
+            &get_auth_config().password_hasher,
+        )
+        .await
+        .expect("Failed to create user");
+        let now = chrono::Utc::now().timestamp();
+        let tokens =
+            xors_api::db_utils::signin_user(&conn, user, &auth_config, NewSessionSchema::default())
+                .await
+                .expect("Failed to signin user");
+
+        let decode = |token: &str| {
+            jsonwebtoken::decode::<xors_api::api::jwt::JwtClaims>(
+                token,
+                &jsonwebtoken::DecodingKey::from_secret(get_secret_key().as_bytes()),
+                &jsonwebtoken::Validation::default(),
+            )
+            .expect("Failed to decode the token")
+            .claims
+        };
+        let jwt = decode(&tokens.jwt);
+        let refresh_token = decode(&tokens.refresh_token);
+
+        assert!((jwt.exp - now - 10 * 60).abs() <= 1);
+        assert!((refresh_token.exp - now - 30 * 60).abs() <= 1);
+        assert!(
+            (refresh_token.active_after.expect("It's a refresh token") - now - 20 * 60).abs() <= 1
+        );
+    }
+
+    #[tokio::test]
+    async fn asymmetric_keys_rotation() {
+        let conn = get_connection().await.expect("Failed to get connection");
+        let auth_config = asymmetric_auth_config(
+            "ed-1",
+            Algorithm::EdDSA,
+            include_bytes!("keys/ed25519_1.pem"),
+        );
+        let service = xors_api::api::service(
+            get_connection().await.expect("Failed to get connection"),
+            100,
+            10,
+            auth_config.clone(),
+            get_mailer(),
+        )
+        .0;
+        let user = xors_api::db_utils::create_user(
+            &conn,
+            NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: Some("Last".to_owned()),
+                username: "asymmetric_keys_rotation".to_owned(),
+                email: None,
+                password: "fdkjhKFHDKH347(#*&".to_owned(),
+            },
+            &get_auth_config().password_hasher,
+        )
+        .await
+        .expect("Failed to create user");
+
+        // The old key, the new key and the RSA key are all active
+        for (config, status_code) in [
+            (auth_config, StatusCode::OK),
+            (
+                asymmetric_auth_config(
+                    "ed-2",
+                    Algorithm::EdDSA,
+                    include_bytes!("keys/ed25519_2.pem"),
+                ),
+                StatusCode::OK,
+            ),
+            (
+                asymmetric_auth_config("rsa-1", Algorithm::RS256, include_bytes!("keys/rsa.pem")),
+                StatusCode::OK,
+            ),
+            // The token is signed by `ed-2` key but claims it's `ed-1`
+            (
+                asymmetric_auth_config(
+                    "ed-1",
+                    Algorithm::EdDSA,
+                    include_bytes!("keys/ed25519_2.pem"),
+                ),
+                StatusCode::FORBIDDEN,
+            ),
+            // Unknown key id
+            (
+                asymmetric_auth_config(
+                    "ed-3",
+                    Algorithm::EdDSA,
+                    include_bytes!("keys/ed25519_1.pem"),
+                ),
+                StatusCode::FORBIDDEN,
+            ),
+            (get_auth_config(), StatusCode::FORBIDDEN),
+        ] {
+            let jwt = xors_api::db_utils::signin_user(
+                &conn,
+                user.clone(),
+                &config,
+                NewSessionSchema::default(),
+            )
+            .await
+            .expect("Failed to signin user")
+            .jwt;
+
+            let res = get_me(&service, &jwt).await;
+            assert_eq!(
+                res.status_code,
+                Some(status_code),
+                "The token of `{:?}` key should be verified {res:?}",
+                config.signing_key.kid
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn jwks_endpoint() {
+        let conn = get_connection().await.expect("Failed to get connection");
+        let auth_config =
+            asymmetric_auth_config("rsa-1", Algorithm::RS256, include_bytes!("keys/rsa.pem"));
+        let service = xors_api::api::service(
+            get_connection().await.expect("Failed to get connection"),
+            100,
+            10,
+            auth_config.clone(),
+            get_mailer(),
+        )
+        .0;
+
+        let mut res = send(
+            &service,
+            ".well-known/jwks.json",
+            Method::GET,
+            None::<&str>,
+            vec![],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let jwks: JwksSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+        assert_eq!(
+            jwks.keys
+                .iter()
+                .map(|key| (key.kid.as_str(), key.kty.as_str(), key.alg.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("ed-1", "OKP", "EdDSA"),
+                ("ed-2", "OKP", "EdDSA"),
+                ("rsa-1", "RSA", "RS256")
+            ]
+        );
+
+        // Other services can verify the tokens with the published key
+        let user = xors_api::db_utils::create_user(
+            &conn,
+            NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: Some("Last".to_owned()),
+                username: "jwks_endpoint".to_owned(),
+                email: None,
+                password: "fdkjhKFHDKH347(#*&".to_owned(),
+            },
+            &get_auth_config().password_hasher,
+        )
+        .await
+        .expect("Failed to create user");
+        let jwt = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &auth_config,
+            NewSessionSchema::default(),
+        )
+        .await
+        .expect("Failed to signin user")
+        .jwt;
+        let jwk: jsonwebtoken::jwk::Jwk =
+            serde_json::from_value(serde_json::to_value(&jwks.keys[2]).unwrap())
+                .expect("The key should be a valid JWK");
+        let claims = jsonwebtoken::decode::<xors_api::api::jwt::JwtClaims>(
+            &jwt,
+            &jsonwebtoken::DecodingKey::from_jwk(&jwk).expect("Failed to create the key"),
+            &jsonwebtoken::Validation::new(Algorithm::RS256),
+        )
+        .expect("The token should be verified with the published key")
+        .claims;
+        assert_eq!(claims.uuid, user.uuid);
+    }
+
+    #[tokio::test]
+    async fn jwks_with_secret() {
+        let service = get_service().await.expect("Failed to get service");
+
+        let mut res = send(
+            &service,
+            ".well-known/jwks.json",
+            Method::GET,
+            None::<&str>,
+            vec![],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let jwks: JwksSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+        assert!(jwks.keys.is_empty(), "The secret must not be published");
+    }
+
+    #[tokio::test]
+    async fn rehash_outdated_password() {
+        let conn = get_connection().await.expect("Failed to get connection");
+        let service = get_service().await.expect("Failed to get service");
+        let password_hasher = get_auth_config().password_hasher;
+
+        xors_api::db_utils::create_user(
+            &conn,
+            NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: Some("Last".to_owned()),
+                username: "rehash_outdated_password".to_owned(),
+                email: None,
+                password: "fdkjhKFHDKH347(#*&".to_owned(),
+            },
+            &PasswordHasher::Bcrypt { cost: 4 },
+        )
+        .await
+        .expect("Failed to create user");
+        let password_hash = |conn| async move {
+            xors_api::db_utils::get_user_by_username(conn, "rehash_outdated_password".to_owned())
+                .await
+                .expect("Failed to get user")
+                .password_hash
+                .unwrap()
+        };
+        assert!(password_hasher.needs_rehash(&password_hash(&conn).await));
+
+        let res = send(
+            &service,
+            "auth/signin",
+            Method::POST,
+            Some(&SigninSchema {
+                username: "rehash_outdated_password".to_owned(),
+                password: "fdkjhKFHDKH347(#*&".to_owned(),
+            }),
+            vec![],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        let new_password_hash = password_hash(&conn).await;
+        assert!(new_password_hash.starts_with("$argon2id$"));
+        assert!(!password_hasher.needs_rehash(&new_password_hash));
+        assert!(
+            PasswordHasher::verify("fdkjhKFHDKH347(#*&", &new_password_hash)
+                .expect("Failed to verify the password")
+        );
+    }
+}
+
+#[cfg(test)]
+mod totp {
+    use super::*;
+    use base64::Engine;
+    use xors_api::totp;
+
+    async fn send_with_token<T: Serialize + ?Sized>(
+        service: &Service,
+        path: &str,
+        body: Option<&T>,
+        token: &str,
+    ) -> Response {
+        send(
+            service,
+            path,
+            Method::POST,
+            body,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", token))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await
+    }
+
+    async fn signin(service: &Service, username: &str) -> serde_json::Value {
+        let mut res = send(
+            service,
+            "auth/signin",
+            Method::POST,
+            Some(&SigninSchema {
+                username: username.to_owned(),
+                password: PLAYER_PASSWORD.to_owned(),
+            }),
+            vec![],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body")
+    }
+
+    /// Returns the user's TOTP secret from the database.
+    async fn get_secret(conn: &sea_orm::DatabaseConnection, user: &UserSchema) -> Vec<u8> {
+        let user = UserEntity::find()
+            .filter(UserColumn::Uuid.eq(user.uuid))
+            .one(conn)
+            .await
+            .expect("Failed to get user")
+            .expect("The user should exist");
+        xors_api::BASE_64_ENGINE
+            .decode(user.totp_secret.expect("The secret should be enrolled"))
+            .expect("The secret should be base64")
+    }
+
+    /// Enrolls and enables the user's two-factor authentication, and returns the secret with the recovery codes.
+    async fn enable_totp(
+        service: &Service,
+        conn: &sea_orm::DatabaseConnection,
+        user: &UserSchema,
+    ) -> (Vec<u8>, Vec<String>) {
+        let session = xors_api::db_utils::signin_user(
+            conn,
+            user.clone(),
+            &get_auth_config(),
+            NewSessionSchema::default(),
+        )
+        .await
+        .expect("Failed to signin user");
+
+        let res = send_with_token(service, "auth/totp/enroll", None::<&str>, &session.jwt).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let secret = get_secret(conn, user).await;
+
+        let mut res = send_with_token(
+            service,
+            "auth/totp/verify",
+            Some(&TotpCodeSchema {
+                code: totp::code_at(&secret, totp::current_step()),
+            }),
+            &session.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let recovery_codes: RecoveryCodesSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+
+        (secret, recovery_codes.recovery_codes)
+    }
+
+    async fn signin_totp(service: &Service, challenge_token: &str, code: &str) -> Response {
+        send(
+            service,
+            "auth/signin/totp",
+            Method::POST,
+            Some(&TotpSigninSchema {
+                challenge_token: challenge_token.to_owned(),
+                code: code.to_owned(),
+            }),
+            vec![],
+        )
+        .await
+    }
+
+    #[test]
+    fn rfc6238_codes() {
+        let secret = b"12345678901234567890";
+        for (time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ] {
+            assert_eq!(totp::code_at(secret, time / 30), code, "At {time}");
+        }
+    }
+
+    #[test]
+    fn base32_secret() {
+        assert_eq!(totp::encode_secret(b""), "");
+        assert_eq!(totp::encode_secret(b"f"), "MY");
+        assert_eq!(totp::encode_secret(b"foob"), "MZXW6YQ");
+        assert_eq!(totp::encode_secret(b"foobar"), "MZXW6YTBOI");
+    }
+
+    #[tokio::test]
+    async fn enroll_and_verify() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "totp_enroll_and_verify").await;
+        let session = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &get_auth_config(),
+            NewSessionSchema::default(),
+        )
+        .await
+        .expect("Failed to signin user");
+
+        let res = send_with_token(
+            &service,
+            "auth/totp/verify",
+            Some(&TotpCodeSchema::default()),
+            &session.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The TOTP can't be verified before the enrollment {res:?}"
+        );
+
+        let mut res =
+            send_with_token(&service, "auth/totp/enroll", None::<&str>, &session.jwt).await;
+        let enrollment: TotpEnrollmentSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+        let secret = get_secret(&conn, &user).await;
+        assert_eq!(enrollment.secret, totp::encode_secret(&secret));
+        assert_eq!(
+            enrollment.provisioning_uri,
+            totp::provisioning_uri(&user.username, &secret)
+        );
+        assert!(enrollment.qr_code.starts_with("data:image/png;base64,"));
+
+        let code = totp::code_at(&secret, totp::current_step() - 5);
+        let res = send_with_token(
+            &service,
+            "auth/totp/verify",
+            Some(&TotpCodeSchema { code }),
+            &session.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The old code should be rejected {res:?}"
+        );
+
+        let code = totp::code_at(&secret, totp::current_step());
+        let res = send_with_token(
+            &service,
+            "auth/totp/verify",
+            Some(&TotpCodeSchema { code }),
+            &session.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        let res = send_with_token(&service, "auth/totp/enroll", None::<&str>, &session.jwt).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The enabled TOTP can't be enrolled again {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn signin_with_challenge() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "totp_signin_with_challenge").await;
+        let (secret, _) = enable_totp(&service, &conn, &user).await;
+
+        let challenge: TotpChallengeSchema =
+            serde_json::from_value(signin(&service, &user.username).await)
+                .expect("The signin should return a challenge");
+
+        let res = send(
+            &service,
+            "user/me",
+            Method::GET,
+            None::<&str>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", challenge.challenge_token))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The challenge token can't be used as a JWT {res:?}"
+        );
+
+        // The current code is used by the verification
+        let used_code = totp::code_at(&secret, totp::current_step());
+        let res = signin_totp(&service, &challenge.challenge_token, &used_code).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The used code should be rejected {res:?}"
+        );
+
+        let code = totp::code_at(&secret, totp::current_step() + 1);
+        let mut res = signin_totp(&service, &challenge.challenge_token, &code).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let user_signin: UserSigninSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+        assert_eq!(user_signin.user, user);
+
+        let res = signin_totp(&service, "invalid", &code).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The invalid challenge should be rejected {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn recovery_codes() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "totp_recovery_codes").await;
+        let (_, recovery_codes) = enable_totp(&service, &conn, &user).await;
+        assert_eq!(recovery_codes.len(), 10);
+        let password_hasher = get_auth_config().password_hasher;
+        let recovery_codes_models = RecoveryCodeEntity::find()
+            .filter(RecoveryCodeColumn::UserUuid.eq(user.uuid))
+            .all(&conn)
+            .await
+            .expect("Failed to get the recovery codes");
+        assert!(
+            recovery_codes_models
+                .iter()
+                .all(|recovery_code| !password_hasher.needs_rehash(&recovery_code.code_hash)),
+            "The recovery codes should be hashed with the configured hasher"
+        );
+
+        let challenge: TotpChallengeSchema =
+            serde_json::from_value(signin(&service, &user.username).await)
+                .expect("The signin should return a challenge");
+
+        let res = signin_totp(&service, &challenge.challenge_token, &recovery_codes[0]).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let res = signin_totp(&service, &challenge.challenge_token, &recovery_codes[0]).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The recovery code can only be used one time {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn disable() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "totp_disable").await;
+        let (_, recovery_codes) = enable_totp(&service, &conn, &user).await;
+        let session = xors_api::db_utils::signin_user(
+            &conn,
+            user.clone(),
+            &get_auth_config(),
+            NewSessionSchema::default(),
+        )
+        .await
+        .expect("Failed to signin user");
+
+        let res = send_with_token(
+            &service,
+            "auth/totp/disable",
+            Some(&DisableTotpSchema {
+                password: "Wrong_Password1".to_owned(),
+                code: recovery_codes[0].clone(),
+            }),
+            &session.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The wrong password should be rejected {res:?}"
+        );
+
+        let res = send_with_token(
+            &service,
+            "auth/totp/disable",
+            Some(&DisableTotpSchema {
+                password: PLAYER_PASSWORD.to_owned(),
+                code: recovery_codes[0].clone(),
+            }),
+            &session.jwt,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        let user_signin: UserSigninSchema =
+            serde_json::from_value(signin(&service, &user.username).await)
+                .expect("The signin should not return a challenge");
+        assert_eq!(user_signin.user, user);
+    }
+}
+
+#[cfg(test)]
+mod lockout {
+    use super::*;
+
+    const PASSWORD: &str = "fdkjhKFHDKH347(#*&";
+
+    /// Returns a service that locks after 3 failed attempts, for 2 seconds up to 4 seconds.
+    async fn get_lockout_service() -> Service {
+        std::env::set_var("XORS_API_TEST", "true");
+        xors_api::api::service(
+            get_connection().await.expect("Failed to get connection"),
+            100,
+            10,
+            AuthConfig {
+                lockout_threshold: 3,
+                lockout_duration: Duration::seconds(2),
+                lockout_max_duration: Duration::seconds(4),
+                ..get_auth_config()
+            },
+            get_mailer(),
+        )
+        .0
+    }
+
+    async fn signin(service: &Service, username: &str, password: &str) -> Response {
+        send(
+            service,
+            "auth/signin",
+            Method::POST,
+            Some(&SigninSchema {
+                username: username.to_owned(),
+                password: password.to_owned(),
+            }),
+            vec![],
+        )
+        .await
+    }
+
+    fn retry_after(res: &Response) -> i64 {
+        res.headers()
+            .get(header::RETRY_AFTER)
+            .expect("The locked response should have a `Retry-After` header")
+            .to_str()
+            .expect("The header should be a string")
+            .parse()
+            .expect("The header should be a number")
+    }
+
+    #[test]
+    fn lockout_durations() {
+        let auth_config = AuthConfig {
+            lockout_threshold: 5,
+            lockout_duration: Duration::seconds(30),
+            lockout_max_duration: Duration::hours(1),
+            ..get_auth_config()
+        };
+        for (failed_attempts, duration) in [
+            (0, None),
+            (4, None),
+            (5, Some(Duration::seconds(30))),
+            (6, Some(Duration::seconds(60))),
+            (8, Some(Duration::seconds(240))),
+            (12, Some(Duration::hours(1))),
+            (u64::MAX, Some(Duration::hours(1))),
+        ] {
+            assert_eq!(
+                auth_config.lockout_duration_after(failed_attempts),
+                duration,
+                "After {failed_attempts} failed attempts"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn lock_after_failed_attempts() {
+        let service = get_lockout_service().await;
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = xors_api::db_utils::create_user(
+            &conn,
+            NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: Some("Last".to_owned()),
+                username: "lock_after_failed_attempts".to_owned(),
+                email: None,
+                password: PASSWORD.to_owned(),
+            },
+            &get_auth_config().password_hasher,
+        )
+        .await
+        .expect("Failed to create user");
+
+        for _ in 0..3 {
+            let res = signin(&service, &user.username, "Wrong_Password1").await;
+            assert!(
+                res.headers().get(header::RETRY_AFTER).is_none(),
+                "The account should not be locked yet {res:?}"
+            );
+        }
+
+        let res = signin(&service, &user.username, PASSWORD).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The locked account can't signin even with the correct password {res:?}"
+        );
+        assert!((1..=2).contains(&retry_after(&res)));
+
+        tokio::time::sleep(Duration::seconds(2).to_std().unwrap()).await;
+        signin(&service, &user.username, "Wrong_Password1").await;
+        // The lockout is doubled
+        let res = signin(&service, &user.username, PASSWORD).await;
+        assert!((3..=4).contains(&retry_after(&res)));
+
+        tokio::time::sleep(Duration::seconds(4).to_std().unwrap()).await;
+        let mut res = signin(&service, &user.username, PASSWORD).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let user_signin: UserSigninSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+        let failed_signins = user_signin
+            .failed_signins
+            .expect("The failed attempts should be returned");
+        assert_eq!(failed_signins.count, 4);
+        assert_eq!(failed_signins.last_ip.as_deref(), Some("127.1.1.2"));
+
+        // The failed attempts are reset after the signin
+        let mut res = signin(&service, &user.username, PASSWORD).await;
+        let user_signin: UserSigninSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+        assert_eq!(user_signin.failed_signins, None);
+    }
+
+    #[tokio::test]
+    async fn lock_unknown_username() {
+        let service = get_lockout_service().await;
+
+        for _ in 0..3 {
+            signin(&service, "lock_unknown_username", "Wrong_Password1").await;
+        }
+        let res = signin(&service, "lock_unknown_username", "Wrong_Password1").await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The unknown username should be locked like the existing ones {res:?}"
+        );
+        assert!((1..=2).contains(&retry_after(&res)));
+    }
+}
+
+#[cfg(test)]
+mod recovery {
+    use super::*;
+
+    const PASSWORD: &str = "fdkjhKFHDKH347(#*&";
+    const NEW_PASSWORD: &str = "fdkjhKFHDKH347(#*f";
+
+    async fn send_json<T: Serialize + ?Sized>(
+        service: &Service,
+        path: &str,
+        method: Method,
+        body: &T,
+        token: Option<&str>,
+    ) -> Response {
+        send(
+            service,
+            path,
+            method,
+            Some(body),
+            token
+                .map(|token| {
+                    (
+                        header::AUTHORIZATION,
+                        HeaderValue::from_str(&format!("Bearer {}", token))
+                            .expect("Failed to create header value"),
+                    )
+                })
+                .into_iter()
+                .collect(),
+        )
+        .await
+    }
+
+    async fn signup(service: &Service, username: &str) -> UserSigninSchema {
+        let mut res = send_json(
+            service,
+            "auth/signup",
+            Method::POST,
+            &NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: Some("Last".to_owned()),
+                username: username.to_owned(),
+                email: None,
+                password: PASSWORD.to_owned(),
+            },
+            None,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body")
+    }
+
+    async fn signin_status(service: &Service, username: &str, password: &str) -> StatusCode {
+        send_json(
+            service,
+            "auth/signin",
+            Method::POST,
+            &SigninSchema {
+                username: username.to_owned(),
+                password: password.to_owned(),
+            },
+            None,
+        )
+        .await
+        .status_code
+        .expect("The response should have a status code")
+    }
+
+    async fn reset_password(service: &Service, reset_token: &str) -> Response {
+        send_json(
+            service,
+            "auth/reset_password",
+            Method::POST,
+            &CompletePasswordResetSchema {
+                reset_token: reset_token.to_owned(),
+                new_password: NEW_PASSWORD.to_owned(),
+            },
+            None,
+        )
+        .await
+    }
+
+    /// Returns the password reset tokens that are mailed to the email, the token is the only line of 43 characters.
+    fn mailed_tokens(email: &str) -> Vec<String> {
+        std::fs::read_dir(get_mail_dir())
+            .expect("Failed to read the mails directory")
+            .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path()).ok())
+            .filter(|mail| mail.contains(&format!("To: {email}")))
+            .flat_map(|mail| {
+                mail.lines()
+                    .map(str::trim)
+                    .filter(|line| {
+                        line.len() == 43
+                            && line
+                                .chars()
+                                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                    })
+                    .map(ToOwned::to_owned)
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Returns the email verification tokens that are mailed to the email, they are in the verification links.
+    /// The long link lines are quoted-printable encoded, so the soft line breaks are removed first.
+    fn mailed_verification_tokens(email: &str) -> Vec<String> {
+        std::fs::read_dir(get_mail_dir())
+            .expect("Failed to read the mails directory")
+            .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path()).ok())
+            .filter(|mail| mail.contains(&format!("To: {email}")))
+            .map(|mail| {
+                mail.replace("=\r\n", "")
+                    .replace("=\n", "")
+                    .replace("=3D", "=")
+            })
+            .filter_map(|mail| {
+                mail.lines()
+                    .find_map(|line| line.trim().split_once("/auth/verify_email?token="))
+                    .map(|(_, token)| token.to_owned())
+            })
+            .collect()
+    }
+
+    async fn update_email(service: &Service, jwt: &str, email: Option<&str>) -> Response {
+        send_json(
+            service,
+            "user/email",
+            Method::PUT,
+            &UpdateEmailSchema {
+                email: email.map(ToOwned::to_owned),
+                password: PASSWORD.to_owned(),
+            },
+            Some(jwt),
+        )
+        .await
+    }
+
+    async fn verify_email(service: &Service, token: &str) -> Response {
+        send(
+            service,
+            &format!("auth/verify_email?token={token}"),
+            Method::GET,
+            None::<&str>,
+            vec![],
+        )
+        .await
+    }
+
+    async fn get_me(service: &Service, jwt: &str) -> UserSchema {
+        let mut res = send(
+            service,
+            "user/me",
+            Method::GET,
+            None::<&str>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", jwt))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await;
+        serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body")
+    }
+
+    #[tokio::test]
+    async fn recover_with_recovery_code() {
+        let service = get_service().await.expect("Failed to get service");
+
+        let user = signup(&service, "recover_with_recovery_code").await;
+        let recovery_codes = user
+            .recovery_codes
+            .expect("The signup should return the recovery codes");
+        assert_eq!(recovery_codes.len(), 10);
+
+        let mut res = send_json(
+            &service,
+            "auth/recover_password",
+            Method::POST,
+            &RecoverPasswordSchema {
+                username: "recover_with_recovery_code".to_owned(),
+                recovery_code: recovery_codes[0].clone(),
+            },
+            None,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let reset_token: PasswordResetTokenSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+
+        // The recovery code can only be used one time
+        let res = send_json(
+            &service,
+            "auth/recover_password",
+            Method::POST,
+            &RecoverPasswordSchema {
+                username: "recover_with_recovery_code".to_owned(),
+                recovery_code: recovery_codes[0].clone(),
+            },
+            None,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The used recovery code should be rejected {res:?}"
+        );
+
+        let res = reset_password(&service, &reset_token.reset_token).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        // The reset token can only be used one time
+        let res = reset_password(&service, &reset_token.reset_token).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The used reset token should be rejected {res:?}"
+        );
+
+        assert_eq!(
+            signin_status(&service, "recover_with_recovery_code", PASSWORD).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            signin_status(&service, "recover_with_recovery_code", NEW_PASSWORD).await,
+            StatusCode::OK
+        );
+
+        // The old sessions are revoked
+        let res = send(
+            &service,
+            "user/me",
+            Method::GET,
+            None::<&str>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The old JWT should be revoked after the password reset {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn recover_with_email() {
+        let service = get_service().await.expect("Failed to get service");
+        let email = format!("recover_with_email_{}@example.com", Uuid::new_v4().simple());
+
+        let user = signup(&service, "recover_with_email").await;
+        let res = update_email(&service, &user.jwt, Some(&email)).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        // The unverified email doesn't receive the reset tokens
+        let res = send_json(
+            &service,
+            "auth/forgot_password",
+            Method::POST,
+            &ForgotPasswordSchema {
+                username: "recover_with_email".to_owned(),
+            },
+            None,
+        )
+        .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert!(mailed_tokens(&email).is_empty());
+
+        let verification_tokens = mailed_verification_tokens(&email);
+        assert_eq!(verification_tokens.len(), 1);
+        let res = verify_email(&service, &verification_tokens[0]).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+
+        for _ in 0..2 {
+            let res = send_json(
+                &service,
+                "auth/forgot_password",
+                Method::POST,
+                &ForgotPasswordSchema {
+                    username: "recover_with_email".to_owned(),
+                },
+                None,
+            )
+            .await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::OK),
+                "The response should have a `200 OK` status code {res:?}"
+            );
+        }
+        let reset_tokens = mailed_tokens(&email);
+        assert_eq!(reset_tokens.len(), 2, "Every request should send a mail");
+
+        // Only the last requested token is valid
+        let mut results = Vec::new();
+        for reset_token in &reset_tokens {
+            results.push(reset_password(&service, reset_token).await.status_code);
+        }
+        results.sort();
+        assert_eq!(
+            results,
+            vec![Some(StatusCode::OK), Some(StatusCode::FORBIDDEN)]
+        );
+        assert_eq!(
+            signin_status(&service, "recover_with_email", NEW_PASSWORD).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn forgot_password_without_email() {
+        let service = get_service().await.expect("Failed to get service");
+
+        signup(&service, "forgot_password_without_email").await;
+        let mut messages = Vec::new();
+        for username in ["forgot_password_without_email", "forgot_password_unknown"] {
+            let mut res = send_json(
+                &service,
+                "auth/forgot_password",
+                Method::POST,
+                &ForgotPasswordSchema {
+                    username: username.to_owned(),
+                },
+                None,
+            )
+            .await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::OK),
+                "The response should have a `200 OK` status code {res:?}"
+            );
+            messages.push(
+                res.take_string()
+                    .await
+                    .expect("Could not get the response body"),
+            );
+        }
+        assert_eq!(
+            messages[0], messages[1],
+            "The response should not reveal whether the user exists"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_email() {
+        let service = get_service().await.expect("Failed to get service");
+
+        let user = signup(&service, "invalid_email").await;
+        for (email, password) in [
+            ("not an email", PASSWORD),
+            ("invalid_email@example.com", NEW_PASSWORD),
+        ] {
+            let res = send_json(
+                &service,
+                "user/email",
+                Method::PUT,
+                &UpdateEmailSchema {
+                    email: Some(email.to_owned()),
+                    password: password.to_owned(),
+                },
+                Some(&user.jwt),
+            )
+            .await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::BAD_REQUEST),
+                "The invalid email or password should be rejected {res:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_email_on_signup() {
+        let service = get_service().await.expect("Failed to get service");
+        let email = format!("verify_email_{}@example.com", Uuid::new_v4().simple());
+
+        let mut res = send_json(
+            &service,
+            "auth/signup",
+            Method::POST,
+            &NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: None,
+                username: "verify_email_on_signup".to_owned(),
+                email: Some(email.clone()),
+                password: PASSWORD.to_owned(),
+            },
+            None,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let user: UserSigninSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+        assert_eq!(user.user.email.as_deref(), Some(email.as_str()));
+        assert_eq!(user.user.verified, Some(false));
+
+        let verification_tokens = mailed_verification_tokens(&email);
+        assert_eq!(
+            verification_tokens.len(),
+            1,
+            "The signup should send a mail"
+        );
+        let res = verify_email(&service, &verification_tokens[0]).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        assert_eq!(get_me(&service, &user.jwt).await.verified, Some(true));
+        let res = verify_email(&service, &verification_tokens[0]).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The email is already verified {res:?}"
+        );
+
+        // The email and the verified flag are only returned to the user himself
+        let mut res = send(
+            &service,
+            &format!("user?uuid={}", user.user.uuid),
+            Method::GET,
+            None::<&str>,
+            vec![],
+        )
+        .await;
+        let body = res
+            .take_string()
+            .await
+            .expect("Could not get the response body");
+        assert!(!body.contains(&email), "The email should be hidden {body}");
+        assert!(
+            !body.contains("verified"),
+            "The flag should be hidden {body}"
+        );
+
+        // Changing the email invalidates the old verification tokens
+        let new_email = format!("verify_email_{}@example.com", Uuid::new_v4().simple());
+        let res = update_email(&service, &user.jwt, Some(&new_email)).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(get_me(&service, &user.jwt).await.verified, Some(false));
+        let res = verify_email(&service, &verification_tokens[0]).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The old email token should be rejected {res:?}"
+        );
+        let res = verify_email(&service, "invalid").await;
+        assert_eq!(res.status_code, Some(StatusCode::FORBIDDEN));
+
+        let res = send(
+            &service,
+            "user/email/verify",
+            Method::POST,
+            None::<&str>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let verification_tokens = mailed_verification_tokens(&new_email);
+        assert_eq!(
+            verification_tokens.len(),
+            2,
+            "The link should be sent again"
+        );
+        for verification_token in &verification_tokens {
+            let res = verify_email(&service, verification_token).await;
+            assert!(matches!(
+                res.status_code,
+                Some(StatusCode::OK) | Some(StatusCode::BAD_REQUEST)
+            ));
+        }
+        assert_eq!(get_me(&service, &user.jwt).await.verified, Some(true));
+    }
+
+    /// A mailer that fails to send every mail.
+    struct FailingMailer;
+
+    #[async_trait::async_trait]
+    impl Mailer for FailingMailer {
+        async fn send(&self, _mail: xors_api::mailer::Mail) -> ApiResult<()> {
+            Err(xors_api::errors::ApiError::Mail(
+                "The mail server is down".to_owned(),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn signup_with_failing_mailer() {
+        std::env::set_var("XORS_API_TEST", "true");
+        let service = xors_api::api::service(
+            get_connection().await.expect("Failed to get connection"),
+            100,
+            10,
+            get_auth_config(),
+            Arc::new(FailingMailer),
+        )
+        .0;
+        let email = format!("failing_mailer_{}@example.com", Uuid::new_v4().simple());
+
+        let mut res = send_json(
+            &service,
+            "auth/signup",
+            Method::POST,
+            &NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: None,
+                username: "signup_with_failing_mailer".to_owned(),
+                email: Some(email.clone()),
+                password: PASSWORD.to_owned(),
+            },
+            None,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The user should be created even if the mail is not sent {res:?}"
+        );
+        let user: UserSigninSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+        assert_eq!(user.user.email.as_deref(), Some(email.as_str()));
+        assert_eq!(user.user.verified, Some(false));
+
+        // The link can be sent again when the mail server is back
+        let service = get_service().await.expect("Failed to get service");
+        let res = send(
+            &service,
+            "user/email/verify",
+            Method::POST,
+            None::<&str>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt))
+                    .expect("Failed to create header value"),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        assert_eq!(mailed_verification_tokens(&email).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn set_recovery_email() {
+        let service = get_service().await.expect("Failed to get service");
+        let email = format!("set_recovery_email_{}@example.com", Uuid::new_v4().simple());
+
+        let user = signup(&service, "set_recovery_email").await;
+        let res = send_json(
+            &service,
+            "auth/recovery/email",
+            Method::PUT,
+            &RecoveryEmailSchema {
+                email: Some(email.clone()),
+                password: NEW_PASSWORD.to_owned(),
+            },
+            Some(&user.jwt),
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The incorrect password should be rejected {res:?}"
+        );
+
+        let res = send_json(
+            &service,
+            "auth/recovery/email",
+            Method::PUT,
+            &RecoveryEmailSchema {
+                email: Some(email.clone()),
+                password: PASSWORD.to_owned(),
+            },
+            Some(&user.jwt),
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        // The recovery email is the user's email, and it's verified like it
+        let me = get_me(&service, &user.jwt).await;
+        assert_eq!(me.email.as_deref(), Some(email.as_str()));
+        assert_eq!(me.verified, Some(false));
+        let verification_tokens = mailed_verification_tokens(&email);
+        assert_eq!(verification_tokens.len(), 1);
+        let res = verify_email(&service, &verification_tokens[0]).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+
+        let res = send_json(
+            &service,
+            "auth/forgot_password",
+            Method::POST,
+            &ForgotPasswordSchema {
+                username: "set_recovery_email".to_owned(),
+            },
+            None,
+        )
+        .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        assert_eq!(mailed_tokens(&email).len(), 1);
+
+        let res = send_json(
+            &service,
+            "auth/recovery/email",
+            Method::PUT,
+            &RecoveryEmailSchema {
+                email: None,
+                password: PASSWORD.to_owned(),
+            },
+            Some(&user.jwt),
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        assert_eq!(get_me(&service, &user.jwt).await.email, None);
+    }
+
+    #[tokio::test]
+    async fn duplicate_email() {
+        let service = get_service().await.expect("Failed to get service");
+        let email = format!("duplicate_email_{}@example.com", Uuid::new_v4().simple());
+
+        let user = signup(&service, "duplicate_email").await;
+        let res = update_email(&service, &user.jwt, Some(&email)).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        // Changing the case of the user's own email is allowed
+        let res = update_email(&service, &user.jwt, Some(&email.to_uppercase())).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+
+        let other_user = signup(&service, "duplicate_email2").await;
+        let res = update_email(&service, &other_user.jwt, Some(&email.to_lowercase())).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The email should be unique regardless of its case {res:?}"
+        );
+        let res = send_json(
+            &service,
+            "auth/signup",
+            Method::POST,
+            &NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: None,
+                username: "duplicate_email3".to_owned(),
+                email: Some(email.to_lowercase()),
+                password: PASSWORD.to_owned(),
+            },
+            None,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The email should be unique regardless of its case {res:?}"
+        );
+
+        // Removing the email frees it
+        let res = update_email(&service, &user.jwt, None).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let res = update_email(&service, &other_user.jwt, Some(&email)).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+    }
+
+    #[tokio::test]
+    async fn regenerate_recovery_codes() {
+        let service = get_service().await.expect("Failed to get service");
+
+        let user = signup(&service, "regenerate_recovery_codes").await;
+        let mut res = send_json(
+            &service,
+            "auth/recovery/codes",
+            Method::POST,
+            &RegenerateRecoveryCodesSchema {
+                password: PASSWORD.to_owned(),
+            },
+            Some(&user.jwt),
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let recovery_codes: RecoveryCodesSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+
+        for (recovery_code, status_code) in [
+            (&user.recovery_codes.unwrap()[0], StatusCode::FORBIDDEN),
+            (&recovery_codes.recovery_codes[0], StatusCode::OK),
+        ] {
+            let res = send_json(
+                &service,
+                "auth/recover_password",
+                Method::POST,
+                &RecoverPasswordSchema {
+                    username: "regenerate_recovery_codes".to_owned(),
+                    recovery_code: recovery_code.clone(),
+                },
+                None,
+            )
+            .await;
+            assert_eq!(
+                res.status_code,
+                Some(status_code),
+                "Only the new recovery codes should be valid {res:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_reset_token() {
+        let service = get_service().await.expect("Failed to get service");
+
+        let res = reset_password(&service, "invalid_reset_token").await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The invalid reset token should be rejected {res:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod oidc {
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use jsonwebtoken::{EncodingKey, Header};
+    use salvo::conn::{Acceptor, TcpListener};
+    use serde::Deserialize;
+    use xors_api::oidc::{self, OidcProvider};
+
+    const CLIENT_ID: &str = "xors";
+    const CLIENT_SECRET: &str = "mock_client_secret";
+
+    /// The user that signs in the mock provider, the test sends it as the authorization code.
+    #[derive(Serialize, Deserialize)]
+    struct MockCode {
+        sub: String,
+        email: Option<String>,
+        email_verified: bool,
+        preferred_username: String,
+        nonce: String,
+        code_challenge: String,
+    }
+
+    /// The authorization request parameters that the provider receives.
+    struct Authorization {
+        state: String,
+        nonce: String,
+        code_challenge: String,
+    }
+
+    #[handler]
+    async fn discovery(req: &mut Request, res: &mut Response) {
+        let issuer = format!("http://{}", req.header::<String>("host").unwrap());
+        res.render(Json(serde_json::json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{issuer}/authorize"),
+            "token_endpoint": format!("{issuer}/token"),
+            "jwks_uri": format!("{issuer}/jwks"),
+            "id_token_signing_alg_values_supported": ["HS256"],
+        })));
+    }
+
+    /// The token endpoint, it checks the PKCE code verifier and returns an ID token signed with the client secret.
+    #[handler]
+    async fn token(req: &mut Request, res: &mut Response) {
+        let issuer = format!("http://{}", req.header::<String>("host").unwrap());
+        let code: MockCode = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(req.form::<String>("code").await.unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        let code_verifier = req.form::<String>("code_verifier").await.unwrap();
+        if oidc::code_challenge(&code_verifier) != code.code_challenge
+            || req.form::<String>("client_secret").await.as_deref() != Some(CLIENT_SECRET)
+        {
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(Json(serde_json::json!({"error": "invalid_grant"})));
+            return;
+        }
+
+        let id_token = jsonwebtoken::encode(
+            &Header::default(),
+            &serde_json::json!({
+                "iss": issuer,
+                "aud": CLIENT_ID,
+                "exp": chrono::Utc::now().timestamp() + 60,
+                "sub": code.sub,
+                "nonce": code.nonce,
+                "email": code.email,
+                "email_verified": code.email_verified,
+                "given_name": "Mock",
+                "family_name": "User",
+                "preferred_username": code.preferred_username,
+            }),
+            &EncodingKey::from_secret(CLIENT_SECRET.as_bytes()),
+        )
+        .unwrap();
+        res.render(Json(serde_json::json!({
+            "access_token": "mock_access_token",
+            "token_type": "Bearer",
+            "id_token": id_token,
+        })));
+    }
+
+    /// Starts the mock OpenID Connect provider, and returns the service that uses it.
+    async fn get_oidc_service() -> Service {
+        get_oidc_service_with_algorithms(None).await
+    }
+
+    /// Starts the mock OpenID Connect provider, and returns the service that uses it
+    /// with the allowed ID token algorithms.
+    async fn get_oidc_service_with_algorithms(
+        id_token_algorithms: Option<Vec<jsonwebtoken::Algorithm>>,
+    ) -> Service {
+        let acceptor = TcpListener::new("127.0.0.1:0").bind().await;
+        let issuer = format!(
+            "http://{}",
+            acceptor.holdings()[0]
+                .local_addr
+                .clone()
+                .into_std()
+                .unwrap()
+        );
+        tokio::spawn(
+            Server::new(acceptor).serve(
+                Router::new()
+                    .push(Router::with_path(".well-known/openid-configuration").get(discovery))
+                    .push(Router::with_path("token").post(token)),
+            ),
+        );
+
+        std::env::set_var("XORS_API_TEST", "true");
+        xors_api::api::service(
+            get_connection().await.expect("Failed to get connection"),
+            100,
+            10,
+            AuthConfig {
+                oidc_providers: vec![OidcProvider {
+                    name: "mock".to_owned(),
+                    issuer,
+                    client_id: CLIENT_ID.to_owned(),
+                    client_secret: Some(CLIENT_SECRET.to_owned()),
+                    redirect_url: "http://localhost:8000/auth/oidc/mock/callback".to_owned(),
+                    scopes: "openid email profile".to_owned(),
+                    id_token_algorithms,
+                }],
+                ..get_auth_config()
+            },
+            get_mailer(),
+        )
+        .0
+    }
+
+    /// Starts the authorization, and returns the parameters that the provider receives.
+    async fn authorize(service: &Service) -> Authorization {
+        let mut res = send(service, "auth/oidc/mock", Method::GET, None::<&str>, vec![]).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        let authorization: OidcAuthorizationSchema = serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body");
+        let url = reqwest::Url::parse(&authorization.authorization_url)
+            .expect("The authorization URL should be valid");
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+                .unwrap_or_else(|| panic!("The authorization URL should have `{name}`"))
+        };
+        assert_eq!(param("code_challenge_method"), "S256");
+        assert_eq!(param("client_id"), CLIENT_ID);
+
+        Authorization {
+            state: param("state"),
+            nonce: param("nonce"),
+            code_challenge: param("code_challenge"),
+        }
+    }
+
+    async fn callback(service: &Service, state: &str, code: &MockCode) -> Response {
+        send(
+            service,
+            &format!(
+                "auth/oidc/mock/callback?state={state}&code={}",
+                URL_SAFE_NO_PAD.encode(serde_json::to_vec(code).unwrap())
+            ),
+            Method::GET,
+            None::<&str>,
+            vec![],
+        )
+        .await
+    }
+
+    async fn signin(
+        service: &Service,
+        sub: &str,
+        email: Option<&str>,
+        username: &str,
+    ) -> UserSigninSchema {
+        let authorization = authorize(service).await;
+        let mut res = callback(
+            service,
+            &authorization.state,
+            &MockCode {
+                sub: sub.to_owned(),
+                email: email.map(ToOwned::to_owned),
+                email_verified: true,
+                preferred_username: username.to_owned(),
+                nonce: authorization.nonce,
+                code_challenge: authorization.code_challenge,
+            },
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `200 OK` status code {res:?}"
+        );
+        match serde_json::from_str(
+            &res.take_string()
+                .await
+                .expect("Could not get the response body"),
+        )
+        .expect("Failed to parse response body")
+        {
+            SigninResponseSchema::Signin(user_signin) => *user_signin,
+            SigninResponseSchema::TotpChallenge(_) => panic!("The user has no TOTP"),
+        }
+    }
+
+    #[tokio::test]
+    async fn oidc_signin_creates_then_signs_in() {
+        let service = get_oidc_service().await;
+        let sub = Uuid::new_v4().to_string();
+
+        let user_signin = signin(&service, &sub, None, "oidc_new_user").await;
+        assert_eq!(user_signin.user.username, "oidc_new_user");
+        assert_eq!(user_signin.user.first_name, "Mock");
+        assert_eq!(user_signin.user.last_name.as_deref(), Some("User"));
+        assert!(
+            user_signin.recovery_codes.is_some(),
+            "The new user should get the recovery codes"
+        );
+
+        let second_signin = signin(&service, &sub, None, "oidc_new_user").await;
+        assert_eq!(second_signin.user.uuid, user_signin.user.uuid);
+        assert!(second_signin.recovery_codes.is_none());
+
+        // The taken username gets a suffix
+        let other_signin =
+            signin(&service, &Uuid::new_v4().to_string(), None, "oidc_new_user").await;
+        assert_ne!(other_signin.user.uuid, user_signin.user.uuid);
+        assert!(other_signin.user.username.starts_with("oidc_new_user_"));
+    }
+
+    #[tokio::test]
+    async fn oidc_links_verified_email() {
+        let service = get_oidc_service().await;
+        let conn = get_connection().await.expect("Failed to get connection");
+        let email = format!("oidc_link_{}@example.com", Uuid::new_v4().simple());
+
+        let user = db_utils::create_user(
+            &conn,
+            NewUserSchema {
+                first_name: "First".to_owned(),
+                last_name: None,
+                username: "oidc_link_user".to_owned(),
+                email: Some(email.clone()),
+                password: "fdkjhKFHDKH347(#*&".to_owned(),
+            },
+            &get_auth_config().password_hasher,
+        )
+        .await
+        .expect("Failed to create user");
+        UserEntity::update_many()
+            .col_expr(
+                UserColumn::EmailVerified,
+                sea_orm::sea_query::Expr::value(true),
+            )
+            .filter(UserColumn::Uuid.eq(user.uuid))
+            .exec(&conn)
+            .await
+            .expect("Failed to verify the email");
+
+        let user_signin = signin(
+            &service,
+            &Uuid::new_v4().to_string(),
+            Some(&email.to_uppercase()),
+            "oidc_link_other",
+        )
+        .await;
+        assert_eq!(
+            user_signin.user.uuid, user.uuid,
+            "The identity should be linked to the user with the verified email"
+        );
+        assert!(user_signin.recovery_codes.is_none());
+    }
+
+    #[tokio::test]
+    async fn oidc_rejects_invalid_callbacks() {
+        let service = get_oidc_service().await;
+
+        let res = send(
+            &service,
+            "auth/oidc/unknown",
+            Method::GET,
+            None::<&str>,
+            vec![],
+        )
+        .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+
+        let authorization = authorize(&service).await;
+        let code = MockCode {
+            sub: Uuid::new_v4().to_string(),
+            email: None,
+            email_verified: false,
+            preferred_username: "oidc_invalid".to_owned(),
+            nonce: authorization.nonce.clone(),
+            code_challenge: oidc::code_challenge("another verifier"),
+        };
+        let res = callback(&service, &authorization.state, &code).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The provider should reject the wrong code verifier {res:?}"
+        );
+        // The state can only be used one time
+        let res = callback(
+            &service,
+            &authorization.state,
+            &MockCode {
+                code_challenge: authorization.code_challenge,
+                ..code
+            },
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The used state should be rejected {res:?}"
+        );
+
+        let authorization = authorize(&service).await;
+        let res = callback(
+            &service,
+            &authorization.state,
+            &MockCode {
+                sub: Uuid::new_v4().to_string(),
+                email: None,
+                email_verified: false,
+                preferred_username: "oidc_invalid".to_owned(),
+                nonce: "another nonce".to_owned(),
+                code_challenge: authorization.code_challenge,
+            },
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The ID token with another nonce should be rejected {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn oidc_rejects_not_allowed_algorithm() {
+        // The mock provider signs the ID tokens with `HS256`
+        let service =
+            get_oidc_service_with_algorithms(Some(vec![jsonwebtoken::Algorithm::RS256])).await;
+
+        let authorization = authorize(&service).await;
+        let res = callback(
+            &service,
+            &authorization.state,
+            &MockCode {
+                sub: Uuid::new_v4().to_string(),
+                email: None,
+                email_verified: false,
+                preferred_username: "oidc_not_allowed_algorithm".to_owned(),
+                nonce: authorization.nonce,
+                code_challenge: authorization.code_challenge,
+            },
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The ID token with a not allowed algorithm should be rejected {res:?}"
+        );
+    }
 }
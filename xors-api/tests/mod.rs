@@ -33,11 +33,15 @@ use serde::Serialize;
 use std::env;
 use std::net::Ipv4Addr;
 use std::net::SocketAddrV4;
+use std::sync::Arc;
 #[allow(unused_imports)]
 use uuid::Uuid;
+use xors_api::config::AuthConfig;
 #[allow(unused_imports)]
 use xors_api::db_utils;
 use xors_api::errors::ApiResult;
+use xors_api::mailer::{FileMailer, Mailer};
+use xors_api::password::PasswordHasher;
 #[allow(unused_imports)]
 use xors_api::schemas::*;
 
@@ -62,6 +66,28 @@ where
             HeaderValue::from_static("application/json"),
         );
     };
+    call(service, req, headers).await
+}
+
+/// Function to send a request with a raw body to the API.
+pub async fn send_raw(
+    service: &Service,
+    path: &str,
+    method: Method,
+    body: Vec<u8>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+) -> Response {
+    let req = RequestBuilder::new(format!("{}/{}", API_URL, path), method)
+        .bytes(body)
+        .build();
+    call(service, req, headers).await
+}
+
+async fn call(
+    service: &Service,
+    mut req: Request,
+    headers: Vec<(HeaderName, HeaderValue)>,
+) -> Response {
     for (header_name, header_value) in headers {
         req.headers_mut().insert(header_name, header_value);
     }
@@ -84,10 +110,93 @@ pub fn get_secret_key() -> String {
     env::var("XORS_API_SECRET_KEY").expect("`XORS_API_SECRET_KEY` environment variable must be set")
 }
 
+/// Returns the authentication configuration, with the default lifetimes
+/// and a cheap password hasher to not slow down the tests.
+///
+/// The tests of the tokens expiration build their own configuration with short lifetimes.
+pub fn get_auth_config() -> AuthConfig {
+    AuthConfig {
+        password_hasher: PasswordHasher::Argon2id {
+            memory_cost: 8,
+            time_cost: 1,
+            parallelism: 1,
+        },
+        ..AuthConfig::new(get_secret_key())
+    }
+}
+
+/// The password of the players that are created by [`create_player`].
+pub const PLAYER_PASSWORD: &str = "kdfkl(#0()$fkLKJF";
+
+/// Creates a player with the given names, his password is [`PLAYER_PASSWORD`].
+pub async fn create_player_named(
+    conn: &sea_orm::DatabaseConnection,
+    username: &str,
+    first_name: &str,
+    last_name: Option<&str>,
+) -> UserSchema {
+    db_utils::create_user(
+        conn,
+        NewUserSchema {
+            first_name: first_name.to_owned(),
+            last_name: last_name.map(ToOwned::to_owned),
+            username: username.to_owned(),
+            email: None,
+            password: PLAYER_PASSWORD.to_owned(),
+        },
+        &get_auth_config().password_hasher,
+    )
+    .await
+    .expect("Failed to create player")
+}
+
+/// Creates a player named `Player`, his password is [`PLAYER_PASSWORD`].
+pub async fn create_player(conn: &sea_orm::DatabaseConnection, username: &str) -> UserSchema {
+    create_player_named(conn, username, "Player", None).await
+}
+
+/// Signin the player in a new session.
+pub async fn signin_player(
+    conn: &sea_orm::DatabaseConnection,
+    player: &UserSchema,
+) -> UserSigninSchema {
+    db_utils::signin_user(
+        conn,
+        player.clone(),
+        &get_auth_config(),
+        NewSessionSchema::default(),
+    )
+    .await
+    .expect("Failed to signin player")
+}
+
+/// Returns the directory that the tests mails are written to.
+pub fn get_mail_dir() -> std::path::PathBuf {
+    env::temp_dir().join("xors_api_mails")
+}
+
+/// Returns the mailer, it writes the mails to the tests mails directory.
+pub fn get_mailer() -> Arc<dyn Mailer> {
+    std::fs::create_dir_all(get_mail_dir()).expect("Failed to create the mails directory");
+    Arc::new(FileMailer::new(
+        get_mail_dir(),
+        "XORS <noreply@localhost>"
+            .parse()
+            .expect("Is valid mailbox"),
+    ))
+}
+
 /// Returns the service.
 pub async fn get_service() -> ApiResult<Service> {
     // Set the test environment variable to true
     std::env::set_var("XORS_API_TEST", "true");
 
-    Ok(xors_api::api::service(get_connection().await?, 100, 10, get_secret_key()).0)
+    Ok(xors_api::api::service(
+        get_connection().await?,
+        100,
+        10,
+        get_auth_config(),
+        get_mailer(),
+    )
+    .0)
 }
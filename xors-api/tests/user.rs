@@ -24,21 +24,25 @@ mod get_me {
     async fn get_me_success() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_get_me".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -71,21 +75,25 @@ mod get_me {
     async fn get_me_with_refresh_token() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_get_me_with_refresh_token".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -154,21 +162,25 @@ mod get_user {
     async fn get_user_success() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_get_user_success".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -190,7 +202,15 @@ mod get_user {
         let res_json: UserSchema =
             serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
                 .expect("Failed to parse body");
-        assert_eq!(res_json, user.user, "User should be the same");
+        assert_eq!(
+            res_json,
+            UserSchema {
+                email: None,
+                verified: None,
+                ..user.user
+            },
+            "User should be the same, without the owner only fields"
+        );
     }
 
     #[tokio::test]
@@ -250,26 +270,31 @@ mod get_user {
 #[cfg(test)]
 mod update_user {
     use super::*;
+    use base64::Engine;
 
     #[tokio::test]
     async fn update_user_first_name() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_update_user_first_name".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -312,21 +337,25 @@ mod update_user {
     async fn update_user_last_name() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_update_user_last_name".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -370,21 +399,25 @@ mod update_user {
     async fn update_user_with_invalid_first_name() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_update_user_with_invalid_first_name".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -482,21 +515,25 @@ mod update_user {
     async fn update_user_with_invalid_last_name() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_update_user_with_invalid_last_name".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -592,21 +629,25 @@ mod update_user {
     async fn update_user_with_null_first_name() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_update_user_with_null_first_name".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -689,21 +730,25 @@ mod update_user {
     async fn update_user_with_refresh_token() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_update_user_with_refresh_token".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -730,6 +775,179 @@ mod update_user {
             "The response should have a `BAD_REQUEST` status code {res:?}"
         );
     }
+
+    /// Returns a solid color image encoded in the given format, base64 encoded.
+    fn encode_image(width: u32, height: u32, format: image::ImageOutputFormat) -> String {
+        let mut image_bytes = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            width,
+            height,
+            image::Rgb([200, 30, 60]),
+        ))
+        .write_to(&mut std::io::Cursor::new(&mut image_bytes), format)
+        .expect("Failed to encode the image");
+        xors_api::BASE_64_ENGINE.encode(image_bytes)
+    }
+
+    async fn update_profile_image(
+        conn: &sea_orm::DatabaseConnection,
+        service: &Service,
+        username: &str,
+        profile_image: String,
+    ) -> (UserSigninSchema, Response) {
+        let user = db_utils::signin_user(
+            conn,
+            db_utils::create_user(
+                conn,
+                NewUserSchema {
+                    first_name: "First".to_owned(),
+                    last_name: None,
+                    username: username.to_owned(),
+                    email: None,
+                    password: "kdfkl(#0()$fkLKJF".to_owned(),
+                },
+                &get_auth_config().password_hasher,
+            )
+            .await
+            .expect("Failed to create user"),
+            &get_auth_config(),
+            NewSessionSchema::default(),
+        )
+        .await
+        .expect("Failed to signin user");
+
+        let res = send(
+            service,
+            "user",
+            Method::PUT,
+            Some(&UpdateUserSchema {
+                first_name: Some("First".to_owned()),
+                last_name: None,
+                profile_image: Some(profile_image),
+            }),
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        (user, res)
+    }
+
+    #[tokio::test]
+    async fn update_user_with_jpeg_profile_image() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let (user, res) = update_profile_image(
+            &conn,
+            &service,
+            "update_user_jpeg_profile_image",
+            encode_image(300, 200, image::ImageOutputFormat::Jpeg(90)),
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+
+        // The image is center-cropped, resized and stored as png
+        let image_path = format!("./profile_images/{}", user.user.uuid);
+        let stored = std::fs::read(&image_path).expect("Failed to read the profile image");
+        std::fs::remove_file(&image_path).expect("Failed to remove the profile image");
+        assert_eq!(
+            image::guess_format(&stored).expect("The image should be valid"),
+            image::ImageFormat::Png
+        );
+        let stored = image::load_from_memory(&stored).expect("The image should be valid");
+        assert_eq!((stored.width(), stored.height()), (128, 128));
+    }
+
+    #[tokio::test]
+    async fn update_user_with_large_profile_image() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        // A noisy image, so its base64 is bigger than the global JSON body limit
+        let mut seed = 7_u32;
+        let image = image::RgbImage::from_fn(256, 256, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            image::Rgb(seed.to_be_bytes()[..3].try_into().unwrap())
+        });
+        let mut image_bytes = Vec::new();
+        image::DynamicImage::ImageRgb8(image)
+            .write_to(
+                &mut std::io::Cursor::new(&mut image_bytes),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("Failed to encode the image");
+        let profile_image = xors_api::BASE_64_ENGINE.encode(image_bytes);
+        assert!(profile_image.len() > 64 * 1024);
+
+        let (user, res) = update_profile_image(
+            &conn,
+            &service,
+            "update_user_large_profile_image",
+            profile_image,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        std::fs::remove_file(format!("./profile_images/{}", user.user.uuid))
+            .expect("Failed to remove the profile image");
+
+        let (_, res) = update_profile_image(
+            &conn,
+            &service,
+            "update_user_too_large_profile_image",
+            "A".repeat(7_000_000),
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::PAYLOAD_TOO_LARGE),
+            "The response should have a `PAYLOAD_TOO_LARGE` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn update_user_with_invalid_profile_image() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        for (username, profile_image, message) in [
+            (
+                "update_user_too_wide_profile_image",
+                encode_image(5000, 1, image::ImageOutputFormat::Png),
+                "The image's dimensions must be at most 4096x4096 pixels",
+            ),
+            (
+                "update_user_gif_profile_image",
+                xors_api::BASE_64_ENGINE.encode(b"GIF89a\x01\x00\x01\x00\x00\x00\x00;"),
+                "The image's format must be png, jpeg or webp",
+            ),
+            (
+                "update_user_corrupted_profile_image",
+                xors_api::BASE_64_ENGINE.encode(b"\x89PNG\r\n\x1a\ncorrupted"),
+                "Invalid image",
+            ),
+        ] {
+            let (_, mut res) = update_profile_image(&conn, &service, username, profile_image).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::BAD_REQUEST),
+                "The response should have a `BAD_REQUEST` status code {res:?}"
+            );
+            let res_json: serde_json::Value =
+                serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                    .expect("Failed to parse body");
+            assert_eq!(res_json["message"], message);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -740,21 +958,25 @@ mod delete_user {
     async fn delete_user_success() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_delete_user_success".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -780,32 +1002,291 @@ mod delete_user {
         );
 
         assert!(
-            xors_api::db_utils::get_user(&conn, user.user.uuid)
+            xors_api::db_utils::get_active_user(&conn, user.user.uuid)
                 .await
                 .is_err(),
             "User should be deleted"
         );
     }
 
+    #[tokio::test]
+    async fn delete_user_and_restore() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let auth_config = get_auth_config();
+
+        const PASSWORD: &str = "kdfkl(#0()$fkLKJF";
+        let user = db_utils::signin_user(
+            &conn,
+            db_utils::create_user(
+                &conn,
+                NewUserSchema {
+                    first_name: "First".to_string(),
+                    last_name: None,
+                    username: "delete_user_and_restore".to_string(),
+                    email: None,
+                    password: PASSWORD.to_owned(),
+                },
+                &get_auth_config().password_hasher,
+            )
+            .await
+            .expect("Failed to create user"),
+            &auth_config,
+            NewSessionSchema::default(),
+        )
+        .await
+        .expect("Failed to signin user");
+
+        let res = send(
+            &service,
+            "user",
+            Method::DELETE,
+            Some(&DeleteUserSchema {
+                password: PASSWORD.to_owned(),
+            }),
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+
+        // The deleted user is hidden from the other users
+        let res = send(
+            &service,
+            &format!("user?uuid={}", user.user.uuid),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::NOT_FOUND),
+            "The response should have a `NOT_FOUND` status code {res:?}"
+        );
+
+        // The deleted user can signin to restore his account
+        let mut res = send(
+            &service,
+            "auth/signin",
+            Method::POST,
+            Some(&SigninSchema {
+                username: "delete_user_and_restore".to_owned(),
+                password: PASSWORD.to_owned(),
+            }),
+            vec![],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let signin: UserSigninSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert!(
+            signin.user.deleted_at.is_some(),
+            "The signin should return the deletion date"
+        );
+
+        // The deleted account can only be restored, exported or logged out
+        let res = send(
+            &service,
+            "user/me",
+            Method::GET,
+            None::<&()>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", signin.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The response should have a `FORBIDDEN` status code {res:?}"
+        );
+
+        let mut res = send(
+            &service,
+            "user/restore",
+            Method::POST,
+            None::<&()>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", signin.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let restored: UserSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert_eq!(restored.deleted_at, None, "The account should be restored");
+        assert!(
+            db_utils::get_active_user(&conn, user.user.uuid)
+                .await
+                .is_ok(),
+            "The user should be visible again"
+        );
+
+        let res = send(
+            &service,
+            "user/restore",
+            Method::POST,
+            None::<&()>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", signin.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The response should have a `BAD_REQUEST` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn purge_deleted_user() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let mut players = Vec::new();
+        for username in ["purge_deleted_user", "purge_deleted_user_other"] {
+            players.push(
+                db_utils::create_user(
+                    &conn,
+                    NewUserSchema {
+                        first_name: "Player".to_owned(),
+                        last_name: None,
+                        username: username.to_owned(),
+                        email: None,
+                        password: "kdfkl(#0()$fkLKJF".to_owned(),
+                    },
+                    &get_auth_config().password_hasher,
+                )
+                .await
+                .expect("Failed to create user"),
+            );
+        }
+        let game = db_utils::create_game(&conn, players[0].uuid, players[1].uuid, 10)
+            .await
+            .expect("Failed to create game");
+        db_utils::end_game(&conn, game.uuid.as_ref(), None, &GameOverReason::Draw)
+            .await
+            .expect("Failed to end game");
+
+        // The account is deleted before a long grace period, the other tests deletions are not purged
+        let mut user = db_utils::get_user(&conn, players[0].uuid)
+            .await
+            .expect("Failed to get user");
+        user.deleted_at = Set(Some(chrono::Utc::now().naive_utc() - Duration::days(1000)));
+        user.save(&conn).await.expect("Failed to delete user");
+
+        let purged = db_utils::purge_deleted_users(&conn, Duration::days(999))
+            .await
+            .expect("Failed to purge the deleted users");
+        assert_eq!(purged, 1, "The deleted user should be purged");
+        let user = db_utils::get_user(&conn, players[0].uuid)
+            .await
+            .expect("The purged user should be kept anonymized");
+        assert!(
+            user.purged_at.as_ref().is_some(),
+            "The user should be purged"
+        );
+        let placeholder_name = format!("deleted-{}", &players[0].uuid.simple().to_string()[..16]);
+        assert_eq!(user.username.as_ref(), &placeholder_name);
+        assert_eq!(user.first_name.as_ref(), &placeholder_name);
+        assert!(user.password_hash.as_ref().is_empty() && user.email.as_ref().is_none());
+        assert_eq!(
+            db_utils::purge_deleted_users(&conn, Duration::days(999))
+                .await
+                .expect("Failed to purge the deleted users"),
+            0,
+            "The purged user should not be purged again"
+        );
+
+        // The purged username is released, the games don't show it anymore
+        let res = send(
+            &service,
+            "auth/signup",
+            Method::POST,
+            Some(&NewUserSchema {
+                first_name: "Player".to_owned(),
+                last_name: None,
+                username: "Purge_Deleted_User".to_owned(),
+                email: None,
+                password: "kdfkl(#0()$fkLKJF".to_owned(),
+            }),
+            vec![],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+
+        let mut res = send(
+            &service,
+            &format!("game/{}", game.uuid.as_ref()),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let res_game: GameSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert_eq!(res_game.x_player.uuid, Uuid::nil());
+        assert_eq!(
+            res_game.x_player.username, placeholder_name,
+            "The game should keep the frozen placeholder name"
+        );
+        assert_eq!(res_game.o_player.uuid, players[1].uuid);
+    }
+
     #[tokio::test]
     async fn delete_user_with_invalid_password() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_delete_user_with_invalid_password".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -873,21 +1354,25 @@ mod delete_user {
     async fn delete_user_with_refresh_token() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         let user = xors_api::db_utils::signin_user(
+            &conn,
             xors_api::db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_delete_user_with_refresh_token".to_string(),
+                    email: None,
                     password: "kdfkl(#0()$fkLKJF".to_string(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -922,24 +1407,28 @@ mod reset_password {
     async fn reset_password_success() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         const OLD_PASSWORD: &str = "kdfkl(#0()$fkLKJF";
         const NEW_PASSWORD: &str = "kdfkl(#0()$fkLKJf";
 
         let user = db_utils::signin_user(
+            &conn,
             db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "reset_password_success".to_string(),
+                    email: None,
                     password: OLD_PASSWORD.to_owned(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -969,7 +1458,7 @@ mod reset_password {
             .await
             .expect("Failed to get user");
         assert!(
-            bcrypt::verify(NEW_PASSWORD, user_with_new_password.password_hash.as_ref())
+            PasswordHasher::verify(NEW_PASSWORD, user_with_new_password.password_hash.as_ref())
                 .expect("Failed to verify password"),
             "Password should be updated"
         );
@@ -979,24 +1468,28 @@ mod reset_password {
     async fn reset_password_with_invalid_old_password() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         const OLD_PASSWORD: &str = "kdfkl(#0()$fkLKJF";
         const NEW_PASSWORD: &str = "kdfkl(#0()$fkLKJf";
 
         let user = db_utils::signin_user(
+            &conn,
             db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "reset_password_with_invalid_old".to_string(),
+                    email: None,
                     password: OLD_PASSWORD.to_owned(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -1076,23 +1569,27 @@ mod reset_password {
     async fn reset_password_with_same_password() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         const OLD_PASSWORD: &str = "kdfkl(#0()$fkLKJF";
 
         let user = db_utils::signin_user(
+            &conn,
             db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "username_reset_password_with_same_password".to_string(),
+                    email: None,
                     password: OLD_PASSWORD.to_owned(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -1123,23 +1620,27 @@ mod reset_password {
     async fn reset_password_with_invalid_new_password() {
         let service = get_service().await.expect("Failed to get service");
         let conn = get_connection().await.expect("Failed to get connection");
-        let secret_key = get_secret_key();
+        let auth_config = get_auth_config();
 
         const OLD_PASSWORD: &str = "kdfkl(#0()$fkLKJF";
 
         let user = db_utils::signin_user(
+            &conn,
             db_utils::create_user(
                 &conn,
                 NewUserSchema {
                     first_name: "First".to_string(),
                     last_name: Some("Last".to_string()),
                     username: "reset_password_with_invalid_new".to_string(),
+                    email: None,
                     password: OLD_PASSWORD.to_owned(),
                 },
+                &get_auth_config().password_hasher,
             )
             .await
             .expect("Failed to create user"),
-            &secret_key,
+            &auth_config,
+            NewSessionSchema::default(),
         )
         .await
         .expect("Failed to signin user");
@@ -1208,3 +1709,1321 @@ mod reset_password {
         );
     }
 }
+
+#[cfg(test)]
+mod update_username {
+    use super::*;
+
+    async fn update_username(
+        service: &Service,
+        jwt: &str,
+        username: &str,
+        password: &str,
+    ) -> Response {
+        send(
+            service,
+            "user/username",
+            Method::PUT,
+            Some(&UpdateUsernameSchema {
+                username: username.to_owned(),
+                password: password.to_owned(),
+            }),
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {jwt}")).unwrap(),
+            )],
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn update_username_success() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(&conn, &create_player(&conn, "update_username_old").await).await;
+
+        let mut res =
+            update_username(&service, &user.jwt, "update_username_new", PLAYER_PASSWORD).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let res_json: UserSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert_eq!(res_json.username, "update_username_new");
+
+        // The cooldown prevents another change
+        let res = update_username(
+            &service,
+            &user.jwt,
+            "update_username_other",
+            PLAYER_PASSWORD,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The response should have a `FORBIDDEN` status code {res:?}"
+        );
+        assert!(
+            res.headers().contains_key(header::RETRY_AFTER),
+            "The response should have a `Retry-After` header {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn update_username_reserved() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(&conn, &create_player(&conn, "reserved_username_old").await).await;
+        let other_user = signin_player(
+            &conn,
+            &create_player(&conn, "reserved_username_other").await,
+        )
+        .await;
+
+        let res = update_username(
+            &service,
+            &user.jwt,
+            "reserved_username_new",
+            PLAYER_PASSWORD,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+
+        let res = update_username(
+            &service,
+            &other_user.jwt,
+            "reserved_username_old",
+            PLAYER_PASSWORD,
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The response should have a `BAD_REQUEST` status code {res:?}"
+        );
+
+        let res = send(
+            &service,
+            "auth/signup",
+            Method::POST,
+            Some(&NewUserSchema {
+                first_name: "Player".to_owned(),
+                last_name: None,
+                username: "reserved_username_old".to_owned(),
+                email: None,
+                password: PLAYER_PASSWORD.to_owned(),
+            }),
+            vec![],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The response should have a `BAD_REQUEST` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn update_username_with_invalid_password() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(
+            &conn,
+            &create_player(&conn, "username_invalid_password").await,
+        )
+        .await;
+
+        let res = update_username(
+            &service,
+            &user.jwt,
+            "username_invalid_password_new",
+            "invalid_password",
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The response should have a `BAD_REQUEST` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn update_username_with_invalid_username() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(
+            &conn,
+            &create_player(&conn, "username_invalid_username").await,
+        )
+        .await;
+
+        for username in ["ab", "1username", "user name", "username_invalid_username"] {
+            let res = update_username(&service, &user.jwt, username, PLAYER_PASSWORD).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::BAD_REQUEST),
+                "The response should have a `BAD_REQUEST` status code for `{username}` {res:?}"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod export {
+    use super::*;
+
+    #[tokio::test]
+    async fn export_user_data_success() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let player = create_player(&conn, "export_user_data").await;
+        let other = create_player(&conn, "export_user_data_other").await;
+
+        // More games than a page, so the games are written in multiple pages
+        let rounds_result = "XO- XXXOO----:03142,XX-OOO--X:031485,XOXXOOOXX:012435768";
+        let mut games = Vec::new();
+        for _ in 0..150 {
+            let mut game = db_utils::create_game(&conn, player.uuid, other.uuid, 10)
+                .await
+                .expect("Failed to create game");
+            game.rounds_result = Set(rounds_result.to_owned());
+            let game = game.save(&conn).await.expect("Failed to save game");
+            games.push(*game.uuid.as_ref());
+        }
+
+        // Signin after creating the games, so the short-lived test token doesn't expire
+        let user = signin_player(&conn, &player).await;
+
+        let mut res = send(
+            &service,
+            "user/export",
+            Method::GET,
+            None::<&()>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        assert!(
+            res.headers()
+                .get(header::CONTENT_DISPOSITION)
+                .is_some_and(|value| value.to_str().unwrap().starts_with("attachment")),
+            "The export should be an attachment {res:?}"
+        );
+        let export: UserExportSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+
+        assert_eq!(export.user, user.user);
+        assert_eq!(export.profile_image, None);
+        assert_eq!(export.sessions.len(), 1);
+        assert!(
+            export.sessions[0].current,
+            "The session should be the current"
+        );
+        assert_eq!(
+            export
+                .games
+                .iter()
+                .map(|game| game.uuid)
+                .collect::<Vec<_>>(),
+            games,
+            "The export should contains all the games, oldest first"
+        );
+        assert!(export
+            .games
+            .iter()
+            .all(|game| game.rounds_results.boards().len() == 3
+                && game.x_player == user.user.uuid
+                && game.o_player == other.uuid));
+    }
+
+    #[tokio::test]
+    async fn export_user_data_without_auth() {
+        let service = get_service().await.expect("Failed to get service");
+
+        let res = send(&service, "user/export", Method::GET, None::<&()>, vec![]).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The response should have a `UNAUTHORIZED` status code {res:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod versus {
+    use super::*;
+
+    #[tokio::test]
+    async fn versus_success() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let user = create_player(&conn, "versus_success_user").await;
+        let other = create_player(&conn, "versus_success_other").await;
+        let stranger = create_player(&conn, "versus_success_stranger").await;
+
+        // X won the first round, O the second, and the third is a draw.
+        let rounds_result = "XO- XXXOO----:03142,XX-OOO--X:031485,XOXXOOOXX:012435768";
+        let mut games = Vec::new();
+        for (x_player, o_player, winner) in [
+            (&user, &other, Some(user.uuid)),
+            (&other, &user, Some(other.uuid)),
+            (&user, &other, None),
+            (&user, &stranger, Some(user.uuid)),
+        ] {
+            let mut game = db_utils::create_game(&conn, x_player.uuid, o_player.uuid, 10)
+                .await
+                .expect("Failed to create game");
+            game.rounds_result = Set(rounds_result.to_owned());
+            let game = game.save(&conn).await.expect("Failed to save game");
+            db_utils::end_game(
+                &conn,
+                game.uuid.as_ref(),
+                winner,
+                &if winner.is_some() {
+                    GameOverReason::PlayerWon
+                } else {
+                    GameOverReason::Draw
+                },
+            )
+            .await
+            .expect("Failed to end game");
+            games.push(*game.uuid.as_ref());
+        }
+
+        let mut res = send(
+            &service,
+            &format!("user/{}/versus/{}?last=2", user.uuid, other.uuid),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await;
+
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let versus: VersusSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert_eq!(
+            versus.games,
+            ResultsSchema {
+                wins: 1,
+                losses: 1,
+                draws: 1
+            }
+        );
+        assert_eq!(
+            versus.rounds,
+            ResultsSchema {
+                wins: 3,
+                losses: 3,
+                draws: 3
+            }
+        );
+        assert_eq!(versus.latest_games, vec![games[2], games[1]]);
+    }
+
+    #[tokio::test]
+    async fn versus_same_user() {
+        let service = get_service().await.expect("Failed to get service");
+        let uuid = Uuid::new_v4();
+
+        let res = send(
+            &service,
+            &format!("user/{uuid}/versus/{uuid}"),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await;
+
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The response should have a `BAD_REQUEST` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn versus_user_not_found() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "versus_user_not_found").await;
+
+        let res = send(
+            &service,
+            &format!("user/{}/versus/{}", user.uuid, Uuid::new_v4()),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await;
+
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::NOT_FOUND),
+            "The response should have a `NOT_FOUND` status code {res:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod stats {
+    use super::*;
+
+    async fn get_stats(service: &Service, uuid: Uuid) -> Response {
+        send(
+            service,
+            &format!("user/{uuid}/stats"),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn stats_success() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let user = create_player(&conn, "stats_success_user").await;
+        let other = create_player(&conn, "stats_success_other").await;
+
+        // X won the first round, O the second, and the third is a draw.
+        let rounds_result = "XO- XXXOO----:03142,XX-OOO--X:031485,XOXXOOOXX:012435768";
+        for (x_player, o_player, winner, reason, auto_plays) in [
+            (
+                &user,
+                &other,
+                Some(user.uuid),
+                GameOverReason::PlayerWon,
+                (2, 7),
+            ),
+            (
+                &user,
+                &other,
+                Some(user.uuid),
+                GameOverReason::PlayerWon,
+                (0, 0),
+            ),
+            (
+                &other,
+                &user,
+                Some(other.uuid),
+                GameOverReason::PlayerDisconnected,
+                (0, 0),
+            ),
+            (&other, &user, None, GameOverReason::Draw, (0, 3)),
+            (
+                &user,
+                &other,
+                Some(user.uuid),
+                GameOverReason::PlayerWon,
+                (0, 0),
+            ),
+        ] {
+            let mut game = db_utils::create_game(&conn, x_player.uuid, o_player.uuid, 10)
+                .await
+                .expect("Failed to create game");
+            game.rounds_result = Set(rounds_result.to_owned());
+            game.x_auto_plays = Set(auto_plays.0);
+            game.o_auto_plays = Set(auto_plays.1);
+            let game = game.save(&conn).await.expect("Failed to save game");
+            db_utils::end_game(&conn, game.uuid.as_ref(), winner, &reason)
+                .await
+                .expect("Failed to end game");
+        }
+
+        let mut res = get_stats(&service, user.uuid).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let stats: UserStatsSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+
+        assert_eq!(stats.user, user.uuid);
+        assert_eq!(
+            stats.as_x,
+            SymbolStatsSchema {
+                games: ResultsSchema {
+                    wins: 3,
+                    losses: 0,
+                    draws: 0
+                },
+                win_rate: Some(1.0)
+            }
+        );
+        assert_eq!(
+            stats.as_o,
+            SymbolStatsSchema {
+                games: ResultsSchema {
+                    wins: 0,
+                    losses: 1,
+                    draws: 1
+                },
+                win_rate: Some(0.0)
+            }
+        );
+        assert_eq!(
+            stats.rounds,
+            ResultsSchema {
+                wins: 5,
+                losses: 5,
+                draws: 5
+            }
+        );
+        assert_eq!(stats.most_common_opening, Some(0));
+        assert_eq!(stats.auto_played_moves, 5);
+        assert_eq!(stats.disconnect_losses, 1);
+        assert_eq!(stats.current_win_streak, 1);
+        assert_eq!(stats.best_win_streak, 2);
+    }
+
+    #[tokio::test]
+    async fn stats_without_games() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = create_player(&conn, "stats_without_games").await;
+
+        let mut res = get_stats(&service, user.uuid).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let stats: UserStatsSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+
+        assert_eq!(stats.as_x.win_rate, None);
+        assert_eq!(stats.as_o.win_rate, None);
+        assert_eq!(stats.rounds, ResultsSchema::default());
+        assert_eq!(stats.most_common_opening, None);
+        assert_eq!(stats.best_win_streak, 0);
+    }
+
+    #[tokio::test]
+    async fn stats_user_not_found() {
+        let service = get_service().await.expect("Failed to get service");
+
+        let res = get_stats(&service, Uuid::new_v4()).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::NOT_FOUND),
+            "The response should have a `NOT_FOUND` status code {res:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod privacy {
+    use super::*;
+
+    async fn update_privacy(service: &Service, jwt: &str, settings: PrivacySettingsSchema) {
+        let mut res = send(
+            service,
+            "user/privacy",
+            Method::PUT,
+            Some(&settings),
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {jwt}")).unwrap(),
+            )],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let res_json: PrivacySettingsSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert_eq!(res_json, settings);
+    }
+
+    async fn get(service: &Service, path: &str, jwt: Option<&str>) -> Response {
+        send(
+            service,
+            path,
+            Method::GET,
+            None::<&()>,
+            jwt.map(|jwt| {
+                (
+                    header::AUTHORIZATION,
+                    HeaderValue::from_str(&format!("Bearer {jwt}")).unwrap(),
+                )
+            })
+            .into_iter()
+            .collect(),
+        )
+        .await
+    }
+
+    async fn create_ended_game(
+        conn: &sea_orm::DatabaseConnection,
+        x_player: Uuid,
+        o_player: Uuid,
+    ) -> Uuid {
+        let mut game = db_utils::create_game(conn, x_player, o_player, 10)
+            .await
+            .expect("Failed to create game");
+        game.rounds_result =
+            Set("XO- XXXOO----:03142,XX-OOO--X:031485,XOXXOOOXX:012435768".to_owned());
+        let game = game.save(conn).await.expect("Failed to save game");
+        db_utils::end_game(
+            conn,
+            game.uuid.as_ref(),
+            Some(x_player),
+            &GameOverReason::PlayerWon,
+        )
+        .await
+        .expect("Failed to end game");
+        *game.uuid.as_ref()
+    }
+
+    #[tokio::test]
+    async fn privacy_default_settings() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(&conn, &create_player(&conn, "privacy_default").await).await;
+
+        let mut res = get(&service, "user/privacy", Some(&user.jwt)).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let res_json: PrivacySettingsSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert_eq!(res_json, PrivacySettingsSchema::default());
+
+        let res = get(&service, "user/privacy", None).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::UNAUTHORIZED),
+            "The response should have a `UNAUTHORIZED` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn privacy_hide_real_name() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(
+            &conn,
+            &create_player_named(&conn, "privacy_hide_real_name", "Player", Some("Last")).await,
+        )
+        .await;
+        let other = signin_player(
+            &conn,
+            &create_player(&conn, "privacy_hide_real_name_other").await,
+        )
+        .await;
+
+        update_privacy(
+            &service,
+            &user.jwt,
+            PrivacySettingsSchema {
+                hide_real_name: true,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let path = format!("user?uuid={}", user.user.uuid);
+        for (jwt, first_name, last_name) in [
+            (None, "privacy_hide_real_name", None),
+            (Some(other.jwt.as_str()), "privacy_hide_real_name", None),
+            (Some(user.jwt.as_str()), "Player", Some("Last")),
+        ] {
+            let mut res = get(&service, &path, jwt).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::OK),
+                "The response should have a `OK` status code {res:?}"
+            );
+            let res_json: UserSchema =
+                serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                    .expect("Failed to parse body");
+            assert_eq!(res_json.first_name, first_name);
+            assert_eq!(res_json.last_name.as_deref(), last_name);
+        }
+    }
+
+    #[tokio::test]
+    async fn privacy_hide_game_history() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(
+            &conn,
+            &create_player(&conn, "privacy_hide_game_history").await,
+        )
+        .await;
+        let other = signin_player(
+            &conn,
+            &create_player(&conn, "privacy_hide_game_history_other").await,
+        )
+        .await;
+        let game = create_ended_game(&conn, user.user.uuid, other.user.uuid).await;
+
+        update_privacy(
+            &service,
+            &user.jwt,
+            PrivacySettingsSchema {
+                hide_game_history: true,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let mut res = get(&service, &format!("user?uuid={}", user.user.uuid), None).await;
+        let res_json: UserSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert!(res_json.latest_games.is_empty());
+        assert_eq!(
+            (res_json.wins, res_json.losts, res_json.draw),
+            (0, 0, 0),
+            "The statistics should be hidden"
+        );
+        let stored = db_utils::get_user(&conn, user.user.uuid)
+            .await
+            .expect("Failed to get user");
+        assert_eq!(
+            *stored.wins.as_ref(),
+            1,
+            "The user should have won the game"
+        );
+
+        for path in [
+            format!("games?player={}", user.user.uuid),
+            format!("user/{}/stats", user.user.uuid),
+            format!("user/{}/versus/{}", other.user.uuid, user.user.uuid),
+        ] {
+            let res = get(&service, &path, Some(&other.jwt)).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::FORBIDDEN),
+                "The response should have a `FORBIDDEN` status code {res:?}"
+            );
+        }
+
+        // The game is hidden from the other player, but not from the user
+        let other_jwt = signin_player(&conn, &other.user).await.jwt;
+        let res = get(&service, &format!("game/{game}"), Some(&other_jwt)).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::NOT_FOUND),
+            "The response should have a `NOT_FOUND` status code {res:?}"
+        );
+        let mut res = get(
+            &service,
+            &format!("games?player={}", other.user.uuid),
+            Some(&other_jwt),
+        )
+        .await;
+        let page: GamesPageSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert!(page.games.is_empty());
+
+        let user_jwt = signin_player(&conn, &user.user).await.jwt;
+        let res = get(&service, &format!("game/{game}"), Some(&user_jwt)).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        let mut res = get(
+            &service,
+            &format!("games?player={}", user.user.uuid),
+            Some(&user_jwt),
+        )
+        .await;
+        let page: GamesPageSchema =
+            serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                .expect("Failed to parse body");
+        assert_eq!(
+            page.games.iter().map(|g| g.uuid).collect::<Vec<_>>(),
+            vec![game]
+        );
+    }
+
+    #[tokio::test]
+    async fn privacy_friends_only() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(&conn, &create_player(&conn, "privacy_friends_only").await).await;
+
+        update_privacy(
+            &service,
+            &user.jwt,
+            PrivacySettingsSchema {
+                friends_only: true,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let path = format!("user?uuid={}", user.user.uuid);
+        let res = get(&service, &path, None).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::FORBIDDEN),
+            "The response should have a `FORBIDDEN` status code {res:?}"
+        );
+        let res = get(&service, &path, Some(&user.jwt)).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod search {
+    use super::*;
+
+    async fn search(service: &Service, query: &str) -> Response {
+        send(
+            service,
+            &format!("users/search?{query}"),
+            Method::GET,
+            None::<&()>,
+            vec![],
+        )
+        .await
+    }
+
+    async fn search_page(service: &Service, query: &str) -> UsersPageSchema {
+        let mut res = search(service, query).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+            .expect("Failed to parse body")
+    }
+
+    #[tokio::test]
+    async fn search_users_success() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        create_player_named(&conn, "srchzq_two", "Player", None).await;
+        create_player_named(&conn, "srchzq_one", "Player", None).await;
+        create_player_named(&conn, "namematch_srch", "Player", Some("Srchzq")).await;
+        create_player_named(&conn, "srchzx_q", "Player", None).await;
+        let hidden_name = create_player_named(&conn, "hiddenname_srch", "Srchzq", None).await;
+        PrivacySettingActiveModel {
+            user_uuid: Set(hidden_name.uuid),
+            hide_real_name: Set(true),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .expect("Failed to insert privacy settings");
+        let private = create_player_named(&conn, "srchzq_private", "Player", None).await;
+        PrivacySettingActiveModel {
+            user_uuid: Set(private.uuid),
+            friends_only: Set(true),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .expect("Failed to insert privacy settings");
+        let deleted = create_player_named(&conn, "srchzq_deleted", "Player", None).await;
+        let mut deleted = db_utils::get_user(&conn, deleted.uuid)
+            .await
+            .expect("Failed to get user");
+        deleted.deleted_at = Set(Some(chrono::Utc::now().naive_utc()));
+        deleted.save(&conn).await.expect("Failed to delete user");
+
+        let page = search_page(&service, "q=SrchZQ&limit=2").await;
+        assert_eq!(
+            page.users
+                .iter()
+                .map(|user| user.username.as_str())
+                .collect::<Vec<_>>(),
+            vec!["srchzq_one", "srchzq_two"]
+        );
+        let next_cursor = page.next_cursor.expect("There should be a next page");
+
+        let page = search_page(&service, &format!("q=SrchZQ&limit=2&cursor={next_cursor}")).await;
+        assert_eq!(
+            page.users
+                .iter()
+                .map(|user| user.username.as_str())
+                .collect::<Vec<_>>(),
+            vec!["namematch_srch", "srchzx_q"]
+        );
+        assert!(page.next_cursor.is_none());
+
+        // The privacy settings are applied to the found users
+        let page = search_page(&service, "q=hiddenname_srch").await;
+        let user = page
+            .users
+            .iter()
+            .find(|user| user.uuid == hidden_name.uuid)
+            .expect("The user should be found by his username");
+        assert_eq!(user.first_name, "hiddenname_srch");
+    }
+
+    #[tokio::test]
+    async fn search_users_invalid() {
+        let service = get_service().await.expect("Failed to get service");
+
+        for query in [
+            "q=".to_owned(),
+            format!("q={}", "a".repeat(51)),
+            "q=player&limit=0".to_owned(),
+            "q=player&limit=51".to_owned(),
+            "q=player&cursor=invalid".to_owned(),
+        ] {
+            let res = search(&service, &query).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::BAD_REQUEST),
+                "The response should have a `BAD_REQUEST` status code {res:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn search_users_rate_limited() {
+        let service = get_service().await.expect("Failed to get service");
+
+        for _ in 0..15 {
+            let res = search(&service, "q=player").await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::OK),
+                "The response should have a `OK` status code {res:?}"
+            );
+        }
+        let res = search(&service, "q=player").await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::TOO_MANY_REQUESTS),
+            "The response should have a `TOO_MANY_REQUESTS` status code {res:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod profile_image {
+    use super::*;
+    use std::path::Path;
+
+    const BOUNDARY: &str = "xors-profile-image-boundary";
+
+    /// Returns a `multipart/form-data` body with the file in the given field.
+    fn multipart_body(field: &str, file: &[u8]) -> Vec<u8> {
+        let mut body = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{field}\"; filename=\"image\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n"
+        )
+        .into_bytes();
+        body.extend_from_slice(file);
+        body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+        body
+    }
+
+    fn upload_headers(jwt: &str) -> Vec<(HeaderName, HeaderValue)> {
+        vec![
+            (
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {jwt}")).unwrap(),
+            ),
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(&format!("multipart/form-data; boundary={BOUNDARY}"))
+                    .unwrap(),
+            ),
+        ]
+    }
+
+    async fn upload_image(service: &Service, jwt: &str, body: Vec<u8>) -> Response {
+        send_raw(
+            service,
+            "user/profile_image",
+            Method::PUT,
+            body,
+            upload_headers(jwt),
+        )
+        .await
+    }
+
+    /// Uploads the body in chunks, so the request has no `Content-Length` header.
+    async fn upload_chunked_image(service: &Service, jwt: &str, body: Vec<u8>) -> Response {
+        let chunks = body
+            .chunks(16 * 1024)
+            .map(|chunk| {
+                Ok::<_, salvo::BoxedError>(salvo::http::body::Frame::data(
+                    salvo::hyper::body::Bytes::copy_from_slice(chunk),
+                ))
+            })
+            .collect::<Vec<_>>();
+        let mut req =
+            RequestBuilder::new(format!("{API_URL}/user/profile_image"), Method::PUT).build();
+        *req.body_mut() = ReqBody::Boxed(Box::pin(http_body_util::StreamBody::new(
+            futures_util::stream::iter(chunks),
+        )));
+        call(service, req, upload_headers(jwt)).await
+    }
+
+    fn jpeg_image(width: u32, height: u32) -> Vec<u8> {
+        let mut image_bytes = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            width,
+            height,
+            image::Rgb([30, 200, 60]),
+        ))
+        .write_to(
+            &mut std::io::Cursor::new(&mut image_bytes),
+            image::ImageOutputFormat::Jpeg(90),
+        )
+        .expect("Failed to encode the image");
+        image_bytes
+    }
+
+    async fn take_user(res: &mut Response) -> UserSchema {
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+            .expect("Failed to parse body")
+    }
+
+    #[tokio::test]
+    async fn update_and_delete_profile_image() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(
+            &conn,
+            &create_player(&conn, "update_and_delete_profile_image").await,
+        )
+        .await;
+        let image_path = format!("./profile_images/{}", user.user.uuid);
+
+        let mut res = upload_image(
+            &service,
+            &user.jwt,
+            multipart_body("image", &jpeg_image(320, 240)),
+        )
+        .await;
+        let res_json = take_user(&mut res).await;
+        assert_eq!(
+            res_json.profile_image_path,
+            format!("/profiles/{}", user.user.uuid)
+        );
+        let mut res = get_image(
+            &service,
+            &format!("profiles/{}.png?size=64", user.user.uuid),
+            vec![],
+        )
+        .await;
+        let thumbnail = image::load_from_memory(&take_image(&mut res).await)
+            .expect("The image should be valid");
+        assert_eq!((thumbnail.width(), thumbnail.height()), (64, 64));
+
+        // Updating the name only keeps the profile image
+        let mut res = send(
+            &service,
+            "user",
+            Method::PUT,
+            Some(&UpdateUserSchema {
+                first_name: Some("NewFirst".to_owned()),
+                last_name: None,
+                profile_image: None,
+            }),
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        let res_json = take_user(&mut res).await;
+        assert_eq!(
+            res_json.profile_image_path,
+            format!("/profiles/{}", user.user.uuid)
+        );
+        assert!(Path::new(&image_path).exists());
+
+        let mut res = send(
+            &service,
+            "user/profile_image",
+            Method::DELETE,
+            None::<&()>,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        let res_json = take_user(&mut res).await;
+        assert_eq!(res_json.profile_image_path, "/profiles/default");
+        assert!(!Path::new(&image_path).exists());
+        assert!(!Path::new(&format!(
+            "./profile_images/thumbnails/{}-64",
+            user.user.uuid
+        ))
+        .exists());
+    }
+
+    #[tokio::test]
+    async fn update_profile_image_invalid() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(
+            &conn,
+            &create_player(&conn, "update_profile_image_invalid").await,
+        )
+        .await;
+
+        for (body, message) in [
+            (
+                multipart_body("file", &jpeg_image(64, 64)),
+                "Invalid request body: The `image` file is required",
+            ),
+            (
+                b"not a multipart body".to_vec(),
+                "Invalid request body: incomplete multipart stream",
+            ),
+        ] {
+            let mut res = upload_image(&service, &user.jwt, body).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::BAD_REQUEST),
+                "The response should have a `BAD_REQUEST` status code {res:?}"
+            );
+            let res_json: serde_json::Value =
+                serde_json::from_str(&res.take_string().await.expect("Failed to get body"))
+                    .expect("Failed to parse body");
+            assert_eq!(res_json["message"], message);
+        }
+
+        // The size is checked while reading the body, with or without the `Content-Length` header
+        let res = upload_image(
+            &service,
+            &user.jwt,
+            multipart_body("image", &vec![0; 6_000_000]),
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::PAYLOAD_TOO_LARGE),
+            "The response should have a `PAYLOAD_TOO_LARGE` status code {res:?}"
+        );
+        let res = upload_chunked_image(
+            &service,
+            &user.jwt,
+            multipart_body("image", &vec![0; 6_000_000]),
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::PAYLOAD_TOO_LARGE),
+            "The response should have a `PAYLOAD_TOO_LARGE` status code {res:?}"
+        );
+        assert!(!Path::new(&format!("./profile_images/{}", user.user.uuid)).exists());
+    }
+
+    #[tokio::test]
+    async fn update_profile_image_chunked() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+        let user = signin_player(
+            &conn,
+            &create_player(&conn, "update_profile_image_chunked").await,
+        )
+        .await;
+
+        let mut res = upload_chunked_image(
+            &service,
+            &user.jwt,
+            multipart_body("image", &jpeg_image(64, 64)),
+        )
+        .await;
+        let res_json = take_user(&mut res).await;
+        std::fs::remove_file(format!("./profile_images/{}", user.user.uuid))
+            .expect("Failed to remove the profile image");
+        assert_eq!(
+            res_json.profile_image_path,
+            format!("/profiles/{}", user.user.uuid)
+        );
+    }
+
+    async fn get_image(
+        service: &Service,
+        path: &str,
+        headers: Vec<(HeaderName, HeaderValue)>,
+    ) -> Response {
+        send(service, path, Method::GET, None::<&()>, headers).await
+    }
+
+    async fn take_image(res: &mut Response) -> Vec<u8> {
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::OK),
+            "The response should have a `OK` status code {res:?}"
+        );
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE),
+            Some(&HeaderValue::from_static("image/png"))
+        );
+        res.take_bytes(None)
+            .await
+            .expect("Failed to get body")
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn profile_image_png_caching() {
+        let service = get_service().await.expect("Failed to get service");
+
+        let mut res = get_image(&service, "profiles/default.png", vec![]).await;
+        let image = take_image(&mut res).await;
+        assert_eq!(
+            image,
+            std::fs::read("./profile_images/default").expect("Failed to read the default image")
+        );
+        assert!(
+            res.headers().contains_key(header::LAST_MODIFIED),
+            "The response should have a `Last-Modified` header {res:?}"
+        );
+        let etag = res
+            .headers()
+            .get(header::ETAG)
+            .expect("The response should have an `ETag` header")
+            .clone();
+
+        let res = get_image(
+            &service,
+            "profiles/default.png",
+            vec![(header::IF_NONE_MATCH, etag)],
+        )
+        .await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::NOT_MODIFIED),
+            "The response should have a `NOT_MODIFIED` status code {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn profile_image_png_sizes() {
+        let service = get_service().await.expect("Failed to get service");
+
+        for size in [32, 64, 128] {
+            let mut res = get_image(
+                &service,
+                &format!("profiles/default.png?size={size}"),
+                vec![],
+            )
+            .await;
+            let image = image::load_from_memory(&take_image(&mut res).await)
+                .expect("The image should be valid");
+            assert_eq!((image.width(), image.height()), (size, size));
+        }
+
+        let res = get_image(&service, "profiles/default.png?size=50", vec![]).await;
+        assert_eq!(
+            res.status_code,
+            Some(StatusCode::BAD_REQUEST),
+            "The response should have a `BAD_REQUEST` status code {res:?}"
+        );
+        for path in [
+            "profiles/not-a-user.png".to_owned(),
+            format!("profiles/{}.png", Uuid::new_v4()),
+        ] {
+            let res = get_image(&service, &path, vec![]).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::NOT_FOUND),
+                "The response should have a `NOT_FOUND` status code {res:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn profile_image_png_private() {
+        let service = get_service().await.expect("Failed to get service");
+        let conn = get_connection().await.expect("Failed to get connection");
+
+        let user = signin_player(
+            &conn,
+            &create_player(&conn, "profile_image_png_private").await,
+        )
+        .await;
+        let image_path = format!("./profile_images/{}", user.user.uuid);
+        let mut user_image = Vec::new();
+        image::DynamicImage::new_rgb8(128, 128)
+            .write_to(
+                &mut std::io::Cursor::new(&mut user_image),
+                image::ImageOutputFormat::Png,
+            )
+            .expect("Failed to encode the image");
+        std::fs::write(&image_path, &user_image).expect("Failed to write the image");
+        PrivacySettingActiveModel {
+            user_uuid: Set(user.user.uuid),
+            friends_only: Set(true),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .expect("Failed to insert privacy settings");
+
+        let path = format!("profiles/{}.png", user.user.uuid);
+        let mut res = get_image(&service, &path, vec![]).await;
+        let public_image = take_image(&mut res).await;
+        let mut res = get_image(
+            &service,
+            &path,
+            vec![(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", user.jwt)).unwrap(),
+            )],
+        )
+        .await;
+        let owner_image = take_image(&mut res).await;
+        std::fs::remove_file(&image_path).expect("Failed to remove the image");
+
+        assert_eq!(
+            public_image,
+            std::fs::read("./profile_images/default").expect("Failed to read the default image")
+        );
+        assert_eq!(owner_image, user_image);
+    }
+}
@@ -0,0 +1,174 @@
+// A RESTful tic tac toy API for XORS project
+// Copyright (C) 2024  Awiteb <Awiteb@pm.me>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use proptest::prelude::*;
+use xors_api::schemas::*;
+
+/// Returns a board played in the given cells order, X starts first.
+/// Will stop playing when the board is end.
+fn play_board(cells: &[u8]) -> Board {
+    let mut board = Board::default();
+    for &cell in cells {
+        if board.is_end() {
+            break;
+        }
+        board.set_cell(cell, board.turn());
+    }
+    board
+}
+
+/// Strategy of a board played in a random cells order, it may not be ended.
+fn board_strategy() -> impl Strategy<Value = Board> {
+    (Just((0..9).collect::<Vec<u8>>()).prop_shuffle(), 0..=9usize)
+        .prop_map(|(cells, count)| play_board(&cells[..count]))
+}
+
+/// Strategy of an ended board.
+fn ended_board_strategy() -> impl Strategy<Value = Board> {
+    Just((0..9).collect::<Vec<u8>>())
+        .prop_shuffle()
+        .prop_map(|cells| play_board(&cells))
+}
+
+/// Strategy of a rounds result with 0 to 3 ended rounds.
+fn rounds_result_strategy() -> impl Strategy<Value = RoundsResult> {
+    prop::collection::vec(ended_board_strategy(), 0..=3).prop_map(|boards| {
+        let mut rounds_result = RoundsResult::default();
+        for board in boards {
+            if board.is_win(&XoSymbol::X) {
+                rounds_result.add_win(&XoSymbol::X);
+            } else if board.is_win(&XoSymbol::O) {
+                rounds_result.add_win(&XoSymbol::O);
+            } else {
+                rounds_result.draws += 1;
+            }
+            rounds_result.add_board(board);
+        }
+        rounds_result
+    })
+}
+
+#[cfg(test)]
+mod board {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn to_string_from_str_round_trip(board in board_strategy()) {
+            let parsed: Board = board.to_string().parse().expect("The board should be valid");
+            prop_assert_eq!(parsed, board);
+        }
+
+        #[test]
+        fn from_str_never_panics(board in "\\PC*") {
+            let _ = board.parse::<Board>();
+        }
+
+        #[test]
+        fn from_str_never_panics_with_board_chars(board in "[XO-]{0,10}:[0-9]{0,10}") {
+            let _ = board.parse::<Board>();
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn validate_played_boards(board in board_strategy()) {
+            prop_assert!(board.validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn invalid_boards() {
+        for board in [
+            "",
+            "---------",
+            "--------:",
+            "----------:",
+            "A--------:0",
+            "X--------:9",
+            "X--------:a",
+            "---------:0",
+            "XOXOXOXOX:0123456780",
+            "X--------:00",
+            "O--------:0",
+            "XX-------:01",
+            "XO-------:0",
+            "XXXOO----:3041",
+            "XXXOO-O--:03142",
+            "XXXOO-O--:031426",
+            "XOXOXOXOX:01234567",
+        ] {
+            assert!(
+                board.parse::<Board>().is_err(),
+                "The board `{board}` should be invalid"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod rounds_result {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn to_string_from_str_round_trip(rounds_result in rounds_result_strategy()) {
+            let parsed: RoundsResult = rounds_result
+                .to_string()
+                .parse()
+                .expect("The rounds result should be valid");
+            prop_assert_eq!(parsed, rounds_result);
+        }
+
+        #[test]
+        fn from_str_never_panics(rounds_result in "\\PC*") {
+            let _ = rounds_result.parse::<RoundsResult>();
+        }
+    }
+
+    #[test]
+    fn valid_rounds_results() {
+        for rounds_result in [
+            " ",
+            "X XXXOO----:03142",
+            "XO- XXXOO----:03142,XX-OOO--X:031485,XOXXOOOXX:012435768",
+        ] {
+            assert!(
+                rounds_result.parse::<RoundsResult>().is_ok(),
+                "The rounds result `{rounds_result}` should be valid"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_rounds_results() {
+        for rounds_result in [
+            "",
+            "XO-",
+            "XA ",
+            "X ---------",
+            "X XXX------:012,",
+            "O XXXOO----:03142",
+            "X XXOO-----:0314",
+            "XXXX XXXOO----:03142,XXXOO----:03142,XXXOO----:03142,XXXOO----:03142",
+        ] {
+            assert!(
+                rounds_result.parse::<RoundsResult>().is_err(),
+                "The rounds result `{rounds_result}` should be invalid"
+            );
+        }
+    }
+}